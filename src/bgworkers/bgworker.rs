@@ -1,8 +1,8 @@
 use crate::errors;
 use bytes::Bytes;
-use crossbeam_channel::{select, Sender};
-use log::{error, info};
-use std::{thread, fmt::Display};
+use crossbeam_channel::{select, Receiver, Sender};
+use log::{debug, error, info};
+use std::{thread, fmt::Display, time::{Duration, Instant}};
 
 pub struct BgWorker<B>
 where
@@ -20,6 +20,28 @@ where
     pub fn new(
         name: &str,
         work: impl Fn(B) -> Result<Bytes, errors::DbError> + Send + 'static,
+    ) -> Self {
+        Self::spawn(name, crossbeam_channel::never(), || None, work)
+    }
+
+    // every runs work on task every interval, besides the tasks sent to it.
+    pub fn every(
+        name: &str,
+        interval: Duration,
+        task: B,
+        work: impl Fn(B) -> Result<Bytes, errors::DbError> + Send + 'static,
+    ) -> Self
+    where
+        B: Clone,
+    {
+        Self::spawn(name, crossbeam_channel::tick(interval), move || Some(task.clone()), work)
+    }
+
+    fn spawn(
+        name: &str,
+        ticker: Receiver<Instant>,
+        tick: impl Fn() -> Option<B> + Send + 'static,
+        work: impl Fn(B) -> Result<Bytes, errors::DbError> + Send + 'static,
     ) -> Self {
         let (s, r) = crossbeam_channel::unbounded::<B>();
         let (stop_s, stop_r) = crossbeam_channel::bounded::<bool>(1);
@@ -29,21 +51,26 @@ where
             stop_sender: stop_s,
         };
         let worker_name = name.to_owned();
+        let run = move |task: B| match work(task) {
+            Err(err) => error!("background worker {} process data with error {:?}", &worker_name, err),
+            Ok(msg) => debug!("background worker {} processed {:?}", &worker_name, msg),
+        };
+        let worker_name = name.to_owned();
         thread::spawn(move || loop {
             select! {
                 recv(r) -> task => {
                     match task {
-                        Ok(task) => {
-                            match work(task) {
-                                Err(err) => error!("background worker {} process data with error {:?}", &worker_name, err),
-                                Ok(msg) => println!("{:#?}", msg)
-                            }
-                        }
+                        Ok(task) => run(task),
                         Err(err) => {
                             error!("background worker {} recv data with error {:?}", &worker_name, err);
                         }
                     }
                 },
+                recv(ticker) -> _ => {
+                    if let Some(task) = tick() {
+                        run(task);
+                    }
+                },
                 recv(stop_r) -> _ => {
                     info!("background worker {} Received stop signal, worker exit", &worker_name);
                     break;
@@ -93,4 +120,17 @@ mod tests {
         std::thread::sleep(std::time::Duration::from_secs(1));
         worker.stop();
     }
+
+    #[test]
+    fn test_bg_worker_every() {
+        let (s, r) = crossbeam_channel::unbounded();
+        let worker = BgWorker::every("test", std::time::Duration::from_millis(10), s, |s: crossbeam_channel::Sender<()>| {
+            s.send(()).unwrap();
+            Ok(Bytes::new())
+        });
+        for _ in 0..3 {
+            r.recv_timeout(std::time::Duration::from_secs(1)).unwrap();
+        }
+        worker.stop();
+    }
 }
\ No newline at end of file
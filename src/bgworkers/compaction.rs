@@ -1,16 +1,95 @@
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
 
-use crate::bgworkers::bgworker::BgWorker;
-use crate::index::Record;
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+
+use crate::bgworkers::{bgworker::BgWorker, flush::HELD_RETRY_INTERVAL};
+use crate::db::{datafile::DataFiles, status::Status};
+use crate::fileio::rate_limiter::RateLimiter;
+use crate::index::{Index, Record};
+use crate::errors::DbError;
+
+// CompactionTask is sent to the compaction worker for each live record of the files being
+// compacted, then once they are all sent.
+#[derive(Clone)]
+pub enum CompactionTask {
+    // rewrites the record at the end of the output file
+    Rewrite(Box<Record>),
+    // the records of the compacted files are all rewritten, the output file is sealed
+    Finish(Vec<u32>),
+}
 
 pub struct CompactionWorker {
-    bg_worker: BgWorker<Record>,
+    // a none task retries the held tasks
+    bg_worker: BgWorker<Option<CompactionTask>>,
     compaction_worker_idx: usize,
 }
 
 impl CompactionWorker {
-    
-    pub fn send(&self, record: Record) {
-        self.bg_worker.send(record)
+    // new rewrites the records sent to it to the data file file_id, the index of their bucket then points at their new place.
+    pub fn new(compaction_worker_idx: usize, file_id: u32, data_files: Arc<DataFiles>, rate_limiter: Arc<RateLimiter>, index: Arc<RwLock<HashMap<String, Index>>>, status: Arc<Status>) -> Result<Self, DbError> {
+        let file = data_files.open(file_id)?;
+        let write_at = Mutex::new(0u64);
+        // the keys rewritten by bucket, for the bloom filters of the output file
+        let keys: Mutex<HashMap<String, Vec<Bytes>>> = Mutex::default();
+        let run = {
+            let status = Arc::clone(&status);
+            move |task: &CompactionTask| -> Result<Bytes, DbError> {
+                let mut record = match task {
+                    CompactionTask::Rewrite(record) => (**record).clone(),
+                    CompactionTask::Finish(compacted) => {
+                        data_files.seal(file_id, *write_at.lock(), &keys.lock(), &mut index.write()).map_err(|err| status.report(err))?;
+                        // the blocks of the compacted files are not read again
+                        compacted.iter().for_each(|compacted| data_files.evict(*compacted));
+                        return Ok(Bytes::new());
+                    }
+                };
+                // the compaction reads leave the block cache to the point reads
+                if !record.held() {
+                    record = data_files.read(&record, false)?;
+                }
+                let (from_file_id, from_offset) = (record.hint.file_id, record.hint.offset);
+                let mut write_at = write_at.lock();
+                record.hint.file_id = file_id;
+                record.hint.offset = *write_at;
+                // the rewrites of the live records go through the limiter shared with the flush workers
+                *write_at += rate_limiter.write(file.write().as_mut(), &record.encode(), record.hint.offset).map_err(|err| status.report(err))? as u64;
+
+                // the key may have been written again meanwhile, its index then points at the newer record
+                let bucket = String::from_utf8_lossy(&record.entry.meta.bucket).into_owned();
+                let key = String::from_utf8_lossy(&record.hint.key).into_owned();
+                keys.lock().entry(bucket.clone()).or_default().push(record.hint.key.clone());
+                let mut indexes = index.write();
+                let Some(index) = indexes.get_mut(&bucket) else {
+                    return Ok(record.hint.key);
+                };
+                if index.get(&key).is_some_and(|live| live.hint.file_id == from_file_id && live.hint.offset == from_offset) {
+                    let rewritten = record.hint.key.clone();
+                    index.put(key, record)?;
+                    return Ok(rewritten);
+                }
+                Ok(Bytes::new())
+            }
+        };
+        let held: Mutex<VecDeque<CompactionTask>> = Mutex::default();
+        let bg_worker = BgWorker::every(format!("compaction-worker-{}", compaction_worker_idx).as_str(), HELD_RETRY_INTERVAL, None, move|task: Option<CompactionTask>| {
+            let mut held = held.lock();
+            held.extend(task);
+            // the tasks are held while the db is read only after a disk full error, the records stay in the compacted files
+            // until it resumes and the tasks are run in order. a failed task is kept and retried.
+            status.check_writable()?;
+            let mut done = Bytes::new();
+            while let Some(task) = held.front() {
+                done = run(task)?;
+                held.pop_front();
+            }
+            Ok(done)
+        });
+        Ok(CompactionWorker { bg_worker, compaction_worker_idx })
+    }
+
+    pub fn send(&self, task: CompactionTask) {
+        self.bg_worker.send(Some(task))
     }
 
     pub fn stop(&self) {
@@ -18,3 +97,140 @@ impl CompactionWorker {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::{data::entry::Entry, enums::{self, DataTypes, EntryOperate}, fileio::FDManager, option};
+
+    fn data_files(dir: &std::path::Path, rw_mode: enums::RWMode) -> Arc<DataFiles> {
+        let opt = option::Option::default().with_dir(dir.to_str().unwrap()).with_dat_file_size(1).with_rw_mode(rw_mode);
+        Arc::new(DataFiles::new(&opt, FDManager::new(4), opt.new_block_cache()))
+    }
+
+    fn record(key: &str, file_id: u32, offset: u64) -> Record {
+        let mut record = Record {
+            entry: Entry::new(Bytes::from("bucket"), Bytes::from(key.to_owned()), Bytes::from("value"), EntryOperate::Put, DataTypes::String),
+            ..Default::default()
+        };
+        record.hint.key = record.entry.key.clone();
+        record.hint.meta = record.entry.meta.clone();
+        record.hint.file_id = file_id;
+        record.hint.offset = offset;
+        record
+    }
+
+    #[test]
+    fn test_compaction_rewrite() {
+        let dir = std::env::temp_dir().join("arrowdb_compaction_rewrite");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut bucket = Index::default();
+        bucket.put("key1".to_owned(), record("key1", 0, 0)).unwrap();
+        // written again since the compaction started
+        bucket.put("key2".to_owned(), record("key2", 2, 0)).unwrap();
+        let index = Arc::new(RwLock::new(HashMap::from([("bucket".to_owned(), bucket)])));
+        let rate_limiter = Arc::new(RateLimiter::new(0));
+        let data_files = data_files(&dir, enums::RWMode::StdIO);
+        let block_cache = data_files.block_cache().unwrap();
+        block_cache.insert(0, 0, Bytes::from("block"));
+        let worker = CompactionWorker::new(0, 1, Arc::clone(&data_files), Arc::clone(&rate_limiter), Arc::clone(&index), Arc::default()).unwrap();
+
+        worker.send(CompactionTask::Rewrite(Box::new(record("key1", 0, 0))));
+        worker.send(CompactionTask::Rewrite(Box::new(record("key2", 0, 100))));
+        worker.send(CompactionTask::Finish(vec![0]));
+        thread::sleep(Duration::from_millis(100));
+        worker.stop();
+
+        let indexes = index.read();
+        let (key1, key2) = (indexes["bucket"].get("key1").unwrap(), indexes["bucket"].get("key2").unwrap());
+        assert_eq!((key1.hint.file_id, key1.hint.offset), (1, 0));
+        assert_eq!((key2.hint.file_id, key2.hint.offset), (2, 0));
+        let written = std::fs::read(dir.join("1.dat")).unwrap();
+        assert_eq!(Record::decode(&written[..key1.encode().len()]).unwrap().entry.key, Bytes::from("key1"));
+        // the output file is sealed with its filter
+        assert!(indexes["bucket"].may_contain(1, b"key1"));
+        assert!(!indexes["bucket"].may_contain(1, b"key3"));
+        assert!(dir.join("filters").join("bucket").join("1.bloom").exists());
+        // the compacted file is evicted
+        assert!(block_cache.get(0, 0).is_none());
+    }
+
+    #[test]
+    fn test_compaction_read_only() {
+        let dir = std::env::temp_dir().join("arrowdb_compaction_read_only");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = Arc::new(RwLock::new(HashMap::from([("bucket".to_owned(), Index::default())])));
+        index.write().get_mut("bucket").unwrap().put("key1".to_owned(), record("key1", 0, 0)).unwrap();
+        let status: Arc<Status> = Arc::default();
+        status.report(DbError::IOError(std::io::Error::from_raw_os_error(libc::ENOSPC)));
+        let worker = CompactionWorker::new(0, 1, data_files(&dir, enums::RWMode::StdIO), Arc::new(RateLimiter::new(0)), Arc::clone(&index), Arc::clone(&status)).unwrap();
+
+        worker.send(CompactionTask::Rewrite(Box::new(record("key1", 0, 0))));
+        worker.send(CompactionTask::Finish(vec![0]));
+        thread::sleep(Duration::from_millis(100));
+        // the record is left in the compacted file
+        assert_eq!(index.read()["bucket"].get("key1").unwrap().hint.file_id, 0);
+        assert_eq!(std::fs::metadata(dir.join("1.dat")).unwrap().len(), enums::MB);
+
+        // the held tasks are run in order once the db resumes
+        status.resume(|| Ok(())).unwrap();
+        for _ in 0..50 {
+            if index.read()["bucket"].get("key1").unwrap().hint.file_id == 1 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        thread::sleep(Duration::from_millis(50));
+        worker.stop();
+        assert_eq!(index.read()["bucket"].get("key1").unwrap().hint.file_id, 1);
+        // sealed after the rewrite, its filter rules the other keys out
+        assert!(index.read()["bucket"].may_contain(1, b"key1"));
+        assert!(!index.read()["bucket"].may_contain(1, b"key2"));
+    }
+
+    #[test]
+    fn test_compaction_read_direct() {
+        let dir = std::env::temp_dir().join("arrowdb_compaction_read_direct");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = Arc::new(RwLock::new(HashMap::from([("bucket".to_owned(), Index::default())])));
+        index.write().get_mut("bucket").unwrap().put("key1".to_owned(), record("key1", 0, 0)).unwrap();
+        let data_files = data_files(&dir, enums::RWMode::DirectIO);
+        let worker = CompactionWorker::new(0, 1, Arc::clone(&data_files), Arc::new(RateLimiter::new(0)), Arc::clone(&index), Arc::default()).unwrap();
+
+        worker.send(CompactionTask::Rewrite(Box::new(record("key1", 0, 0))));
+        thread::sleep(Duration::from_millis(100));
+        worker.stop();
+        // the rewritten record is still in the buffer of the writer, the reads share it
+        let rewritten = index.read()["bucket"].get("key1").unwrap().clone();
+        assert_eq!(rewritten.hint.file_id, 1);
+        let read = data_files.read(&Record { hint: rewritten.hint, ..Default::default() }, true).unwrap();
+        assert_eq!(read.entry.value, Bytes::from("value"));
+    }
+
+    #[test]
+    fn test_compaction_seal_mmap() {
+        let dir = std::env::temp_dir().join("arrowdb_compaction_seal_mmap");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = Arc::new(RwLock::new(HashMap::from([("bucket".to_owned(), Index::default())])));
+        index.write().get_mut("bucket").unwrap().put("key1".to_owned(), record("key1", 0, 0)).unwrap();
+        let data_files = data_files(&dir, enums::RWMode::MMap);
+        let worker = CompactionWorker::new(0, 1, Arc::clone(&data_files), Arc::new(RateLimiter::new(0)), Arc::clone(&index), Arc::default()).unwrap();
+
+        worker.send(CompactionTask::Rewrite(Box::new(record("key1", 0, 0))));
+        worker.send(CompactionTask::Finish(vec![0]));
+        thread::sleep(Duration::from_millis(100));
+        worker.stop();
+        // the mapping grew past the record, the sealed file shrinks to it
+        let len = record("key1", 1, 0).encode().len() as u64;
+        assert_eq!(std::fs::metadata(dir.join("1.dat")).unwrap().len(), len);
+        let rewritten = index.read()["bucket"].get("key1").unwrap().clone();
+        let read = data_files.read(&Record { hint: rewritten.hint, ..Default::default() }, true).unwrap();
+        assert_eq!(read.entry.value, Bytes::from("value"));
+    }
+}
@@ -1,30 +1,51 @@
-use std::path::PathBuf;
+use std::{collections::VecDeque, path::PathBuf, sync::Arc, time::Duration};
 
+use bytes::Bytes;
+use parking_lot::Mutex;
 
-use crate::{index::Record, fileio::FileManager, enums, errors::DbError};
+use crate::{db::status::Status, index::Record, fileio::{rate_limiter::RateLimiter, FDManager, FileManager}, enums, errors::DbError};
 use super::bgworker::BgWorker;
 
+// the records held while the db is read only are retried at this interval
+pub(super) const HELD_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct FlushWorker {
-    bg_worker: BgWorker<Record>,
+    // a none task retries the held records
+    bg_worker: BgWorker<Option<Record>>,
     flush_worker_idx: usize,
 }
 
 impl FlushWorker {
-    pub fn new(flush_worker_idx: usize, flush_mode: enums::RWMode, dir: PathBuf, file_size_mb: u64) -> Result<Self, DbError> {
+    pub fn new(flush_worker_idx: usize, flush_mode: enums::RWMode, dir: PathBuf, file_size_mb: u64, fd_manager: Arc<Mutex<FDManager>>, rate_limiter: Arc<RateLimiter>, status: Arc<Status>) -> Result<Self, DbError> {
         let data_file = dir.join(format!("{}.dat", flush_worker_idx));
-        let bg_worker = BgWorker::new(format!("flush-worker-{}", flush_worker_idx).as_str(), move|record: Record| {
-            let mut file_manager = FileManager::new(flush_mode.clone());
-            let data_file_manager = file_manager.get_fileio_manager(data_file.to_str().unwrap(), file_size_mb)?;
-            let mut file = data_file_manager.write();
-            file.write(&record.encode(), record.hint.offset)?;
-            let record_key = record.hint.key.to_owned();
-            Ok(record_key)
+        let held: Mutex<VecDeque<Record>> = Mutex::default();
+        let bg_worker = BgWorker::every(format!("flush-worker-{}", flush_worker_idx).as_str(), HELD_RETRY_INTERVAL, None, move|record: Option<Record>| {
+            let mut held = held.lock();
+            held.extend(record);
+            // the records are held while the db is read only after a disk full error, they are flushed in order once it resumes
+            status.check_writable()?;
+            let flush = |record: &Record| -> Result<_, DbError> {
+                let mut file_manager = FileManager::new(flush_mode.clone(), fd_manager.clone());
+                let data_file_manager = file_manager.get_fileio_manager(data_file.to_str().unwrap(), file_size_mb)?;
+                let mut file = data_file_manager.write();
+                // flushes are throttled so that they do not delay the foreground reads
+                rate_limiter.write(file.as_mut(), &record.encode(), record.hint.offset)?;
+                Ok(())
+            };
+            let mut flushed = Bytes::new();
+            while let Some(record) = held.front() {
+                // a failed record is kept, it is retried after the resume of a disk full error or at the next interval
+                flush(record).map_err(|err| status.report(err))?;
+                flushed = record.hint.key.to_owned();
+                held.pop_front();
+            }
+            Ok(flushed)
         });
         Ok(FlushWorker { bg_worker, flush_worker_idx })
     }
     
     pub fn send(&self, record: Record) {
-        self.bg_worker.send(record)
+        self.bg_worker.send(Some(record))
     }
 
     pub fn stop(&self) {
@@ -33,3 +54,71 @@ impl FlushWorker {
 
 }
 
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{data::entry::Entry, enums::{DataTypes, EntryOperate}};
+
+    #[test]
+    fn test_flush_held_while_read_only() {
+        let dir = std::env::temp_dir().join("arrowdb_flush_held_while_read_only");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let status = Arc::new(Status::default());
+        status.report(DbError::IOError(std::io::Error::from_raw_os_error(libc::ENOSPC)));
+        let worker = FlushWorker::new(0, enums::RWMode::StdIO, dir.clone(), 1, FDManager::new(4), Arc::new(RateLimiter::new(0)), Arc::clone(&status)).unwrap();
+
+        let mut record = Record {
+            entry: Entry::new(Bytes::from("bucket"), Bytes::from("key1"), Bytes::from("value"), EntryOperate::Put, DataTypes::String),
+            ..Default::default()
+        };
+        record.hint.key = Bytes::from("key1");
+        record.hint.meta = record.entry.meta.clone();
+        worker.send(record.clone());
+        let flushed = || std::fs::read(dir.join("0.dat")).is_ok_and(|b| b.starts_with(&record.encode()));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!flushed());
+
+        status.resume(|| Ok(())).unwrap();
+        for _ in 0..50 {
+            if flushed() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(flushed());
+        worker.stop();
+    }
+
+    #[test]
+    fn test_flush_kept_on_error() {
+        let dir = std::env::temp_dir().join("arrowdb_flush_kept_on_error");
+        let _ = std::fs::remove_dir_all(&dir);
+        // the data file cannot be opened while a dir takes its name
+        std::fs::create_dir_all(dir.join("0.dat")).unwrap();
+        let worker = FlushWorker::new(0, enums::RWMode::StdIO, dir.clone(), 1, FDManager::new(4), Arc::new(RateLimiter::new(0)), Arc::default()).unwrap();
+
+        let mut record = Record {
+            entry: Entry::new(Bytes::from("bucket"), Bytes::from("key1"), Bytes::from("value"), EntryOperate::Put, DataTypes::String),
+            ..Default::default()
+        };
+        record.hint.key = Bytes::from("key1");
+        record.hint.meta = record.entry.meta.clone();
+        worker.send(record.clone());
+        let flushed = || std::fs::read(dir.join("0.dat")).is_ok_and(|b| b.starts_with(&record.encode()));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!flushed());
+
+        std::fs::remove_dir(dir.join("0.dat")).unwrap();
+        for _ in 0..50 {
+            if flushed() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(flushed());
+        worker.stop();
+    }
+}
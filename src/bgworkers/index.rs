@@ -4,7 +4,8 @@ use anyhow::anyhow;
 use crossbeam_channel::{Sender, Receiver};
 use parking_lot::{RwLock, Mutex};
 use super::bgworker::BgWorker;
-use crate::{index::{Record, Index}, fileio::FileManager, enums::{self, DataTypes, EntryOperate}, errors::{DbError, self}};
+use bytes::Bytes;
+use crate::{data::payload, index::{Record, Index}, fileio::FileManager, enums::{self, DataTypes, EntryOperate}, errors::{DbError, self}};
 
 pub struct IndexWorker {
     bg_worker: BgWorker<(EntryOperate, Record, usize)>,
@@ -20,6 +21,15 @@ impl IndexWorker {
             match record.0 {
                 EntryOperate::Put => index.write().put(record_key_str, record.1)?,
                 EntryOperate::Del => index.write().del(&record_key_str)?,
+                // the wal entries of append and setrange carry the written bytes only, they are applied to the indexed value
+                EntryOperate::Append => index.write().append(&record_key_str, record.1),
+                EntryOperate::SetRange => {
+                    let (offset, value) = payload::decode_setrange(&record.1.entry.value).ok_or_else(|| payload_error(&record.1))?;
+                    let mut written = record.1.clone();
+                    written.entry.value = Bytes::copy_from_slice(value);
+                    written.entry.meta.value_size = value.len() as u32;
+                    index.write().setrange(&record_key_str, offset, written)
+                }
                 EntryOperate::Ttl => unimplemented!(),
                 EntryOperate::LLpush => index.write().lpush(&record_key_str, record.1)?,
                 EntryOperate::LLpop => index.write().lpop(&record_key_str).map(|_| 1)?,
@@ -27,10 +37,42 @@ impl IndexWorker {
                 EntryOperate::LRpop => index.write().rpop(&record_key_str).map(|_| 1)?,
                 EntryOperate::LLpushx => index.write().lpushx(&record_key_str, record.1)?,
                 EntryOperate::LRpushx => index.write().rpushx(&record_key_str, record.1)?,
-                EntryOperate::LRem => unimplemented!(),
+                EntryOperate::LRem => {
+                    let (count, element) = payload::decode_lrem(&record.1.entry.value).ok_or_else(|| payload_error(&record.1))?;
+                    index.write().lrem(&record_key_str, count, element)
+                }
+                EntryOperate::LInsert => {
+                    let (before, pivot, element) = payload::decode_linsert(&record.1.entry.value).ok_or_else(|| payload_error(&record.1))?;
+                    let mut inserted = record.1.clone();
+                    inserted.entry.value = Bytes::copy_from_slice(element);
+                    inserted.entry.meta.value_size = element.len() as u32;
+                    index.write().linsert(&record_key_str, before, pivot, inserted).unwrap_or(0)
+                }
+                EntryOperate::LTrim => {
+                    let (start, end) = payload::decode_ltrim(&record.1.entry.value).ok_or_else(|| payload_error(&record.1))?;
+                    index.write().ltrim(&record_key_str, start, end).unwrap_or(0)
+                }
+                EntryOperate::LMove => {
+                    let (destination, wherefrom, whereto) = payload::decode_lmove(&record.1.entry.value).ok_or_else(|| payload_error(&record.1))?;
+                    index.write().lmove(&record_key_str, destination, wherefrom, whereto)?.map_or(0, |_| 1)
+                }
                 EntryOperate::LSet => index.write().lset(&record_key_str, record.2, record.1).unwrap_or(0),
                 EntryOperate::SAdd => index.write().sadd(&record_key_str, vec![record.1]).unwrap_or(0),
                 EntryOperate::SRem => index.write().srem(&record_key_str, vec![record.1]).unwrap_or(0),
+                EntryOperate::SMove => {
+                    let (destination, member) = payload::decode_smove(&record.1.entry.value).ok_or_else(|| payload_error(&record.1))?;
+                    index.write().smove(&record_key_str, destination, member).unwrap_or(0)
+                }
+                EntryOperate::SDiffStore | EntryOperate::SInterStore | EntryOperate::SUnionStore => {
+                    let source_keys = payload::decode_sstore(&record.1.entry.value).ok_or_else(|| payload_error(&record.1))?;
+                    let (key, keys) = source_keys.split_first().ok_or_else(|| payload_error(&record.1))?;
+                    let mut index = index.write();
+                    match record.0 {
+                        EntryOperate::SDiffStore => index.sdiffstore(&record_key_str, key, keys.to_vec()),
+                        EntryOperate::SInterStore => index.sinterstore(&record_key_str, key, keys.to_vec()),
+                        _ => index.suionstore(&record_key_str, key, keys.to_vec()),
+                    }.unwrap_or(0)
+                }
                 EntryOperate::ZPut => unimplemented!(),
                 EntryOperate::ZRem => unimplemented!(),
                 _ => 0,
@@ -48,4 +90,12 @@ impl IndexWorker {
     fn stop(&self) {
         self.bg_worker.stop()
     }
-}
\ No newline at end of file
+}
+
+fn payload_error(record: &Record) -> DbError {
+    DbError::EntryDecodeError {
+        bucket: String::from_utf8(record.entry.meta.bucket.to_vec()).unwrap_or("".to_owned()),
+        key: String::from_utf8(record.hint.key.to_vec()).unwrap_or("".to_owned()),
+        msg: "invalid operate payload".to_owned(),
+    }
+}
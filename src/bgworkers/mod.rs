@@ -2,3 +2,5 @@ pub mod bgworker;
 pub mod compaction;
 pub mod flush;
 pub mod index;
+pub mod retention;
+pub mod sync;
@@ -0,0 +1,41 @@
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use log::debug;
+
+use crate::{
+    db::status::Status,
+    memtable::{self, MemTables},
+};
+
+use super::bgworker::BgWorker;
+
+// RetentionWorker drops the time series samples out of their retention every interval, on
+// the active memtable of a db. the trims are logged, so they are left while the db is read
+// only.
+pub struct RetentionWorker {
+    bg_worker: BgWorker<MemTables>,
+}
+
+impl RetentionWorker {
+    pub fn new(mem_tables: MemTables, interval: Duration, status: Arc<Status>) -> Self {
+        let bg_worker = BgWorker::every(
+            "retention-worker",
+            interval,
+            mem_tables,
+            move |mem_tables: MemTables| {
+                status.check_writable()?;
+                let dropped =
+                    memtable::with_active(&mem_tables, |memtable| memtable.ts_retention())
+                        .map_err(|err| status.report(err))?;
+                debug!("retention worker dropped {} samples", dropped);
+                Ok(Bytes::new())
+            },
+        );
+        RetentionWorker { bg_worker }
+    }
+
+    pub fn stop(&self) {
+        self.bg_worker.stop();
+    }
+}
@@ -0,0 +1,44 @@
+use std::{thread, time::Duration};
+
+use crossbeam_channel::{select, Sender};
+use log::{error, info};
+
+use crate::memtable::MemTables;
+
+// SyncWorker syncs the wal of the active memtable of a db every interval, it backs
+// SyncPolicy::Periodic. the wals rotated out are synced by the rotation.
+pub struct SyncWorker {
+    stop_sender: Sender<bool>,
+}
+
+impl SyncWorker {
+    pub fn new(mem_tables: MemTables, interval: Duration) -> Self {
+        let (stop_s, stop_r) = crossbeam_channel::bounded::<bool>(1);
+        let ticker = crossbeam_channel::tick(interval);
+        thread::spawn(move || loop {
+            select! {
+                recv(ticker) -> _ => {
+                    // the memtable is only locked to get its wal, writers go on while it syncs
+                    let active = mem_tables.read().last().cloned();
+                    let Some(group_commit) = active.map(|memtable| memtable.read().group_commit()) else {
+                        continue;
+                    };
+                    if let Err(err) = group_commit.sync() {
+                        error!("sync worker failed to sync the wal: {}", err);
+                    }
+                },
+                recv(stop_r) -> _ => {
+                    info!("sync worker Received stop signal, worker exit");
+                    break;
+                },
+            }
+        });
+        SyncWorker {
+            stop_sender: stop_s,
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop_sender.send(true).unwrap();
+    }
+}
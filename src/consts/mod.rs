@@ -1 +0,0 @@
-pub const ZESTKEYVALSPLITCHAR: char = '|';
@@ -1,8 +1,10 @@
 use super::{meta::Meta, ENTRYHEADERSIZE};
+use crate::enums::{DataTypes, EntryOperate, EntryStatus};
 use crate::errors::DbError;
 use bytes::{Bytes, BytesMut};
 use chrono::Local;
 use crc::{Crc, CRC_32_ISCSI};
+use std::cmp;
 
 #[derive(Debug, Clone, Default)]
 pub struct Entry {
@@ -23,6 +25,32 @@ pub struct Entry {
 //
 
 impl Entry {
+    pub fn new(
+        bucket: Bytes,
+        key: Bytes,
+        value: Bytes,
+        operate: EntryOperate,
+        data_type: DataTypes,
+    ) -> Self {
+        let meta = Meta::new(
+            bucket,
+            key.len() as u32,
+            value.len() as u32,
+            Local::now().timestamp(),
+            0,
+            operate as u16,
+            data_type as u16,
+            0,
+            EntryStatus::Commited as u16,
+        );
+        Entry {
+            key,
+            value,
+            meta,
+            crc: 0,
+        }
+    }
+
     pub fn size(&self) -> usize {
         ENTRYHEADERSIZE
             + self.meta.key_size as usize
@@ -31,15 +59,7 @@ impl Entry {
     }
 
     pub fn is_expired(&self) -> bool {
-        if self.meta.ttl == 0 {
-            return false;
-        }
-        let ex = if self.meta.ttl + self.meta.timestamp as u32 > Local::now().timestamp() as u32 {
-            false
-        } else {
-            true
-        };
-        ex
+        self.meta.is_expired()
     }
 
     pub fn encode(&self) -> Vec<u8> {
@@ -60,14 +80,44 @@ impl Entry {
             ..(ENTRYHEADERSIZE + bucket_size as usize + key_size as usize + value_size as usize)]
             .copy_from_slice(&self.value);
 
-        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
-        let c32 = crc.checksum(&buf[4..]);
-        buf[0..4].copy_from_slice(&c32.to_le_bytes());
+        Self::set_crc(&mut buf);
 
         buf
     }
 
     pub fn decode(buf: &[u8]) -> Result<Self, DbError> {
+        if Self::encoded_size(buf) != Some(buf.len()) {
+            return Err(DbError::EntryDecodeError {
+                bucket: "".to_owned(),
+                key: "".to_owned(),
+                msg: format!("entry of {} bytes does not match its header", buf.len()),
+            });
+        }
+        let mut entry = Self::decode_unchecked(buf);
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
+        let expected_crc = crc.checksum(&buf[4..]);
+        if entry.crc != expected_crc {
+            return Err(DbError::EntryCRCInvalid {
+                bucket: String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned()),
+                key: String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned()),
+            });
+        }
+        entry.crc = expected_crc;
+        Ok(entry)
+    }
+
+    // encoded_size reads the size of the encoded entry starting at buf from its header,
+    // None if buf is shorter than a header.
+    pub fn encoded_size(buf: &[u8]) -> Option<usize> {
+        let header = buf.get(..ENTRYHEADERSIZE)?;
+        let size_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap()) as usize;
+        Some(ENTRYHEADERSIZE + size_at(12) + size_at(16) + size_at(26))
+    }
+
+    // decode_unchecked decodes an entry without checking its crc, it is used for the
+    // entries held by the memtable whose crc is not kept up to date by append_encoded and
+    // set_range_encoded. the crc is set again when the entry is encoded.
+    pub fn decode_unchecked(buf: &[u8]) -> Self {
         let timestamp = i64::from_le_bytes(buf[4..12].try_into().unwrap());
         let key_size = u32::from_le_bytes(buf[12..16].try_into().unwrap());
         let value_size = u32::from_le_bytes(buf[16..20].try_into().unwrap());
@@ -84,17 +134,7 @@ impl Entry {
             ..(42 + bucket_size as usize + key_size as usize + value_size as usize)]
             .to_vec();
 
-        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
-        let expected_crc = crc.checksum(&buf[4..]);
-        let actual_crc = u32::from_le_bytes(buf[0..4].try_into().unwrap());
-        if actual_crc != expected_crc {
-            return Err(DbError::EntryCRCInvalid {
-                bucket: String::from_utf8(bucket).unwrap_or("".to_owned()),
-                key: String::from_utf8(key).unwrap_or("".to_owned()),
-            });
-        }
-
-        Ok(Entry {
+        Entry {
             meta: Meta {
                 timestamp,
                 key_size,
@@ -109,7 +149,134 @@ impl Entry {
             },
             key: Bytes::from(key),
             value: Bytes::from(value),
-            crc: expected_crc,
-        })
+            crc: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+        }
+    }
+
+    // encoded_value returns the value part of an encoded entry without decoding it.
+    pub fn encoded_value(buf: &[u8]) -> &[u8] {
+        &buf[Self::value_offset(buf)..]
+    }
+
+    // encoded_value_range returns the value bytes between start and end (both
+    // inclusive) of an encoded entry, negative offsets count from the value end.
+    pub fn encoded_value_range(buf: &[u8], start: isize, end: isize) -> &[u8] {
+        let value = Self::encoded_value(buf);
+        let len = value.len() as isize;
+        let start = cmp::max(if start < 0 { len + start } else { start }, 0);
+        let end = cmp::min(if end < 0 { len + end } else { end }, len - 1);
+        if start > end {
+            return &[];
+        }
+        &value[start as usize..=end as usize]
+    }
+
+    // append_encoded appends b to the value of an encoded entry in place, only the
+    // value size in the header is rewritten, the crc is left stale so that appending to a
+    // large value does not checksum it again. returns the new value size.
+    pub fn append_encoded(buf: &mut BytesMut, b: &[u8]) -> usize {
+        buf.extend_from_slice(b);
+        Self::reset_value_size(buf)
+    }
+
+    // set_range_encoded overwrites the value of an encoded entry from offset with b,
+    // the value is padded with zero bytes if offset is past its end. the crc is left stale
+    // like append_encoded. returns the new value size.
+    pub fn set_range_encoded(buf: &mut BytesMut, offset: usize, b: &[u8]) -> usize {
+        if b.is_empty() {
+            return Self::encoded_value(buf).len();
+        }
+        let start = Self::value_offset(buf) + offset;
+        if start + b.len() > buf.len() {
+            buf.resize(start + b.len(), 0);
+        }
+        buf[start..start + b.len()].copy_from_slice(b);
+        Self::reset_value_size(buf)
+    }
+
+    // truncate_encoded cuts the value of an encoded entry to len bytes, the crc is left
+    // stale like append_encoded. returns the new value size.
+    pub fn truncate_encoded(buf: &mut BytesMut, len: usize) -> usize {
+        buf.truncate(Self::value_offset(buf) + len);
+        Self::reset_value_size(buf)
+    }
+
+    fn value_offset(buf: &[u8]) -> usize {
+        let key_size = u32::from_le_bytes(buf[12..16].try_into().unwrap());
+        let bucket_size = u32::from_le_bytes(buf[26..30].try_into().unwrap());
+        ENTRYHEADERSIZE + bucket_size as usize + key_size as usize
+    }
+
+    fn reset_value_size(buf: &mut [u8]) -> usize {
+        let value_size = buf.len() - Self::value_offset(buf);
+        buf[16..20].copy_from_slice(&(value_size as u32).to_le_bytes());
+        value_size
+    }
+
+    fn set_crc(buf: &mut [u8]) {
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI);
+        let c32 = crc.checksum(&buf[4..]);
+        buf[0..4].copy_from_slice(&c32.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_entry(key: &str, value: &str) -> Entry {
+        Entry {
+            key: Bytes::from(key.to_owned()),
+            value: Bytes::from(value.to_owned()),
+            meta: Meta::new(
+                Bytes::from("bucket"),
+                key.len() as u32,
+                value.len() as u32,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ),
+            crc: 0,
+        }
+    }
+
+    #[test]
+    fn test_append_encoded() {
+        let mut buf = BytesMut::from(&new_entry("key1", "hello").encode()[..]);
+        assert_eq!(Entry::append_encoded(&mut buf, b" world"), 11);
+        assert!(Entry::decode(&buf).is_err());
+        let entry = Entry::decode_unchecked(&buf);
+        assert_eq!(entry.value, Bytes::from("hello world"));
+        assert_eq!(entry.meta.value_size, 11);
+        assert_eq!(entry.key, Bytes::from("key1"));
+        // the crc is set again when the entry is encoded
+        assert!(Entry::decode(&entry.encode()).is_ok());
+    }
+
+    #[test]
+    fn test_set_range_encoded() {
+        let mut buf = BytesMut::from(&new_entry("key1", "hello world").encode()[..]);
+        assert_eq!(Entry::set_range_encoded(&mut buf, 6, b"redis"), 11);
+        assert_eq!(Entry::encoded_value(&buf), b"hello redis");
+
+        assert_eq!(Entry::set_range_encoded(&mut buf, 13, b"!"), 14);
+        let entry = Entry::decode_unchecked(&buf);
+        assert_eq!(entry.value, Bytes::from_static(b"hello redis\0\0!"));
+
+        assert_eq!(Entry::set_range_encoded(&mut buf, 100, b""), 14);
+    }
+
+    #[test]
+    fn test_encoded_value_range() {
+        let buf = new_entry("key1", "This is a string").encode();
+        assert_eq!(Entry::encoded_value_range(&buf, 0, 3), b"This");
+        assert_eq!(Entry::encoded_value_range(&buf, -3, -1), b"ing");
+        assert_eq!(Entry::encoded_value_range(&buf, 0, -1), b"This is a string");
+        assert_eq!(Entry::encoded_value_range(&buf, 10, 100), b"string");
+        assert_eq!(Entry::encoded_value_range(&buf, 5, 3), b"");
+        assert_eq!(Entry::encoded_value_range(&buf, -100, 1), b"Th");
     }
 }
@@ -1,4 +1,5 @@
 use bytes::Bytes;
+use chrono::Local;
 
 #[derive(Debug, Clone, Default)]
 pub struct Meta {
@@ -41,6 +42,13 @@ impl Meta {
         }
     }
 
+    pub fn is_expired(&self) -> bool {
+        if self.ttl == 0 {
+            return false;
+        }
+        self.ttl + self.timestamp as u32 <= Local::now().timestamp() as u32
+    }
+
     pub fn set_entry_header_buf<'a>(&self, buf: &'a mut [u8]) -> &'a mut [u8] {
         let timestamp_bytes = self.timestamp.to_le_bytes();
         buf[4..12].copy_from_slice(&timestamp_bytes);
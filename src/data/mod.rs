@@ -1,4 +1,7 @@
 pub mod entry;
 pub mod meta;
+pub mod payload;
 
 pub static ENTRYHEADERSIZE: usize = 42;
+// max size of a value, appends and setranges growing a value past it fail
+pub static MAX_VALUE_SIZE: usize = 512 * 1024 * 1024;
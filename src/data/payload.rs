@@ -0,0 +1,342 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::enums::{ListDirection, TsAggregation};
+
+// payloads of the operates that need more arguments than a key and a value, they are
+// stored as the entry value so that the wal can be replayed and the index rebuilt.
+//
+//  setrange: | offset u64 | bytes |
+//  lrem:    | count i64 | element |
+//  linsert: | before u8 | pivot size u32 | pivot | element |
+//  lset:    | index u64 | element |
+//  ltrim:   | start i64 | end i64 |
+//  lmove:   | wherefrom u8 | whereto u8 | destination |
+//  smove:   | destination size u32 | destination | member |
+//  sstore:  | key size u32 | key | key size u32 | key | ...
+//  zscore:  | score f64 | value |
+//  json:    | path size u32 | path | value |
+//  tscreate: | retention ms i64 |
+//  tsadd:   | timestamp i64 | value f64 |
+//  tsrule:  | aggregation u8 | bucket ms i64 | destination |
+//  tstrim:  | before i64 |
+//  batch:   | entry size u32 | entry | entry size u32 | entry | ...
+
+pub fn encode_setrange(offset: usize, b: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(8 + b.len());
+    buf.put_u64_le(offset as u64);
+    buf.put_slice(b);
+    buf.freeze()
+}
+
+pub fn decode_setrange(b: &[u8]) -> Option<(usize, &[u8])> {
+    let offset = u64::from_le_bytes(b.get(0..8)?.try_into().ok()?);
+    Some((offset as usize, &b[8..]))
+}
+
+pub fn encode_lrem(count: isize, element: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(8 + element.len());
+    buf.put_i64_le(count as i64);
+    buf.put_slice(element);
+    buf.freeze()
+}
+
+pub fn decode_lrem(b: &[u8]) -> Option<(isize, &[u8])> {
+    let count = i64::from_le_bytes(b.get(0..8)?.try_into().ok()?);
+    Some((count as isize, &b[8..]))
+}
+
+pub fn encode_linsert(before: bool, pivot: &[u8], element: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(5 + pivot.len() + element.len());
+    buf.put_u8(before as u8);
+    buf.put_u32_le(pivot.len() as u32);
+    buf.put_slice(pivot);
+    buf.put_slice(element);
+    buf.freeze()
+}
+
+pub fn decode_linsert(b: &[u8]) -> Option<(bool, &[u8], &[u8])> {
+    let before = *b.first()? == 1;
+    let pivot_size = u32::from_le_bytes(b.get(1..5)?.try_into().ok()?) as usize;
+    let pivot = b.get(5..5 + pivot_size)?;
+    Some((before, pivot, &b[5 + pivot_size..]))
+}
+
+pub fn encode_lset(index: usize, element: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(8 + element.len());
+    buf.put_u64_le(index as u64);
+    buf.put_slice(element);
+    buf.freeze()
+}
+
+pub fn decode_lset(b: &[u8]) -> Option<(usize, &[u8])> {
+    let index = u64::from_le_bytes(b.get(0..8)?.try_into().ok()?);
+    Some((index as usize, &b[8..]))
+}
+
+pub fn encode_ltrim(start: isize, end: isize) -> Bytes {
+    let mut buf = BytesMut::with_capacity(16);
+    buf.put_i64_le(start as i64);
+    buf.put_i64_le(end as i64);
+    buf.freeze()
+}
+
+pub fn decode_ltrim(b: &[u8]) -> Option<(isize, isize)> {
+    let start = i64::from_le_bytes(b.get(0..8)?.try_into().ok()?);
+    let end = i64::from_le_bytes(b.get(8..16)?.try_into().ok()?);
+    Some((start as isize, end as isize))
+}
+
+pub fn encode_lmove(destination: &str, wherefrom: ListDirection, whereto: ListDirection) -> Bytes {
+    let mut buf = BytesMut::with_capacity(2 + destination.len());
+    buf.put_u8(usize::from(wherefrom) as u8);
+    buf.put_u8(usize::from(whereto) as u8);
+    buf.put_slice(destination.as_bytes());
+    buf.freeze()
+}
+
+pub fn decode_lmove(b: &[u8]) -> Option<(&str, ListDirection, ListDirection)> {
+    let wherefrom = ListDirection::try_from(*b.first()? as usize).ok()?;
+    let whereto = ListDirection::try_from(*b.get(1)? as usize).ok()?;
+    let destination = std::str::from_utf8(&b[2..]).ok()?;
+    Some((destination, wherefrom, whereto))
+}
+
+pub fn encode_smove(destination: &str, member: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(4 + destination.len() + member.len());
+    buf.put_u32_le(destination.len() as u32);
+    buf.put_slice(destination.as_bytes());
+    buf.put_slice(member);
+    buf.freeze()
+}
+
+pub fn decode_smove(b: &[u8]) -> Option<(&str, &[u8])> {
+    let destination_size = u32::from_le_bytes(b.get(0..4)?.try_into().ok()?) as usize;
+    let destination = std::str::from_utf8(b.get(4..4 + destination_size)?).ok()?;
+    Some((destination, &b[4 + destination_size..]))
+}
+
+pub fn encode_sstore(keys: &[&str]) -> Bytes {
+    let mut buf = BytesMut::new();
+    for key in keys {
+        buf.put_u32_le(key.len() as u32);
+        buf.put_slice(key.as_bytes());
+    }
+    buf.freeze()
+}
+
+pub fn decode_sstore(b: &[u8]) -> Option<Vec<&str>> {
+    let mut keys = vec![];
+    let mut offset = 0;
+    while offset < b.len() {
+        let key_size = u32::from_le_bytes(b.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        keys.push(std::str::from_utf8(b.get(offset + 4..offset + 4 + key_size)?).ok()?);
+        offset += 4 + key_size;
+    }
+    Some(keys)
+}
+
+pub fn encode_zscore(score: f64, value: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(8 + value.len());
+    buf.put_f64_le(score);
+    buf.put_slice(value);
+    buf.freeze()
+}
+
+pub fn decode_zscore(b: &[u8]) -> Option<(f64, &[u8])> {
+    let score = f64::from_le_bytes(b.get(0..8)?.try_into().ok()?);
+    Some((score, &b[8..]))
+}
+
+pub fn encode_json(path: &str, value: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(4 + path.len() + value.len());
+    buf.put_u32_le(path.len() as u32);
+    buf.put_slice(path.as_bytes());
+    buf.put_slice(value);
+    buf.freeze()
+}
+
+pub fn decode_json(b: &[u8]) -> Option<(&str, &[u8])> {
+    let path_size = u32::from_le_bytes(b.get(0..4)?.try_into().ok()?) as usize;
+    let path = std::str::from_utf8(b.get(4..4 + path_size)?).ok()?;
+    Some((path, &b[4 + path_size..]))
+}
+
+pub fn encode_tscreate(retention_ms: i64) -> Bytes {
+    Bytes::copy_from_slice(&retention_ms.to_le_bytes())
+}
+
+pub fn decode_tscreate(b: &[u8]) -> Option<i64> {
+    Some(i64::from_le_bytes(b.get(0..8)?.try_into().ok()?))
+}
+
+pub fn encode_tsadd(timestamp: i64, value: f64) -> Bytes {
+    let mut buf = BytesMut::with_capacity(16);
+    buf.put_i64_le(timestamp);
+    buf.put_f64_le(value);
+    buf.freeze()
+}
+
+pub fn decode_tsadd(b: &[u8]) -> Option<(i64, f64)> {
+    let timestamp = i64::from_le_bytes(b.get(0..8)?.try_into().ok()?);
+    let value = f64::from_le_bytes(b.get(8..16)?.try_into().ok()?);
+    Some((timestamp, value))
+}
+
+pub fn encode_tsrule(destination: &str, aggregation: TsAggregation, bucket_ms: i64) -> Bytes {
+    let mut buf = BytesMut::with_capacity(9 + destination.len());
+    buf.put_u8(usize::from(aggregation) as u8);
+    buf.put_i64_le(bucket_ms);
+    buf.put_slice(destination.as_bytes());
+    buf.freeze()
+}
+
+pub fn decode_tsrule(b: &[u8]) -> Option<(&str, TsAggregation, i64)> {
+    let aggregation = TsAggregation::try_from(*b.first()? as usize).ok()?;
+    let bucket_ms = i64::from_le_bytes(b.get(1..9)?.try_into().ok()?);
+    let destination = std::str::from_utf8(&b[9..]).ok()?;
+    Some((destination, aggregation, bucket_ms))
+}
+
+pub fn encode_tstrim(before: i64) -> Bytes {
+    Bytes::copy_from_slice(&before.to_le_bytes())
+}
+
+pub fn decode_tstrim(b: &[u8]) -> Option<i64> {
+    Some(i64::from_le_bytes(b.get(0..8)?.try_into().ok()?))
+}
+
+pub fn encode_batch(entries: &[Vec<u8>]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(entries.iter().map(|entry| 4 + entry.len()).sum());
+    for entry in entries {
+        buf.put_u32_le(entry.len() as u32);
+        buf.put_slice(entry);
+    }
+    buf.freeze()
+}
+
+pub fn decode_batch(b: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut entries = vec![];
+    let mut offset = 0;
+    while offset < b.len() {
+        let entry_size = u32::from_le_bytes(b.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        entries.push(b.get(offset + 4..offset + 4 + entry_size)?);
+        offset += 4 + entry_size;
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setrange() {
+        let b = encode_setrange(6, b"redis");
+        assert_eq!(decode_setrange(&b), Some((6, &b"redis"[..])));
+        assert_eq!(decode_setrange(b"short"), None);
+    }
+
+    #[test]
+    fn test_lrem() {
+        let b = encode_lrem(-2, b"element");
+        assert_eq!(decode_lrem(&b), Some((-2, &b"element"[..])));
+        assert_eq!(decode_lrem(b"short"), None);
+    }
+
+    #[test]
+    fn test_linsert() {
+        let b = encode_linsert(true, b"pivot", b"element");
+        assert_eq!(
+            decode_linsert(&b),
+            Some((true, &b"pivot"[..], &b"element"[..]))
+        );
+        assert_eq!(decode_linsert(&b[..7]), None);
+    }
+
+    #[test]
+    fn test_lset() {
+        let b = encode_lset(3, b"element");
+        assert_eq!(decode_lset(&b), Some((3, &b"element"[..])));
+        assert_eq!(decode_lset(b"short"), None);
+    }
+
+    #[test]
+    fn test_ltrim() {
+        let b = encode_ltrim(1, -1);
+        assert_eq!(decode_ltrim(&b), Some((1, -1)));
+    }
+
+    #[test]
+    fn test_lmove() {
+        let b = encode_lmove("key2", ListDirection::Right, ListDirection::Left);
+        assert_eq!(
+            decode_lmove(&b),
+            Some(("key2", ListDirection::Right, ListDirection::Left))
+        );
+        assert_eq!(decode_lmove(&[3, 1]), None);
+    }
+
+    #[test]
+    fn test_smove() {
+        let b = encode_smove("key2", b"member");
+        assert_eq!(decode_smove(&b), Some(("key2", &b"member"[..])));
+        assert_eq!(decode_smove(&b[..5]), None);
+    }
+
+    #[test]
+    fn test_sstore() {
+        let b = encode_sstore(&["key1", "key2", ""]);
+        assert_eq!(decode_sstore(&b), Some(vec!["key1", "key2", ""]));
+        assert_eq!(decode_sstore(&b[..6]), None);
+    }
+
+    #[test]
+    fn test_json() {
+        let b = encode_json("$.a[0]", b"{}");
+        assert_eq!(decode_json(&b), Some(("$.a[0]", &b"{}"[..])));
+        assert_eq!(decode_json(&b[..6]), None);
+    }
+
+    #[test]
+    fn test_zscore() {
+        let b = encode_zscore(-1.5, b"value");
+        assert_eq!(decode_zscore(&b), Some((-1.5, &b"value"[..])));
+        assert_eq!(decode_zscore(b"1|score"), None);
+    }
+
+    #[test]
+    fn test_tscreate() {
+        assert_eq!(
+            decode_tscreate(&encode_tscreate(3_600_000)),
+            Some(3_600_000)
+        );
+        assert_eq!(decode_tscreate(b"short"), None);
+    }
+
+    #[test]
+    fn test_tsadd() {
+        let b = encode_tsadd(-10, 2.5);
+        assert_eq!(decode_tsadd(&b), Some((-10, 2.5)));
+        assert_eq!(decode_tsadd(&b[..8]), None);
+    }
+
+    #[test]
+    fn test_tsrule() {
+        let b = encode_tsrule("key2", TsAggregation::Max, 60_000);
+        assert_eq!(
+            decode_tsrule(&b),
+            Some(("key2", TsAggregation::Max, 60_000))
+        );
+        assert_eq!(decode_tsrule(&[9]), None);
+    }
+
+    #[test]
+    fn test_batch() {
+        let b = encode_batch(&[b"entry1".to_vec(), vec![], b"entry2".to_vec()]);
+        assert_eq!(
+            decode_batch(&b),
+            Some(vec![&b"entry1"[..], &[], &b"entry2"[..]])
+        );
+        assert_eq!(decode_batch(&b[..8]), None);
+    }
+}
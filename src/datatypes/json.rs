@@ -0,0 +1,640 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+
+// max nesting of arrays and objects accepted by the parser.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+// PathSegment is a step of a path, a member of an object or an element of an array.
+// negative indexes count from the end of the array.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(isize),
+}
+
+// Json keeps the parsed document of every key.
+#[derive(Debug, Default)]
+pub struct Json {
+    items: HashMap<String, JsonValue>,
+}
+
+impl Json {
+    pub fn new() -> Self {
+        Json {
+            items: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, key: &str, path: &[PathSegment]) -> Option<&JsonValue> {
+        self.items.get(key)?.get(path)
+    }
+
+    // set puts value at path, the root path creates or replaces the document. a member is
+    // added to an existing object, an array element is only replaced. returns false if the
+    // parent of path does not exist.
+    pub fn set(&mut self, key: &str, path: &[PathSegment], value: JsonValue) -> bool {
+        let Some((last, parent)) = path.split_last() else {
+            self.items.insert(key.to_string(), value);
+            return true;
+        };
+        let Some(parent) = self.items.get_mut(key).and_then(|doc| doc.get_mut(parent)) else {
+            return false;
+        };
+        match (parent, last) {
+            (JsonValue::Object(members), PathSegment::Key(name)) => {
+                members.insert(name.clone(), value);
+                true
+            }
+            (JsonValue::Array(elements), PathSegment::Index(index)) => {
+                match array_index(elements.len(), *index) {
+                    Some(i) => {
+                        elements[i] = value;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // del removes the value at path, the root path removes the document. returns the
+    // number of removed values.
+    pub fn del(&mut self, key: &str, path: &[PathSegment]) -> usize {
+        let Some((last, parent)) = path.split_last() else {
+            return self.items.remove(key).map_or(0, |_| 1);
+        };
+        let Some(parent) = self.items.get_mut(key).and_then(|doc| doc.get_mut(parent)) else {
+            return 0;
+        };
+        let removed = match (parent, last) {
+            (JsonValue::Object(members), PathSegment::Key(name)) => members.remove(name).is_some(),
+            (JsonValue::Array(elements), PathSegment::Index(index)) => {
+                match array_index(elements.len(), *index) {
+                    Some(i) => {
+                        elements.remove(i);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        };
+        removed as usize
+    }
+
+    // arr_append appends values to the array at path and returns its new length.
+    pub fn arr_append(
+        &mut self,
+        key: &str,
+        path: &[PathSegment],
+        values: Vec<JsonValue>,
+    ) -> Option<usize> {
+        match self.items.get_mut(key)?.get_mut(path)? {
+            JsonValue::Array(elements) => {
+                elements.extend(values);
+                Some(elements.len())
+            }
+            _ => None,
+        }
+    }
+
+    // num_incr_by adds increment to the number at path and returns the new number.
+    pub fn num_incr_by(&mut self, key: &str, path: &[PathSegment], increment: f64) -> Option<f64> {
+        match self.items.get_mut(key)?.get_mut(path)? {
+            JsonValue::Number(number) => {
+                *number += increment;
+                Some(*number)
+            }
+            _ => None,
+        }
+    }
+
+    // removed returns the value del removes at path, with the path its index resolves to
+    // so that put_back can insert the value again.
+    pub fn removed(
+        &self,
+        key: &str,
+        path: &[PathSegment],
+    ) -> Option<(Vec<PathSegment>, JsonValue)> {
+        let value = self.get(key, path)?.clone();
+        let mut path = path.to_vec();
+        if let Some((PathSegment::Index(index), parent)) = path.split_last_mut() {
+            if let Some(JsonValue::Array(elements)) = self.get(key, parent) {
+                *index = array_index(elements.len(), *index)? as isize;
+            }
+        }
+        Some((path, value))
+    }
+
+    // put_back puts value back at the path returned by removed, an array element is inserted
+    // at its index rather than replacing the element there.
+    pub fn put_back(&mut self, key: &str, path: &[PathSegment], value: JsonValue) {
+        if let Some((PathSegment::Index(index), parent)) = path.split_last() {
+            let parent = self.items.get_mut(key).and_then(|doc| doc.get_mut(parent));
+            if let Some(JsonValue::Array(elements)) = parent {
+                elements.insert((*index as usize).min(elements.len()), value);
+            }
+            return;
+        }
+        self.set(key, path, value);
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<JsonValue> {
+        self.items.remove(key)
+    }
+
+    pub fn insert(&mut self, key: &str, doc: JsonValue) {
+        self.items.insert(key.to_string(), doc);
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.items.keys()
+    }
+}
+
+impl JsonValue {
+    pub fn parse(b: &[u8]) -> Result<JsonValue, String> {
+        let mut parser = Parser { b, pos: 0 };
+        let value = parser.parse_value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < b.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, path: &[PathSegment]) -> Option<&JsonValue> {
+        path.iter()
+            .try_fold(self, |value, segment| match (value, segment) {
+                (JsonValue::Object(members), PathSegment::Key(name)) => members.get(name),
+                (JsonValue::Array(elements), PathSegment::Index(index)) => {
+                    elements.get(array_index(elements.len(), *index)?)
+                }
+                _ => None,
+            })
+    }
+
+    pub fn get_mut(&mut self, path: &[PathSegment]) -> Option<&mut JsonValue> {
+        path.iter()
+            .try_fold(self, |value, segment| match (value, segment) {
+                (JsonValue::Object(members), PathSegment::Key(name)) => members.get_mut(name),
+                (JsonValue::Array(elements), PathSegment::Index(index)) => {
+                    let i = array_index(elements.len(), *index)?;
+                    elements.get_mut(i)
+                }
+                _ => None,
+            })
+    }
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => f.write_str("null"),
+            JsonValue::Bool(b) => write!(f, "{}", b),
+            // integral numbers are written without a fraction, as they were most likely given
+            JsonValue::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                write!(f, "{}", *n as i64)
+            }
+            JsonValue::Number(n) if n.is_finite() => write!(f, "{}", n),
+            JsonValue::Number(_) => f.write_str("null"),
+            JsonValue::String(s) => write_string(f, s),
+            JsonValue::Array(elements) => {
+                f.write_char('[')?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_char(']')
+            }
+            JsonValue::Object(members) => {
+                f.write_char('{')?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+fn array_index(len: usize, index: isize) -> Option<usize> {
+    let i = if index < 0 {
+        len.checked_sub(index.unsigned_abs())?
+    } else {
+        index as usize
+    };
+    (i < len).then_some(i)
+}
+
+// parse_path parses a JSONPath-like path: $ is the root, followed by .name, ['name'] or
+// ["name"] members and [index] elements. the leading $ may be omitted.
+pub fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let b = path.as_bytes();
+    let mut pos = usize::from(b.first() == Some(&b'$'));
+    let mut segments = vec![];
+    while pos < b.len() {
+        match b[pos] {
+            b'.' => {
+                let start = pos + 1;
+                let end = b[start..]
+                    .iter()
+                    .position(|c| *c == b'.' || *c == b'[')
+                    .map_or(b.len(), |n| start + n);
+                if start == end {
+                    return Err(format!("empty member name at {}", pos));
+                }
+                segments.push(PathSegment::Key(path[start..end].to_owned()));
+                pos = end;
+            }
+            b'[' => {
+                let end = match b.get(pos + 1) {
+                    Some(quote @ (b'\'' | b'"')) => b[pos + 2..]
+                        .iter()
+                        .position(|c| c == quote)
+                        .map(|n| pos + 2 + n + 1),
+                    _ => Some(pos + 1),
+                };
+                let close = end
+                    .and_then(|end| b[end..].iter().position(|c| *c == b']').map(|n| end + n))
+                    .ok_or_else(|| format!("unterminated bracket at {}", pos))?;
+                let inner = &path[pos + 1..close];
+                let segment = match inner.as_bytes().first() {
+                    Some(b'\'' | b'"') if inner.len() >= 2 => {
+                        PathSegment::Key(inner[1..inner.len() - 1].to_owned())
+                    }
+                    _ => PathSegment::Index(
+                        inner
+                            .trim()
+                            .parse()
+                            .map_err(|_| format!("invalid index {} at {}", inner, pos))?,
+                    ),
+                };
+                segments.push(segment);
+                pos = close + 1;
+            }
+            _ => return Err(format!("unexpected character at {}", pos)),
+        }
+    }
+    Ok(segments)
+}
+
+struct Parser<'a> {
+    b: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> String {
+        format!("{} at {}", msg, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.b.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn consume(&mut self, literal: &str) -> bool {
+        if self.b[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            return true;
+        }
+        false
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        self.skip_whitespace();
+        match self.b.get(self.pos) {
+            Some(b'{') => self.parse_object(depth),
+            Some(b'[') => self.parse_array(depth),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            _ if self.consume("null") => Ok(JsonValue::Null),
+            _ if self.consume("true") => Ok(JsonValue::Bool(true)),
+            _ if self.consume("false") => Ok(JsonValue::Bool(false)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<JsonValue, String> {
+        self.pos += 1;
+        let mut members = BTreeMap::new();
+        self.skip_whitespace();
+        if self.consume("}") {
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.b.get(self.pos) != Some(&b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.parse_string()?;
+            self.skip_whitespace();
+            if !self.consume(":") {
+                return Err(self.error("expected ':'"));
+            }
+            members.insert(name, self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            if self.consume("}") {
+                return Ok(JsonValue::Object(members));
+            }
+            if !self.consume(",") {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<JsonValue, String> {
+        self.pos += 1;
+        let mut elements = vec![];
+        self.skip_whitespace();
+        if self.consume("]") {
+            return Ok(JsonValue::Array(elements));
+        }
+        loop {
+            elements.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            if self.consume("]") {
+                return Ok(JsonValue::Array(elements));
+            }
+            if !self.consume(",") {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, String> {
+        let start = self.pos;
+        self.consume("-");
+        let digits = |p: &mut Self| {
+            let start = p.pos;
+            while p.b.get(p.pos).is_some_and(|c| c.is_ascii_digit()) {
+                p.pos += 1;
+            }
+            p.pos > start
+        };
+        // the integer part is 0 or does not start with 0
+        if self.consume("0") {
+            if self.b.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("leading zero"));
+            }
+        } else if !digits(self) {
+            return Err(self.error("expected a digit"));
+        }
+        if self.consume(".") && !digits(self) {
+            return Err(self.error("expected a digit"));
+        }
+        if matches!(self.b.get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.b.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        std::str::from_utf8(&self.b[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|n| n.is_finite())
+            .map(JsonValue::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut s = Vec::new();
+        loop {
+            match self.b.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = match self.b.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    s.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(c) if *c < 0x20 => return Err(self.error("control character in string")),
+                Some(c) => s.push(*c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        String::from_utf8(s).map_err(|_| self.error("invalid utf-8 in string"))
+    }
+
+    // parse_unicode_escape parses the hex digits of a \u escape, pos is on the 'u' and ends
+    // on the last digit. a surrogate pair is made of two escapes.
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if self.b.get(self.pos + 1) != Some(&b'\\') || self.b.get(self.pos + 2) != Some(&b'u') {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.parse_hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("invalid low surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid code point"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let hex = self
+            .b
+            .get(self.pos + 1..self.pos + 5)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(s: &str) -> JsonValue {
+        JsonValue::parse(s.as_bytes()).unwrap()
+    }
+
+    fn path(s: &str) -> Vec<PathSegment> {
+        parse_path(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let value = doc(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "x\"\u00e9\ud83d\ude00"}} "#);
+        assert_eq!(
+            value.get(&path("$.a")),
+            Some(&JsonValue::Array(vec![
+                JsonValue::Number(1.0),
+                JsonValue::Number(-25.0),
+                JsonValue::Bool(true),
+                JsonValue::Null,
+            ]))
+        );
+        assert_eq!(
+            value.get(&path("$.b.c")),
+            Some(&JsonValue::String("x\"é😀".to_owned()))
+        );
+        for invalid in ["", "{", "[1,]", "{\"a\" 1}", "01x", "\"\\x\"", "1 2", "-"] {
+            assert!(JsonValue::parse(invalid.as_bytes()).is_err(), "{}", invalid);
+        }
+        for invalid in ["01", "-012", "1.", ".5", "1e999"] {
+            assert!(JsonValue::parse(invalid.as_bytes()).is_err(), "{}", invalid);
+        }
+        for valid in ["0", "-0", "0.5", "10", "1e5", "-0.0e-1"] {
+            assert!(JsonValue::parse(valid.as_bytes()).is_ok(), "{}", valid);
+        }
+        let nested = "[".repeat(MAX_DEPTH + 2) + &"]".repeat(MAX_DEPTH + 2);
+        assert!(JsonValue::parse(nested.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_to_string() {
+        let s = r#"{"a":[1,2.5,"x\n"],"b":{"c":null,"d":false}}"#;
+        assert_eq!(doc(s).to_string(), s);
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("$"), Ok(vec![]));
+        assert_eq!(
+            parse_path("$.a['b.c'][-1].d"),
+            Ok(vec![
+                PathSegment::Key("a".to_owned()),
+                PathSegment::Key("b.c".to_owned()),
+                PathSegment::Index(-1),
+                PathSegment::Key("d".to_owned()),
+            ])
+        );
+        assert_eq!(parse_path(".a"), Ok(vec![PathSegment::Key("a".to_owned())]));
+        assert!(parse_path("$.").is_err());
+        assert!(parse_path("$[x]").is_err());
+        assert!(parse_path("$['a'").is_err());
+        assert!(parse_path("a").is_err());
+    }
+
+    #[test]
+    fn test_set() {
+        let mut json = Json::new();
+        assert!(!json.set("key1", &path("$.a"), doc("1")));
+        assert!(json.set("key1", &path("$"), doc(r#"{"a":{"b":[1,2]}}"#)));
+        assert!(json.set("key1", &path("$.a.c"), doc("true")));
+        assert!(json.set("key1", &path("$.a.b[-1]"), doc("3")));
+        assert!(!json.set("key1", &path("$.a.b[2]"), doc("4")));
+        assert!(!json.set("key1", &path("$.x.y"), doc("4")));
+        assert_eq!(
+            json.get("key1", &path("$")).unwrap().to_string(),
+            r#"{"a":{"b":[1,3],"c":true}}"#
+        );
+    }
+
+    #[test]
+    fn test_del() {
+        let mut json = Json::new();
+        json.set("key1", &path("$"), doc(r#"{"a":{"b":[1,2,3]},"c":1}"#));
+        assert_eq!(json.del("key1", &path("$.a.b[0]")), 1);
+        assert_eq!(json.del("key1", &path("$.c")), 1);
+        assert_eq!(json.del("key1", &path("$.c")), 0);
+        assert_eq!(
+            json.get("key1", &path("$")).unwrap().to_string(),
+            r#"{"a":{"b":[2,3]}}"#
+        );
+        assert_eq!(json.del("key1", &path("$")), 1);
+        assert_eq!(json.get("key1", &path("$")), None);
+    }
+
+    #[test]
+    fn test_put_back() {
+        let mut json = Json::new();
+        let text = r#"{"a":{"b":[1,2,3]},"c":1}"#;
+        json.set("key1", &path("$"), doc(text));
+        for removed in ["$.a.b[-2]", "$.c", "$"] {
+            let (resolved, value) = json.removed("key1", &path(removed)).unwrap();
+            assert_eq!(json.del("key1", &path(removed)), 1);
+            json.put_back("key1", &resolved, value);
+            assert_eq!(json.get("key1", &path("$")).unwrap().to_string(), text);
+        }
+        assert_eq!(json.removed("key1", &path("$.a.b[3]")), None);
+    }
+
+    #[test]
+    fn test_arr_append() {
+        let mut json = Json::new();
+        json.set("key1", &path("$"), doc(r#"{"a":[1],"b":2}"#));
+        assert_eq!(
+            json.arr_append("key1", &path("$.a"), vec![doc("2"), doc("\"x\"")]),
+            Some(3)
+        );
+        assert_eq!(json.arr_append("key1", &path("$.b"), vec![doc("2")]), None);
+        assert_eq!(json.arr_append("key2", &path("$.a"), vec![doc("2")]), None);
+        assert_eq!(json.get("key1", &path("$.a[2]")), Some(&doc("\"x\"")));
+    }
+
+    #[test]
+    fn test_num_incr_by() {
+        let mut json = Json::new();
+        json.set("key1", &path("$"), doc(r#"{"a":{"n":1},"s":"x"}"#));
+        assert_eq!(json.num_incr_by("key1", &path("$.a.n"), 2.5), Some(3.5));
+        assert_eq!(json.num_incr_by("key1", &path("$.s"), 1.0), None);
+        assert_eq!(
+            json.get("key1", &path("$.a.n")),
+            Some(&JsonValue::Number(3.5))
+        );
+    }
+}
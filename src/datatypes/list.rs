@@ -1,9 +1,12 @@
 use bytes::Bytes;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
+
+use super::quicklist::QuickList;
+use crate::enums::ListDirection;
 
 #[derive(Debug, Default)]
 pub struct List {
-    items: HashMap<String, VecDeque<Bytes>>,
+    items: HashMap<String, QuickList>,
 }
 
 impl List {
@@ -35,13 +38,13 @@ impl List {
     pub(crate) fn lpop(&mut self, key: &str) -> Option<Bytes> {
         self.items
             .get_mut(key)
-            .unwrap_or(&mut VecDeque::new())
+            .unwrap_or(&mut QuickList::new())
             .pop_front()
             .map(|bytes| bytes)
     }
 
     pub(crate) fn rpush(&mut self, key: &str, values: Vec<Bytes>) -> Option<usize> {
-        let list = self.items.entry(key.into()).or_insert(VecDeque::new());
+        let list = self.items.entry(key.into()).or_insert(QuickList::new());
         for v in values.iter() {
             list.push_back(Bytes::from(v.clone()));
         }
@@ -62,13 +65,13 @@ impl List {
     pub(crate) fn rpop(&mut self, key: &str) -> Option<Bytes> {
         self.items
             .get_mut(key)
-            .unwrap_or(&mut VecDeque::new())
+            .unwrap_or(&mut QuickList::new())
             .pop_back()
             .map(|bytes| bytes)
     }
 
     pub(crate) fn llen(&self, key: &str) -> Option<usize> {
-        Some(self.items.get(key).unwrap_or(&VecDeque::default()).len())
+        Some(self.items.get(key).map_or(0, |list| list.len()))
     }
 
     pub(crate) fn lindex(&self, key: &str, index: usize) -> Option<Bytes> {
@@ -83,12 +86,10 @@ impl List {
         Some(Bytes::copy_from_slice(bytes_mut.as_ref()))
     }
 
-    pub(crate) fn lpos(&self, key: &str, value: &Bytes) -> Option<usize> {
-        if !self.items.contains_key(key) {
-            return None;
-        }
-        let list = self.items.get(key).unwrap();
-        list.into_iter().position(|item| item.eq(value))
+    // lpos returns the index of the first element matched, elements hold encoded
+    // entries so callers match on the decoded value.
+    pub(crate) fn lpos(&self, key: &str, matches: impl Fn(&Bytes) -> bool) -> Option<usize> {
+        self.items.get(key)?.iter().position(matches)
     }
 
     pub(crate) fn lset(&mut self, key: &str, index: usize, value: Bytes) -> Option<usize> {
@@ -111,17 +112,186 @@ impl List {
             return None;
         }
         let mut res = vec![];
-        for (index, item) in self.items.get(key).unwrap().iter().enumerate() {
-            if index < start {
-                continue;
-            }
-            if index > end {
-                break;
-            }
+        if start > end {
+            return Some(res);
+        }
+        for item in self
+            .items
+            .get(key)
+            .unwrap()
+            .iter_from(start)
+            .take((end - start).saturating_add(1))
+        {
             res.push(Bytes::copy_from_slice(item));
         }
         Some(res)
     }
+
+    // lrem removes the first count elements matched from head to tail when count > 0,
+    // from tail to head when count < 0 or all of them when count = 0.
+    pub(crate) fn lrem(
+        &mut self,
+        key: &str,
+        count: isize,
+        matches: impl Fn(&Bytes) -> bool,
+    ) -> Option<usize> {
+        let list = self.items.get_mut(key)?;
+        let limit = if count == 0 {
+            usize::MAX
+        } else {
+            count.unsigned_abs()
+        };
+        // from tail to head removes the last limit matches, so the first ones are skipped
+        let mut skip = if count < 0 {
+            let total = list.iter().filter(|item| matches(item)).count();
+            total.saturating_sub(limit)
+        } else {
+            0
+        };
+        let mut removed = 0;
+        list.retain(|item| {
+            if removed == limit || !matches(item) {
+                return true;
+            }
+            if skip > 0 {
+                skip -= 1;
+                return true;
+            }
+            removed += 1;
+            false
+        });
+        Some(removed)
+    }
+
+    // linsert inserts value before or after the first element matched as pivot,
+    // returns the list length or None if the key or the pivot does not exist.
+    pub(crate) fn linsert(
+        &mut self,
+        key: &str,
+        before: bool,
+        pivot: impl Fn(&Bytes) -> bool,
+        value: Bytes,
+    ) -> Option<usize> {
+        let list = self.items.get_mut(key)?;
+        let index = list.iter().position(pivot)?;
+        list.insert(if before { index } else { index + 1 }, value);
+        Some(list.len())
+    }
+
+    // ltrim keeps the elements between start and end (both inclusive), negative offsets
+    // count from the tail. returns the number of removed elements.
+    pub(crate) fn ltrim(&mut self, key: &str, start: isize, end: isize) -> Option<usize> {
+        let list = self.items.get_mut(key)?;
+        let len = list.len();
+        let Some((start, end)) = trim_bounds(len, start, end) else {
+            list.clear();
+            return Some(len);
+        };
+        list.truncate(end + 1);
+        list.truncate_front(start);
+        Some(len - (end - start + 1))
+    }
+
+    // lrem_matches returns the elements lrem removes with their indexes, from head to tail.
+    pub(crate) fn lrem_matches(
+        &self,
+        key: &str,
+        count: isize,
+        matches: impl Fn(&Bytes) -> bool,
+    ) -> Vec<(usize, Bytes)> {
+        let Some(list) = self.items.get(key) else {
+            return vec![];
+        };
+        let mut found: Vec<(usize, Bytes)> = list
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| matches(item))
+            .map(|(index, item)| (index, item.clone()))
+            .collect();
+        let limit = match count {
+            0 => found.len(),
+            _ => count.unsigned_abs().min(found.len()),
+        };
+        if count < 0 {
+            found.drain(..found.len() - limit);
+        } else {
+            found.truncate(limit);
+        }
+        found
+    }
+
+    // ltrim_removed returns the elements ltrim removes with their indexes, from head to tail.
+    pub(crate) fn ltrim_removed(&self, key: &str, start: isize, end: isize) -> Vec<(usize, Bytes)> {
+        let Some(list) = self.items.get(key) else {
+            return vec![];
+        };
+        let kept = trim_bounds(list.len(), start, end);
+        list.iter()
+            .enumerate()
+            .filter(|(index, _)| !kept.is_some_and(|(start, end)| (start..=end).contains(index)))
+            .map(|(index, item)| (index, item.clone()))
+            .collect()
+    }
+
+    // put_back inserts the elements removed from key back at their indexes, which are
+    // ascending as lrem_matches and ltrim_removed return them.
+    pub(crate) fn put_back(&mut self, key: &str, elements: Vec<(usize, Bytes)>) {
+        let list = self.items.entry(key.to_string()).or_default();
+        for (index, item) in elements {
+            list.insert(index, item);
+        }
+    }
+
+    pub(crate) fn remove_at(&mut self, key: &str, index: usize) -> Option<Bytes> {
+        self.items.get_mut(key)?.remove(index)
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&QuickList> {
+        self.items.get(key)
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<QuickList> {
+        self.items.remove(key)
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, list: QuickList) {
+        self.items.insert(key.to_string(), list);
+    }
+
+    // keys returns the keys that have elements.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &String> {
+        self.items
+            .iter()
+            .filter(|(_, list)| !list.is_empty())
+            .map(|(key, _)| key)
+    }
+
+    // lmove pops an element from one side of source and pushes it to one side of destination.
+    pub(crate) fn lmove(
+        &mut self,
+        source: &str,
+        destination: &str,
+        wherefrom: ListDirection,
+        whereto: ListDirection,
+    ) -> Option<Bytes> {
+        let value = match wherefrom {
+            ListDirection::Left => self.lpop(source)?,
+            ListDirection::Right => self.rpop(source)?,
+        };
+        match whereto {
+            ListDirection::Left => self.lpush(destination, vec![value.clone()]),
+            ListDirection::Right => self.rpush(destination, vec![value.clone()]),
+        };
+        Some(value)
+    }
+}
+
+// trim_bounds returns the first and the last index ltrim keeps, none if it keeps nothing.
+fn trim_bounds(len: usize, start: isize, end: isize) -> Option<(usize, usize)> {
+    let len = len as isize;
+    let start = (if start < 0 { len + start } else { start }).max(0);
+    let end = (if end < 0 { len + end } else { end }).min(len - 1);
+    (start <= end).then_some((start as usize, end as usize))
 }
 
 #[cfg(test)]
@@ -275,10 +445,10 @@ mod tests {
             Bytes::from("value3"),
             Bytes::from("value2"),
         ];
-        let result = list.lpos("key1", &Bytes::from("value4"));
+        let result = list.lpos("key1", |item| item == "value4");
         assert_eq!(result, None);
         list.lpush("key1", values);
-        let result = list.lpos("key1", &Bytes::from("value2"));
+        let result = list.lpos("key1", |item| item == "value2");
         assert_eq!(result, Some(0));
     }
 
@@ -312,5 +482,135 @@ mod tests {
         list.lpush("key1", values.clone());
         let result = list.lrange("key1", 0, 1);
         assert_eq!(result, Some(vec![values[2].clone(), values[1].clone()]));
+        let result = list.lrange("key1", 0, usize::MAX);
+        assert_eq!(result.map(|items| items.len()), Some(3));
+    }
+
+    #[test]
+    fn test_lrem() {
+        let mut list = List::new();
+        let values = vec![
+            Bytes::from("a"),
+            Bytes::from("b"),
+            Bytes::from("a"),
+            Bytes::from("c"),
+            Bytes::from("a"),
+        ];
+        assert_eq!(list.lrem("key1", 0, |item| item == "a"), None);
+        list.rpush("key1", values.clone());
+        assert_eq!(list.lrem("key1", -1, |item| item == "a"), Some(1));
+        assert_eq!(
+            list.lrange("key1", 0, 10),
+            Some(vec![
+                values[0].clone(),
+                values[1].clone(),
+                values[2].clone(),
+                values[3].clone()
+            ])
+        );
+        assert_eq!(list.lrem("key1", 1, |item| item == "a"), Some(1));
+        assert_eq!(list.lindex("key1", 0), Some(values[1].clone()));
+        list.rpush("key1", vec![Bytes::from("a"), Bytes::from("a")]);
+        assert_eq!(list.lrem("key1", 0, |item| item == "a"), Some(3));
+        assert_eq!(list.llen("key1"), Some(2));
+    }
+
+    #[test]
+    fn test_linsert() {
+        let mut list = List::new();
+        let values = vec![Bytes::from("a"), Bytes::from("b"), Bytes::from("c")];
+        assert_eq!(
+            list.linsert("key1", true, |item| item == "b", Bytes::from("x")),
+            None
+        );
+        list.rpush("key1", values);
+        assert_eq!(
+            list.linsert("key1", true, |item| item == "d", Bytes::from("x")),
+            None
+        );
+        assert_eq!(
+            list.linsert("key1", true, |item| item == "b", Bytes::from("x")),
+            Some(4)
+        );
+        assert_eq!(
+            list.linsert("key1", false, |item| item == "c", Bytes::from("y")),
+            Some(5)
+        );
+        let list_items = list.items.get("key1").unwrap();
+        assert_eq!(list_items[1], Bytes::from("x"));
+        assert_eq!(list_items[2], Bytes::from("b"));
+        assert_eq!(list_items[4], Bytes::from("y"));
+    }
+
+    #[test]
+    fn test_ltrim() {
+        let mut list = List::new();
+        let values: Vec<Bytes> = (0..5).map(|i| Bytes::from(format!("value{}", i))).collect();
+        assert_eq!(list.ltrim("key1", 0, 1), None);
+        list.rpush("key1", values.clone());
+        assert_eq!(list.ltrim("key1", 1, -2), Some(2));
+        assert_eq!(
+            list.lrange("key1", 0, 10),
+            Some(vec![
+                values[1].clone(),
+                values[2].clone(),
+                values[3].clone()
+            ])
+        );
+        assert_eq!(list.ltrim("key1", -100, 100), Some(0));
+        assert_eq!(list.ltrim("key1", 2, 1), Some(3));
+        assert_eq!(list.llen("key1"), Some(0));
+    }
+
+    #[test]
+    fn test_put_back() {
+        let mut list = List::new();
+        let values: Vec<Bytes> = ["a", "b", "a", "c", "a"]
+            .into_iter()
+            .map(Bytes::from)
+            .collect();
+        list.rpush("key1", values.clone());
+
+        let matched = list.lrem_matches("key1", -2, |item| item == "a");
+        assert_eq!(
+            matched,
+            vec![(2, values[2].clone()), (4, values[4].clone())]
+        );
+        list.lrem("key1", -2, |item| item == "a");
+        list.put_back("key1", matched);
+        assert_eq!(list.lrange("key1", 0, 10), Some(values.clone()));
+
+        let removed = list.ltrim_removed("key1", 1, -2);
+        assert_eq!(
+            removed,
+            vec![(0, values[0].clone()), (4, values[4].clone())]
+        );
+        list.ltrim("key1", 1, -2);
+        list.put_back("key1", removed);
+        assert_eq!(list.lrange("key1", 0, 10), Some(values.clone()));
+        assert_eq!(list.ltrim_removed("key1", 2, 1).len(), 5);
+
+        assert_eq!(list.remove_at("key1", 1), Some(values[1].clone()));
+        assert_eq!(list.llen("key1"), Some(4));
+    }
+
+    #[test]
+    fn test_lmove() {
+        let mut list = List::new();
+        let values = vec![Bytes::from("a"), Bytes::from("b"), Bytes::from("c")];
+        let result = list.lmove("key1", "key2", ListDirection::Left, ListDirection::Right);
+        assert_eq!(result, None);
+        list.rpush("key1", values.clone());
+        let result = list.lmove("key1", "key2", ListDirection::Right, ListDirection::Left);
+        assert_eq!(result, Some(values[2].clone()));
+        let result = list.lmove("key1", "key2", ListDirection::Left, ListDirection::Left);
+        assert_eq!(result, Some(values[0].clone()));
+        assert_eq!(
+            list.lrange("key2", 0, 10),
+            Some(vec![values[0].clone(), values[2].clone()])
+        );
+        let result = list.lmove("key1", "key1", ListDirection::Left, ListDirection::Right);
+        assert_eq!(result, Some(values[1].clone()));
+        assert_eq!(list.llen("key1"), Some(1));
     }
 }
@@ -1,3 +1,7 @@
+pub mod json;
 pub mod list;
+pub mod quicklist;
+pub mod scan;
 pub mod set;
 pub mod sortedset;
+pub mod timeseries;
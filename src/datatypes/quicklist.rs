@@ -0,0 +1,398 @@
+use std::collections::VecDeque;
+use std::ops::Index;
+
+use bytes::{Bytes, BytesMut};
+
+// max number of elements in a chunk, a list up to this size is a single chunk.
+const CHUNK_SIZE: usize = 128;
+// max bytes of a single chunk list whose elements are packed in one allocation.
+const PACKED_SIZE: usize = 8 * 1024;
+
+// QuickList is a deque of bounded chunks, so that indexed access only walks the chunks
+// and inserts/removes in the middle of the list only move the elements of one chunk.
+#[derive(Debug, Default, Clone)]
+pub struct QuickList {
+    chunks: VecDeque<VecDeque<Bytes>>,
+    len: usize,
+}
+
+impl QuickList {
+    pub fn new() -> Self {
+        QuickList {
+            chunks: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_front(&mut self, value: Bytes) {
+        match self.chunks.front_mut() {
+            Some(chunk) if chunk.len() < CHUNK_SIZE => chunk.push_front(value),
+            _ => self.chunks.push_front(VecDeque::from([value])),
+        }
+        self.len += 1;
+        self.pack();
+    }
+
+    pub fn push_back(&mut self, value: Bytes) {
+        match self.chunks.back_mut() {
+            Some(chunk) if chunk.len() < CHUNK_SIZE => chunk.push_back(value),
+            _ => self.chunks.push_back(VecDeque::from([value])),
+        }
+        self.len += 1;
+        self.pack();
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let chunk = self.chunks.front_mut()?;
+        let value = chunk.pop_front();
+        if chunk.is_empty() {
+            self.chunks.pop_front();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        let chunk = self.chunks.back_mut()?;
+        let value = chunk.pop_back();
+        if chunk.is_empty() {
+            self.chunks.pop_back();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Bytes> {
+        let (chunk, offset) = self.locate(index)?;
+        self.chunks[chunk].get(offset)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Bytes> {
+        let (chunk, offset) = self.locate(index)?;
+        self.chunks[chunk].get_mut(offset)
+    }
+
+    // insert puts value at index, a full chunk is split in two halves first.
+    pub fn insert(&mut self, index: usize, value: Bytes) {
+        assert!(index <= self.len, "index out of bounds");
+        if index == self.len {
+            return self.push_back(value);
+        }
+        let (mut chunk, mut offset) = self.locate(index).unwrap();
+        if self.chunks[chunk].len() >= CHUNK_SIZE {
+            let tail = self.chunks[chunk].split_off(CHUNK_SIZE / 2);
+            self.chunks.insert(chunk + 1, tail);
+            if offset >= CHUNK_SIZE / 2 {
+                chunk += 1;
+                offset -= CHUNK_SIZE / 2;
+            }
+        }
+        self.chunks[chunk].insert(offset, value);
+        self.len += 1;
+        self.pack();
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Bytes> {
+        let (chunk, offset) = self.locate(index)?;
+        let value = self.chunks[chunk].remove(offset);
+        self.len -= 1;
+        self.compact(chunk);
+        self.pack();
+        value
+    }
+
+    // retain keeps the elements f returns true for, visiting them from head to tail.
+    pub fn retain(&mut self, mut f: impl FnMut(&Bytes) -> bool) {
+        self.chunks
+            .iter_mut()
+            .for_each(|chunk| chunk.retain(&mut f));
+        self.chunks.retain(|chunk| !chunk.is_empty());
+        self.len = self.chunks.iter().map(|chunk| chunk.len()).sum();
+        // merge the chunks left undersized, as compact does after a remove
+        let mut chunk = 1;
+        while chunk < self.chunks.len() {
+            if fits(&self.chunks[chunk - 1], &self.chunks[chunk]) {
+                let mut current = self.chunks.remove(chunk).unwrap();
+                self.chunks[chunk - 1].append(&mut current);
+            } else {
+                chunk += 1;
+            }
+        }
+        self.pack();
+    }
+
+    // truncate keeps the first len elements.
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            let excess = self.len - len;
+            let chunk = self.chunks.back_mut().unwrap();
+            if chunk.len() <= excess {
+                self.len -= chunk.len();
+                self.chunks.pop_back();
+            } else {
+                chunk.truncate(chunk.len() - excess);
+                self.len = len;
+            }
+        }
+    }
+
+    // truncate_front removes the first n elements.
+    pub fn truncate_front(&mut self, n: usize) {
+        let len = self.len.saturating_sub(n);
+        while self.len > len {
+            let excess = self.len - len;
+            let chunk = self.chunks.front_mut().unwrap();
+            if chunk.len() <= excess {
+                self.len -= chunk.len();
+                self.chunks.pop_front();
+            } else {
+                chunk.drain(..excess);
+                self.len = len;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Bytes> + '_ {
+        self.chunks.iter().flatten()
+    }
+
+    // iter_from iterates from the element at index to the tail.
+    pub fn iter_from(&self, index: usize) -> impl Iterator<Item = &Bytes> + '_ {
+        let (chunk, offset) = self.locate(index).unwrap_or((self.chunks.len(), 0));
+        self.chunks
+            .range(chunk..)
+            .enumerate()
+            .flat_map(move |(i, c)| c.range(if i == 0 { offset } else { 0 }..))
+    }
+
+    // locate returns the chunk and the offset in it of the element at index, walking
+    // from the nearest end of the list.
+    fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        if index >= self.len {
+            return None;
+        }
+        if index < self.len / 2 {
+            let mut remaining = index;
+            for (i, chunk) in self.chunks.iter().enumerate() {
+                if remaining < chunk.len() {
+                    return Some((i, remaining));
+                }
+                remaining -= chunk.len();
+            }
+        } else {
+            let mut remaining = self.len - index;
+            for (i, chunk) in self.chunks.iter().enumerate().rev() {
+                if remaining <= chunk.len() {
+                    return Some((i, chunk.len() - remaining));
+                }
+                remaining -= chunk.len();
+            }
+        }
+        None
+    }
+
+    // compact drops the chunk if it is empty, or merges it with a neighbour when both
+    // together fill no more than half a chunk.
+    fn compact(&mut self, chunk: usize) {
+        if self.chunks[chunk].is_empty() {
+            self.chunks.remove(chunk);
+            return;
+        }
+        if chunk + 1 < self.chunks.len() && fits(&self.chunks[chunk], &self.chunks[chunk + 1]) {
+            let mut next = self.chunks.remove(chunk + 1).unwrap();
+            self.chunks[chunk].append(&mut next);
+        } else if chunk > 0 && fits(&self.chunks[chunk - 1], &self.chunks[chunk]) {
+            let mut current = self.chunks.remove(chunk).unwrap();
+            self.chunks[chunk - 1].append(&mut current);
+        }
+    }
+
+    // pack copies the elements of a small list into one allocation, so that a list of a
+    // few short elements costs a single buffer instead of one per element.
+    fn pack(&mut self) {
+        if self.chunks.len() != 1 {
+            return;
+        }
+        let chunk = &mut self.chunks[0];
+        let size = chunk.iter().map(|value| value.len()).sum::<usize>();
+        if size > PACKED_SIZE {
+            return;
+        }
+        let mut buf = BytesMut::with_capacity(size);
+        chunk.iter().for_each(|value| buf.extend_from_slice(value));
+        let buf = buf.freeze();
+        let mut at = 0;
+        for value in chunk.iter_mut() {
+            *value = buf.slice(at..at + value.len());
+            at += value.len();
+        }
+    }
+}
+
+// fits reports whether two neighbour chunks together fill no more than half a chunk.
+fn fits(a: &VecDeque<Bytes>, b: &VecDeque<Bytes>) -> bool {
+    a.len() + b.len() <= CHUNK_SIZE / 2
+}
+
+impl Index<usize> for QuickList {
+    type Output = Bytes;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(range: std::ops::Range<usize>) -> Vec<Bytes> {
+        range.map(|i| Bytes::from(format!("value{}", i))).collect()
+    }
+
+    fn quicklist(values: &[Bytes]) -> QuickList {
+        let mut list = QuickList::new();
+        values
+            .iter()
+            .for_each(|value| list.push_back(value.clone()));
+        list
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut list = QuickList::new();
+        let values = values(0..CHUNK_SIZE * 2 + 1);
+        for value in values.iter().rev() {
+            list.push_front(value.clone());
+        }
+        assert_eq!(list.len(), values.len());
+        assert_eq!(list.chunks.len(), 3);
+        assert_eq!(list.pop_front(), Some(values[0].clone()));
+        assert_eq!(list.pop_back(), Some(values[values.len() - 1].clone()));
+        list.push_back(Bytes::from("tail"));
+        assert_eq!(list[list.len() - 1], Bytes::from("tail"));
+        while list.pop_back().is_some() {}
+        assert!(list.is_empty());
+        assert!(list.chunks.is_empty());
+    }
+
+    #[test]
+    fn test_get() {
+        let values = values(0..CHUNK_SIZE * 3);
+        let mut list = quicklist(&values);
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(list.get(i), Some(value));
+        }
+        assert_eq!(list.get(values.len()), None);
+        *list.get_mut(CHUNK_SIZE + 1).unwrap() = Bytes::from("value");
+        assert_eq!(list[CHUNK_SIZE + 1], Bytes::from("value"));
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut values = values(0..CHUNK_SIZE);
+        let mut list = quicklist(&values);
+        // the single chunk is full, inserting splits it
+        list.insert(CHUNK_SIZE - 1, Bytes::from("value"));
+        values.insert(CHUNK_SIZE - 1, Bytes::from("value"));
+        assert_eq!(list.chunks.len(), 2);
+        list.insert(0, Bytes::from("head"));
+        values.insert(0, Bytes::from("head"));
+        list.insert(list.len(), Bytes::from("tail"));
+        values.push(Bytes::from("tail"));
+        assert_eq!(list.iter().cloned().collect::<Vec<Bytes>>(), values);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut values = values(0..CHUNK_SIZE + 1);
+        let mut list = quicklist(&values);
+        assert_eq!(list.remove(values.len()), None);
+        // the two chunks merge once they fit in half a chunk
+        for _ in 0..CHUNK_SIZE / 2 + 1 {
+            assert_eq!(list.remove(1), Some(values.remove(1)));
+        }
+        assert_eq!(list.len(), values.len());
+        assert_eq!(list.chunks.len(), 1);
+        assert_eq!(list.iter().cloned().collect::<Vec<Bytes>>(), values);
+    }
+
+    #[test]
+    fn test_retain() {
+        let values = values(0..CHUNK_SIZE * 2);
+        let mut list = quicklist(&values);
+        list.retain(|value| value.len() == 6);
+        assert_eq!(list.len(), 10);
+        assert_eq!(list.chunks.len(), 1);
+        assert_eq!(list[9], Bytes::from("value9"));
+
+        // elements kept in both chunks end up merged in one
+        let mut list = quicklist(&values);
+        let mut i = 0;
+        list.retain(|_| {
+            i += 1;
+            i % 8 == 0
+        });
+        assert_eq!(list.len(), CHUNK_SIZE / 4);
+        assert_eq!(list.chunks.len(), 1);
+        assert_eq!(list[CHUNK_SIZE / 4 - 1], values[CHUNK_SIZE * 2 - 1]);
+    }
+
+    #[test]
+    fn test_pack() {
+        let values = values(0..CHUNK_SIZE);
+        let mut list = quicklist(&values[..3]);
+        list.push_front(Bytes::from("head"));
+        // the elements of a small list share one buffer
+        let chunk = &list.chunks[0];
+        for i in 1..chunk.len() {
+            assert_eq!(chunk[i].as_ptr(), unsafe { chunk[i - 1].as_ptr().add(chunk[i - 1].len()) });
+        }
+        assert_eq!(list[0], Bytes::from("head"));
+        assert_eq!(list[3], values[2]);
+
+        // large elements are left in their own allocations
+        let big = Bytes::from(vec![1u8; PACKED_SIZE + 1]);
+        let mut list = quicklist(&values[..1]);
+        list.push_back(big.clone());
+        assert_eq!(list[1].as_ptr(), big.as_ptr());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let values = values(0..CHUNK_SIZE * 3);
+        let mut list = quicklist(&values);
+        list.truncate(CHUNK_SIZE * 2 - 1);
+        list.truncate_front(CHUNK_SIZE + 1);
+        assert_eq!(list.len(), CHUNK_SIZE - 2);
+        assert_eq!(
+            list.iter().cloned().collect::<Vec<Bytes>>(),
+            values[CHUNK_SIZE + 1..CHUNK_SIZE * 2 - 1].to_vec()
+        );
+        list.truncate_front(CHUNK_SIZE);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let values = values(0..CHUNK_SIZE * 2);
+        let list = quicklist(&values);
+        let result: Vec<Bytes> = list.iter_from(CHUNK_SIZE - 1).take(3).cloned().collect();
+        assert_eq!(result, values[CHUNK_SIZE - 1..CHUNK_SIZE + 2].to_vec());
+        assert_eq!(list.iter_from(values.len()).count(), 0);
+        assert_eq!(list.iter().next_back(), values.last());
+    }
+}
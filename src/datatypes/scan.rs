@@ -0,0 +1,173 @@
+// scan walks items in key order, the cursor is the key to resume from. the items are
+// given from the cursor on, e.g. by a range of an ordered map, so a call only visits the
+// items it returns or skips and an item present during the whole scan is returned exactly
+// once whatever is written between the calls.
+//
+// at most count items are visited per call, the ones that do not match pattern are
+// skipped, so a call may return no item with a cursor to resume from. the returned cursor
+// is the key of the first item left to visit, None when the scan is complete. it is never
+// the empty key, which is the first of all, so callers use an empty cursor to start a scan
+// and to tell that it is complete.
+pub fn scan<'a, T>(
+    items: impl Iterator<Item = (&'a [u8], T)>,
+    pattern: Option<&str>,
+    count: usize,
+) -> (Option<&'a [u8]>, Vec<T>) {
+    let mut items = items.peekable();
+    let res = items
+        .by_ref()
+        .take(count.max(1))
+        .filter(|(key, _)| pattern.is_none_or(|pattern| glob_match(pattern.as_bytes(), key)))
+        .map(|(_, item)| item)
+        .collect();
+    (items.peek().map(|(key, _)| *key), res)
+}
+
+// glob_match matches s against a glob style pattern: * any bytes, ? one byte, [abc], [^abc]
+// and [a-z] a byte in or out of the class, \x the byte x.
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // where to resume after the last star: pattern position and next byte of s it takes
+    let mut backtrack = None;
+    while i < s.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            backtrack = Some((p, i));
+            continue;
+        }
+        if let Some(n) = match_one(&pattern[p..], s[i]) {
+            p += n;
+            i += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star_p, star_i)) => {
+                p = star_p;
+                i = star_i + 1;
+                backtrack = Some((star_p, star_i + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+// match_one returns the length of the pattern element at the start of pattern if it
+// matches c.
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match *pattern.first()? {
+        b'?' => Some(1),
+        b'\\' if pattern.len() > 1 => (pattern[1] == c).then_some(2),
+        b'[' => match_class(pattern, c),
+        p => (p == c).then_some(1),
+    }
+}
+
+// match_class matches c against the class at the start of pattern, an unterminated class
+// is a literal '['.
+fn match_class(pattern: &[u8], c: u8) -> Option<usize> {
+    let negate = pattern.get(1) == Some(&b'^');
+    let mut i = if negate { 2 } else { 1 };
+    let mut matched = false;
+    loop {
+        match pattern.get(i) {
+            None => return (c == b'[').then_some(1),
+            Some(b']') => break,
+            Some(b'\\') if i + 1 < pattern.len() => {
+                matched |= pattern[i + 1] == c;
+                i += 2;
+            }
+            Some(&lo)
+                if pattern.get(i + 1) == Some(&b'-')
+                    && i + 2 < pattern.len()
+                    && pattern[i + 2] != b']' =>
+            {
+                let hi = pattern[i + 2];
+                matched |= (lo.min(hi)..=lo.max(hi)).contains(&c);
+                i += 3;
+            }
+            Some(&x) => {
+                matched |= x == c;
+                i += 1;
+            }
+        }
+    }
+    (matched != negate).then_some(i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeSet, HashSet};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"key*", b"key1"));
+        assert!(!glob_match(b"key*", b"akey1"));
+        assert!(glob_match(b"*:id:*", b"user:id:42"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-f]llo", b"hello"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+        assert!(glob_match(b"[", b"["));
+        assert!(glob_match(b"a*b*c", b"aXbYbZc"));
+        assert!(!glob_match(b"a*b*c", b"aXbYbZ"));
+    }
+
+    #[test]
+    fn test_scan() {
+        let keys: BTreeSet<String> = (0..100).map(|i| format!("key{}", i)).collect();
+        let mut seen = HashSet::new();
+        let mut cursor = String::new();
+        let mut calls = 0;
+        loop {
+            let items = keys
+                .range(cursor.clone()..)
+                .map(|key| (key.as_bytes(), key.clone()));
+            let (next, res) = scan(items, None, 10);
+            assert!(res.len() <= 10);
+            res.into_iter().for_each(|key| assert!(seen.insert(key)));
+            calls += 1;
+            match next {
+                Some(next) => cursor = String::from_utf8(next.to_vec()).unwrap(),
+                None => break,
+            }
+        }
+        assert_eq!(seen.len(), keys.len());
+        assert_eq!(calls, 10);
+
+        let items = keys.iter().map(|key| (key.as_bytes(), key.clone()));
+        let (next, res) = scan(items, Some("key1?"), 1000);
+        assert_eq!(next, None);
+        assert_eq!(res.len(), 10);
+    }
+
+    #[test]
+    fn test_scan_with_writes() {
+        let mut keys: BTreeSet<String> = (0..50).map(|i| format!("key{}", i)).collect();
+        let mut seen = HashSet::new();
+        let mut cursor = String::new();
+        let mut i = 0;
+        loop {
+            let items = keys
+                .range(cursor.clone()..)
+                .map(|key| (key.as_bytes(), key.clone()));
+            let (next, res) = scan(items, None, 7);
+            let next = next.map(|next| String::from_utf8(next.to_vec()).unwrap());
+            res.into_iter().for_each(|key| assert!(seen.insert(key)));
+            // keys 0..25 stay, the others are replaced by new ones while scanning
+            keys.insert(format!("new{}", i));
+            keys.remove(&format!("key{}", 25 + i));
+            i += 1;
+            match next {
+                Some(next) => cursor = next,
+                None => break,
+            }
+        }
+        assert!((0..25).all(|i| seen.contains(&format!("key{}", i))));
+    }
+}
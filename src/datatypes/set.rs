@@ -1,10 +1,16 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
 
 use bytes::Bytes;
+use rand::seq::{IteratorRandom, SliceRandom};
 
+use super::scan;
+
+// Set compares members by their value only, every member keeps the data it was added
+// with (e.g. the encoded entry) alongside, and the read methods return that data.
 #[derive(Debug, Default)]
 pub struct Set {
-    items: HashMap<String, HashSet<Bytes>>,
+    items: HashMap<String, BTreeMap<Bytes, Bytes>>,
 }
 
 impl Set {
@@ -14,14 +20,18 @@ impl Set {
         }
     }
 
-    pub fn sadd(&mut self, key: &str, members: Vec<Bytes>) -> Option<usize> {
-        self.items.entry(key.to_string()).or_default();
-        let num = members.len();
-        members.into_iter().for_each(|member| {
-            self.items.get_mut(key).unwrap().insert(member);
+    // sadd adds (member, data) pairs and returns the number of new members, the data of
+    // an existing member is replaced.
+    pub fn sadd(&mut self, key: &str, members: Vec<(Bytes, Bytes)>) -> Option<usize> {
+        let items = self.items.entry(key.to_string()).or_default();
+        let mut added = 0;
+        members.into_iter().for_each(|(member, data)| {
+            if items.insert(member, data).is_none() {
+                added += 1;
+            }
         });
 
-        Some(num)
+        Some(added)
     }
 
     pub fn scard(&self, key: &str) -> Option<usize> {
@@ -32,70 +42,26 @@ impl Set {
     }
 
     pub fn sdiff(&self, key: &str, keys: Vec<&str>) -> Option<Vec<Bytes>> {
-        let mut res = vec![];
-        let default_set = HashSet::new();
-        let mut diff = self.items.get(key).unwrap_or(&default_set).clone();
-        keys.into_iter().for_each(|set_name| {
-            let other_set = self.items.get(set_name).unwrap_or(&default_set);
-            diff = diff.difference(other_set).cloned().collect();
-        });
-
-        for d in diff.into_iter() {
-            res.push(d.clone());
-        }
-
-        Some(res)
+        Some(self.diff(key, keys).into_values().collect())
     }
 
     pub fn sinter(&self, key: &str, keys: Vec<&str>) -> Option<Vec<Bytes>> {
-        if !self.items.contains_key(key) {
-            return None;
-        }
-        let mut res = vec![];
-        let mut intersection = self.items.get(key).unwrap().clone();
-        let default_set = HashSet::<Bytes>::new();
-
-        keys.into_iter().for_each(|set_name| {
-            if intersection.is_empty() {
-                return;
-            }
-            let other_set = self.items.get(set_name).unwrap_or(&default_set);
-            intersection = intersection.intersection(other_set).cloned().collect();
-        });
-
-        for inter in intersection.into_iter() {
-            res.push(inter.clone());
-        }
-
-        Some(res)
+        Some(self.inter(key, keys)?.into_values().collect())
     }
 
     pub fn suion(&self, key: &str, keys: Vec<&str>) -> Option<Vec<Bytes>> {
-        let mut res = vec![];
-        let mut union = self.items.get(key).unwrap().clone();
-        let default_set = HashSet::new();
-
-        keys.into_iter().for_each(|set_name| {
-            let other_set = self.items.get(set_name).unwrap_or(&default_set);
-            union = union.union(other_set).cloned().collect();
-        });
-
-        for u in union.into_iter() {
-            res.push(u.clone());
-        }
-
-        Some(res)
+        Some(self.union(key, keys).into_values().collect())
     }
 
     pub fn sismember(&self, key: &str, member: Bytes) -> Option<bool> {
-        Some(self.items.contains_key(key) && self.items.get(key).unwrap().contains(&member))
+        Some(self.items.contains_key(key) && self.items.get(key).unwrap().contains_key(&member))
     }
 
     pub fn smembers(&self, key: &str) -> Option<Vec<Bytes>> {
         if !self.items.contains_key(key) {
             return None;
         }
-        let res: Vec<Bytes> = self.items.get(key).unwrap().iter().cloned().collect();
+        let res: Vec<Bytes> = self.items.get(key).unwrap().values().cloned().collect();
 
         Some(res)
     }
@@ -107,19 +73,178 @@ impl Set {
         let mut removed: usize = 0;
         let items = self.items.get_mut(key).unwrap();
         members.into_iter().for_each(|member| {
-            if (*items).remove(&member) {
+            if (*items).remove(&member).is_some() {
                 removed += 1;
             }
         });
 
         Some(removed)
     }
+
+    // spop removes count random members and returns their data.
+    pub fn spop(&mut self, key: &str, count: usize) -> Option<Vec<Bytes>> {
+        let items = self.items.get_mut(key)?;
+        let members: Vec<Bytes> = items
+            .keys()
+            .cloned()
+            .choose_multiple(&mut rand::thread_rng(), count);
+        let res = members
+            .iter()
+            .filter_map(|member| items.remove(member))
+            .collect();
+        Some(res)
+    }
+
+    // srandmember returns count distinct random members when count is positive, or -count
+    // members which may repeat when count is negative.
+    pub fn srandmember(&self, key: &str, count: isize) -> Option<Vec<Bytes>> {
+        let items = self.items.get(key)?;
+        let mut rng = rand::thread_rng();
+        if count >= 0 {
+            return Some(
+                items
+                    .values()
+                    .cloned()
+                    .choose_multiple(&mut rng, count as usize),
+            );
+        }
+
+        let members: Vec<&Bytes> = items.values().collect();
+        let res = (0..count.unsigned_abs())
+            .filter_map(|_| members.choose(&mut rng).map(|member| (*member).clone()))
+            .collect();
+        Some(res)
+    }
+
+    pub fn smove(&mut self, source: &str, destination: &str, member: &[u8]) -> Option<usize> {
+        let removed = self
+            .items
+            .get_mut(source)
+            .and_then(|items| items.remove_entry(member));
+        let (member, data) = match removed {
+            Some(removed) => removed,
+            None => return Some(0),
+        };
+        self.items
+            .entry(destination.to_string())
+            .or_default()
+            .insert(member, data);
+        Some(1)
+    }
+
+    pub fn sdiffstore(&mut self, destination: &str, key: &str, keys: Vec<&str>) -> Option<usize> {
+        let members = self.diff(key, keys);
+        self.store(destination, members)
+    }
+
+    pub fn sinterstore(&mut self, destination: &str, key: &str, keys: Vec<&str>) -> Option<usize> {
+        let members = self.inter(key, keys).unwrap_or_default();
+        self.store(destination, members)
+    }
+
+    pub fn suionstore(&mut self, destination: &str, key: &str, keys: Vec<&str>) -> Option<usize> {
+        let members = self.union(key, keys);
+        self.store(destination, members)
+    }
+
+    // sscan iterates the members of key in member order from cursor and returns their data
+    // with the member to resume from, empty to start and once complete, see scan::scan.
+    pub fn sscan(
+        &self,
+        key: &str,
+        cursor: &[u8],
+        pattern: Option<&str>,
+        count: usize,
+    ) -> Option<(Bytes, Vec<Bytes>)> {
+        let items = self.items.get(key)?;
+        let items = items
+            .range::<[u8], _>((Bound::Included(cursor), Bound::Unbounded))
+            .map(|(member, data)| (member.as_ref(), data.clone()));
+        let (next, data) = scan::scan(items, pattern, count);
+        Some((next.map(Bytes::copy_from_slice).unwrap_or_default(), data))
+    }
+
+    pub fn members(&self, key: &str) -> Option<&BTreeMap<Bytes, Bytes>> {
+        self.items.get(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<BTreeMap<Bytes, Bytes>> {
+        self.items.remove(key)
+    }
+
+    pub fn insert(&mut self, key: &str, members: BTreeMap<Bytes, Bytes>) {
+        self.items.insert(key.to_string(), members);
+    }
+
+    // put_back sets the data of member back to what it was, none removes the member.
+    pub fn put_back(&mut self, key: &str, member: Bytes, data: Option<Bytes>) {
+        let items = self.items.entry(key.to_string()).or_default();
+        match data {
+            Some(data) => items.insert(member, data),
+            None => items.remove(&member),
+        };
+    }
+
+    // keys returns the keys that have members.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.items
+            .iter()
+            .filter(|(_, items)| !items.is_empty())
+            .map(|(key, _)| key)
+    }
+
+    fn diff(&self, key: &str, keys: Vec<&str>) -> BTreeMap<Bytes, Bytes> {
+        let mut diff = self.items.get(key).cloned().unwrap_or_default();
+        keys.into_iter().for_each(|set_name| {
+            if let Some(other_set) = self.items.get(set_name) {
+                diff.retain(|member, _| !other_set.contains_key(member));
+            }
+        });
+        diff
+    }
+
+    fn inter(&self, key: &str, keys: Vec<&str>) -> Option<BTreeMap<Bytes, Bytes>> {
+        let mut intersection = self.items.get(key)?.clone();
+        keys.into_iter()
+            .for_each(|set_name| match self.items.get(set_name) {
+                Some(other_set) => intersection.retain(|member, _| other_set.contains_key(member)),
+                None => intersection.clear(),
+            });
+        Some(intersection)
+    }
+
+    // union keeps the data of the first set a member is found in.
+    fn union(&self, key: &str, keys: Vec<&str>) -> BTreeMap<Bytes, Bytes> {
+        let mut union = self.items.get(key).cloned().unwrap_or_default();
+        keys.into_iter().for_each(|set_name| {
+            if let Some(other_set) = self.items.get(set_name) {
+                other_set.iter().for_each(|(member, data)| {
+                    union.entry(member.clone()).or_insert_with(|| data.clone());
+                });
+            }
+        });
+        union
+    }
+
+    fn store(&mut self, destination: &str, members: BTreeMap<Bytes, Bytes>) -> Option<usize> {
+        let num = members.len();
+        self.items.insert(destination.to_string(), members);
+        Some(num)
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use std::collections::HashSet;
+
+    fn with_data(members: &[Bytes]) -> Vec<(Bytes, Bytes)> {
+        members
+            .iter()
+            .map(|member| (member.clone(), member.clone()))
+            .collect()
+    }
 
     #[test]
     fn test_sadd() {
@@ -129,12 +254,12 @@ mod tests {
             Bytes::from("member2"),
             Bytes::from("member3"),
         ];
-        let result = set.sadd("key1", members.clone());
+        let result = set.sadd("key1", with_data(&members));
         assert_eq!(result, Some(members.len()));
         let set_items = set.items.get("key1").unwrap();
         assert_eq!(set_items.len(), members.len());
         for member in members.iter() {
-            assert!(set_items.contains(member));
+            assert!(set_items.contains_key(member));
         }
     }
 
@@ -148,7 +273,7 @@ mod tests {
         ];
         let result = set.scard("key1");
         assert_eq!(result, None);
-        set.sadd("key1", members.clone());
+        set.sadd("key1", with_data(&members));
         let result = set.scard("key1");
         assert_eq!(result, Some(members.len()));
     }
@@ -172,9 +297,9 @@ mod tests {
             Bytes::from("member2"),
             Bytes::from("member6"),
         ];
-        set.sadd("key1", members1.clone());
-        set.sadd("key2", members2.clone());
-        set.sadd("key3", members3.clone());
+        set.sadd("key1", with_data(&members1));
+        set.sadd("key2", with_data(&members2));
+        set.sadd("key3", with_data(&members3));
         let result = set.sdiff("key1", vec!["key2", "key3"]);
         assert_eq!(result, Some(vec![members1[2].clone()]));
     }
@@ -198,9 +323,9 @@ mod tests {
             Bytes::from("member2"),
             Bytes::from("member6"),
         ];
-        set.sadd("key1", members1.clone());
-        set.sadd("key2", members2.clone());
-        set.sadd("key3", members3.clone());
+        set.sadd("key1", with_data(&members1));
+        set.sadd("key2", with_data(&members2));
+        set.sadd("key3", with_data(&members3));
         let result = set.sinter("key1", vec!["key2", "key3"]);
         assert_eq!(result, Some(vec![members1[1].clone()]));
     }
@@ -218,8 +343,8 @@ mod tests {
             Bytes::from("member4"),
             Bytes::from("member5"),
         ];
-        set.sadd("key1", members1.clone());
-        set.sadd("key2", members2.clone());
+        set.sadd("key1", with_data(&members1));
+        set.sadd("key2", with_data(&members2));
         let result = set.suion("key1", vec!["key2"]);
         assert!(result
             .unwrap()
@@ -235,7 +360,7 @@ mod tests {
             Bytes::from("member2"),
             Bytes::from("member3"),
         ];
-        set.sadd("key1", members.clone());
+        set.sadd("key1", with_data(&members));
         let result = set.sismember("key2", Bytes::from("member1"));
         assert_eq!(result, Some(false));
         let result = set.sismember("key1", Bytes::from("member1"));
@@ -252,7 +377,7 @@ mod tests {
         ];
         let result = set.smembers("key1");
         assert_eq!(result, None);
-        set.sadd("key1", members.clone());
+        set.sadd("key1", with_data(&members));
         let result = set.smembers("key1");
         assert!(result.unwrap().iter().all(|item| members.contains(item)));
     }
@@ -267,7 +392,7 @@ mod tests {
         ];
         let result = set.srem("key1", members.clone());
         assert_eq!(result, Some(0));
-        set.sadd("key1", members.clone());
+        set.sadd("key1", with_data(&members));
         let removed_members = vec![Bytes::from("member1"), Bytes::from("member3")];
         let result = set.srem("key1", removed_members.clone());
         assert_eq!(result, Some(2));
@@ -275,10 +400,143 @@ mod tests {
         assert_eq!(set_items.len(), members.len() - removed_members.len());
         for member in members.iter() {
             if removed_members.contains(member) {
-                assert!(!set_items.contains(member));
+                assert!(!set_items.contains_key(member));
             } else {
-                assert!(set_items.contains(member));
+                assert!(set_items.contains_key(member));
+            }
+        }
+    }
+
+    #[test]
+    fn test_spop() {
+        let mut set = Set::new();
+        let members = vec![
+            Bytes::from("member1"),
+            Bytes::from("member2"),
+            Bytes::from("member3"),
+        ];
+        let result = set.spop("key1", 1);
+        assert_eq!(result, None);
+        set.sadd("key1", with_data(&members));
+        let result = set.spop("key1", 2).unwrap();
+        assert_eq!(result.len(), 2);
+        let set_items = set.items.get("key1").unwrap();
+        assert_eq!(set_items.len(), 1);
+        for member in result.iter() {
+            assert!(members.contains(member));
+            assert!(!set_items.contains_key(member));
+        }
+        let result = set.spop("key1", 5).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(set.scard("key1"), Some(0));
+    }
+
+    #[test]
+    fn test_srandmember() {
+        let mut set = Set::new();
+        let members = vec![
+            Bytes::from("member1"),
+            Bytes::from("member2"),
+            Bytes::from("member3"),
+        ];
+        let result = set.srandmember("key1", 1);
+        assert_eq!(result, None);
+        set.sadd("key1", with_data(&members));
+
+        let result = set.srandmember("key1", 5).unwrap();
+        assert_eq!(result.len(), members.len());
+        let distinct: HashSet<Bytes> = result.iter().cloned().collect();
+        assert_eq!(distinct.len(), members.len());
+
+        let result = set.srandmember("key1", -10).unwrap();
+        assert_eq!(result.len(), 10);
+        assert!(result.iter().all(|item| members.contains(item)));
+        assert_eq!(set.scard("key1"), Some(members.len()));
+    }
+
+    #[test]
+    fn test_smove() {
+        let mut set = Set::new();
+        let members = vec![Bytes::from("member1"), Bytes::from("member2")];
+        set.sadd("key1", with_data(&members));
+        let result = set.smove("key1", "key2", &Bytes::from("member3"));
+        assert_eq!(result, Some(0));
+        let result = set.smove("key1", "key2", &members[0]);
+        assert_eq!(result, Some(1));
+        assert_eq!(set.sismember("key1", members[0].clone()), Some(false));
+        assert_eq!(set.sismember("key2", members[0].clone()), Some(true));
+    }
+
+    #[test]
+    fn test_store() {
+        let mut set = Set::new();
+        let members1 = vec![
+            Bytes::from("member1"),
+            Bytes::from("member2"),
+            Bytes::from("member3"),
+        ];
+        let members2 = vec![Bytes::from("member2"), Bytes::from("member4")];
+        set.sadd("key1", with_data(&members1));
+        set.sadd("key2", with_data(&members2));
+        set.sadd("dest", with_data(&[Bytes::from("old")]));
+
+        assert_eq!(set.sinterstore("dest", "key1", vec!["key2"]), Some(1));
+        assert_eq!(set.smembers("dest"), Some(vec![members1[1].clone()]));
+        assert_eq!(set.suionstore("dest", "key1", vec!["key2"]), Some(4));
+        assert_eq!(set.scard("dest"), Some(4));
+        assert_eq!(set.sdiffstore("dest", "key1", vec!["key2"]), Some(2));
+        assert_eq!(set.sismember("dest", Bytes::from("member2")), Some(false));
+        assert_eq!(set.sinterstore("dest", "key3", vec!["key2"]), Some(0));
+        assert_eq!(set.scard("dest"), Some(0));
+    }
+
+    #[test]
+    fn test_member_data() {
+        let mut set = Set::new();
+        let members = vec![
+            (Bytes::from("member1"), Bytes::from("data1")),
+            (Bytes::from("member2"), Bytes::from("data2")),
+        ];
+        assert_eq!(set.sadd("key1", members), Some(2));
+        // the same member with new data is not added twice
+        let result = set.sadd("key1", vec![(Bytes::from("member1"), Bytes::from("data3"))]);
+        assert_eq!(result, Some(0));
+        assert_eq!(set.scard("key1"), Some(2));
+        assert_eq!(set.sismember("key1", Bytes::from("member1")), Some(true));
+        assert_eq!(set.sismember("key1", Bytes::from("data3")), Some(false));
+
+        let mut result = set.smembers("key1").unwrap();
+        result.sort();
+        assert_eq!(result, vec![Bytes::from("data2"), Bytes::from("data3")]);
+        assert_eq!(set.smove("key1", "key2", b"member2"), Some(1));
+        assert_eq!(set.smembers("key2"), Some(vec![Bytes::from("data2")]));
+        assert_eq!(set.srem("key1", vec![Bytes::from("member1")]), Some(1));
+        assert_eq!(set.scard("key1"), Some(0));
+    }
+
+    #[test]
+    fn test_sscan() {
+        let mut set = Set::new();
+        assert_eq!(set.sscan("key1", b"", None, 10), None);
+        let members: Vec<Bytes> = (0..30)
+            .map(|i| Bytes::from(format!("member{}", i)))
+            .collect();
+        set.sadd("key1", with_data(&members));
+        let mut result = HashSet::new();
+        let mut cursor = Bytes::new();
+        let mut calls = 0;
+        loop {
+            let (next, data) = set.sscan("key1", &cursor, None, 8).unwrap();
+            result.extend(data);
+            calls += 1;
+            if next.is_empty() {
+                break;
             }
+            cursor = next;
         }
+        assert_eq!(result, members.into_iter().collect());
+        assert_eq!(calls, 4);
+        let (_, data) = set.sscan("key1", b"", Some("member2?"), 100).unwrap();
+        assert_eq!(data.len(), 10);
     }
 }
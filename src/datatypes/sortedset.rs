@@ -1,8 +1,12 @@
 use bytes::Bytes;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::Rng;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{BTreeMap, HashMap}, ops::Bound, sync::Arc};
 use atomic_refcell::AtomicRefCell;
 
+use super::scan;
+use crate::enums::ZAggregate;
+
 const SKIPLISTMAXLEVEL: usize = 32;
 const SKIPLISTP: f64 = 0.25;
 
@@ -17,25 +21,26 @@ pub struct SortedSetLevel {
 
 #[derive(Default, Clone, Debug)]
 pub struct SortedSetNode {
-    pub key: String,
+    pub key: Bytes,
     pub value: Bytes,
     pub score: Score,
     backward: Option<ArcNode>,
     level: Vec<SortedSetLevel>,
 }
 
-#[derive(Default, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct SortedSet {
     header: ArcNode,
     tail: Option<ArcNode>,
     length: usize,
     level: usize,
-    dict: HashMap<String, ArcNode>,
+    // the nodes by key, in key order for scan
+    dict: BTreeMap<Bytes, ArcNode>,
 }
 
-fn new_sortedset_node(level: usize, score: Score, key: &str, value: Bytes) -> ArcNode {
+fn new_sortedset_node(level: usize, score: Score, key: &[u8], value: Bytes) -> ArcNode {
     let node = SortedSetNode {
-        key: key.to_string(),
+        key: Bytes::copy_from_slice(key),
         value,
         score,
         backward: None,
@@ -54,39 +59,98 @@ fn random_level() -> usize {
     level
 }
 
+fn weighted_score(score: Score, weight: Score) -> Score {
+    let weighted = score * weight;
+    // inf * 0 is nan, treat it as 0 like redis does
+    if weighted.is_nan() {
+        0.0
+    } else {
+        weighted
+    }
+}
+
+fn aggregate_score(a: Score, b: Score, aggregate: ZAggregate) -> Score {
+    match aggregate {
+        ZAggregate::Sum => {
+            let sum = a + b;
+            // +inf + -inf is nan
+            if sum.is_nan() {
+                0.0
+            } else {
+                sum
+            }
+        }
+        ZAggregate::Min => a.min(b),
+        ZAggregate::Max => a.max(b),
+    }
+}
+
+fn lex_gte_min(key: &[u8], min: Bound<&[u8]>) -> bool {
+    match min {
+        Bound::Included(min) => key >= min,
+        Bound::Excluded(min) => key > min,
+        Bound::Unbounded => true,
+    }
+}
+
+fn lex_lte_max(key: &[u8], max: Bound<&[u8]>) -> bool {
+    match max {
+        Bound::Included(max) => key <= max,
+        Bound::Excluded(max) => key < max,
+        Bound::Unbounded => true,
+    }
+}
+
+fn lex_range_valid(min: Bound<&[u8]>, max: Bound<&[u8]>) -> bool {
+    match (min, max) {
+        (Bound::Included(min), Bound::Included(max)) => min <= max,
+        (Bound::Included(min), Bound::Excluded(max))
+        | (Bound::Excluded(min), Bound::Included(max))
+        | (Bound::Excluded(min), Bound::Excluded(max)) => min < max,
+        _ => true,
+    }
+}
+
+// a sorted set created by default has the header of SortedSet::new, a default node has no level.
+impl Default for SortedSet {
+    fn default() -> Self {
+        SortedSet::new()
+    }
+}
+
 impl SortedSet {
     pub fn new() -> SortedSet {
-        let header = new_sortedset_node(SKIPLISTMAXLEVEL, 0.0, "", Bytes::default());
+        let header = new_sortedset_node(SKIPLISTMAXLEVEL, 0.0, b"", Bytes::default());
         SortedSet {
             header,
             tail: None,
             length: 0,
             level: 1,
-            dict: HashMap::new(),
+            dict: BTreeMap::new(),
         }
     }
 
-    pub fn put(&mut self, key: &str, value: Bytes, score: Score) -> usize {
-        let mut need_del = false;
+    pub fn put(&mut self, key: &[u8], value: Bytes, score: Score) -> usize {
+        let mut old_score = None;
         if let Some(item) = self.dict.get(key) {
             let mut item_mut = item.borrow_mut();
             if item_mut.score == score {
                 item_mut.value = value;
                 return 1;
             }
-            need_del = true;
+            old_score = Some(item_mut.score);
         }
 
-        if need_del {
-            self.delete_node(key, score);
+        if let Some(old_score) = old_score {
+            self.delete_node(key, old_score);
         }
         let new_node = self.insert_sortedset_node(key, value, score);
-        self.dict.insert(key.into(), new_node);
+        self.dict.insert(Bytes::copy_from_slice(key), new_node);
 
         1
     }
 
-    pub fn remove(&mut self, key: &str) -> Option<ArcNode> {
+    pub fn remove(&mut self, key: &[u8]) -> Option<ArcNode> {
         let mut need_del = false;
         let mut res = None;
         let mut found_score = 0.0;
@@ -178,11 +242,11 @@ impl SortedSet {
         }
     }
 
-    pub fn get_by_key(&self, key: &str) -> Option<ArcNode> {
+    pub fn get_by_key(&self, key: &[u8]) -> Option<ArcNode> {
         self.dict.get(key).map(Arc::clone)
     }
 
-    pub fn find_rank(&self, key: &str) -> Option<usize> {
+    pub fn find_rank(&self, key: &[u8]) -> Option<usize> {
         match self.dict.get(key) {
             Some(node) => {
                 let mut rank: usize = 0;
@@ -197,7 +261,7 @@ impl SortedSet {
                             let node_b = node.borrow();
                             if next_node_borrow.score < node_b.score
                                 || (next_node_borrow.score == node_b.score
-                                    && next_node_borrow.key.as_str() <= key)
+                                    && next_node_borrow.key.as_ref() <= key)
                             {
                                 rank += x.borrow().level[i].span;
                             } else {
@@ -219,7 +283,7 @@ impl SortedSet {
         }
     }
 
-    pub fn find_rev_rank(&self, key: &str) -> Option<usize> {
+    pub fn find_rev_rank(&self, key: &[u8]) -> Option<usize> {
         self.find_rank(key).map(|rank| self.length() - rank + 1)
     }
 
@@ -249,7 +313,177 @@ impl SortedSet {
         }
     }
 
-    fn insert_sortedset_node(&mut self, key: &str, value: Bytes, score: Score) -> ArcNode {
+    pub fn score(&self, key: &[u8]) -> Option<Score> {
+        self.dict.get(key).map(|node| node.borrow().score)
+    }
+
+    // incr_by adds increment to the score of key, value builds the value of the node from
+    // the new score.
+    pub fn incr_by(&mut self, key: &[u8], increment: Score, value: impl FnOnce(Score) -> Bytes) -> Score {
+        let score = self.score(key).unwrap_or(0.0) + increment;
+        self.put(key, value(score), score);
+        score
+    }
+
+    // union merges the sets into a new one, every score is multiplied by the weight of its
+    // set (1 if missing) before being aggregated. the value is taken from the first set.
+    pub fn union(sets: &[&SortedSet], weights: &[Score], aggregate: ZAggregate) -> SortedSet {
+        let mut merged: HashMap<Bytes, (Bytes, Score)> = HashMap::new();
+        for (i, set) in sets.iter().enumerate() {
+            let weight = weights.get(i).copied().unwrap_or(1.0);
+            for (key, node) in set.dict.iter() {
+                let node_b = node.borrow();
+                let score = weighted_score(node_b.score, weight);
+                merged
+                    .entry(key.clone())
+                    .and_modify(|(_, s)| *s = aggregate_score(*s, score, aggregate))
+                    .or_insert((node_b.value.clone(), score));
+            }
+        }
+
+        let mut res = SortedSet::new();
+        for (key, (value, score)) in merged {
+            res.put(&key, value, score);
+        }
+        res
+    }
+
+    // inter keeps the keys present in every set, scores are weighted and aggregated like union.
+    pub fn inter(sets: &[&SortedSet], weights: &[Score], aggregate: ZAggregate) -> SortedSet {
+        let mut res = SortedSet::new();
+        let Some(smallest) = sets.iter().min_by_key(|set| set.length()) else {
+            return res;
+        };
+
+        for key in smallest.dict.keys() {
+            let mut value = Bytes::new();
+            let mut score: Option<Score> = None;
+            for (i, set) in sets.iter().enumerate() {
+                let Some(node) = set.dict.get(key) else {
+                    score = None;
+                    break;
+                };
+                let node_b = node.borrow();
+                let weight = weights.get(i).copied().unwrap_or(1.0);
+                let weighted = weighted_score(node_b.score, weight);
+                score = match score {
+                    Some(s) => Some(aggregate_score(s, weighted, aggregate)),
+                    None => {
+                        value = node_b.value.clone();
+                        Some(weighted)
+                    }
+                };
+            }
+            if let Some(score) = score {
+                res.put(key, value, score);
+            }
+        }
+        res
+    }
+
+    // get_by_lex_range returns the nodes whose key is between min and max, it assumes
+    // every node has the same score so that they are ordered by key.
+    pub fn get_by_lex_range(
+        &self,
+        min: Bound<&[u8]>,
+        max: Bound<&[u8]>,
+        limit: usize,
+    ) -> Vec<ArcNode> {
+        let mut res: Vec<ArcNode> = vec![];
+        if self.length() == 0 || limit == 0 || !lex_range_valid(min, max) {
+            return res;
+        }
+
+        let mut x = Arc::clone(&self.header);
+        for i in (0..self.level).rev() {
+            let mut next_node: Arc<AtomicRefCell<SortedSetNode>>;
+            loop {
+                if let Some(ref forward) = x.borrow().level[i].forward {
+                    next_node = Arc::clone(forward);
+                    if lex_gte_min(next_node.borrow().key.as_ref(), min) {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+                x = Arc::clone(&next_node);
+            }
+        }
+
+        let mut x = x.borrow().level[0].forward.clone();
+        while let Some(current) = x {
+            let current_b = current.borrow();
+            if res.len() >= limit || !lex_lte_max(current_b.key.as_ref(), max) {
+                break;
+            }
+            res.push(Arc::clone(&current));
+            x = current_b.level[0].forward.clone();
+        }
+
+        res
+    }
+
+    pub fn lex_count(&self, min: Bound<&[u8]>, max: Bound<&[u8]>) -> usize {
+        self.get_by_lex_range(min, max, usize::MAX).len()
+    }
+
+    pub fn remove_by_lex_range(&mut self, min: Bound<&[u8]>, max: Bound<&[u8]>) -> Vec<ArcNode> {
+        let nodes = self.get_by_lex_range(min, max, usize::MAX);
+        for node in &nodes {
+            let (key, score) = {
+                let node_b = node.borrow();
+                (node_b.key.clone(), node_b.score)
+            };
+            self.delete_node(&key, score);
+        }
+        nodes
+    }
+
+    // scan iterates the members in key order from cursor, it returns the key to resume
+    // from, empty to start and once complete, see scan::scan.
+    pub fn scan(&self, cursor: &[u8], pattern: Option<&str>, count: usize) -> (Bytes, Vec<ArcNode>) {
+        let items = self
+            .dict
+            .range::<[u8], _>((Bound::Included(cursor), Bound::Unbounded))
+            .map(|(key, node)| (key.as_ref(), Arc::clone(node)));
+        let (next, nodes) = scan::scan(items, pattern, count);
+        (next.map(Bytes::copy_from_slice).unwrap_or_default(), nodes)
+    }
+
+    // pop_min removes and returns the count lowest nodes, lowest first.
+    pub fn pop_min(&mut self, count: usize) -> Vec<ArcNode> {
+        if count == 0 || self.length == 0 {
+            return vec![];
+        }
+        self.get_by_rank_range(1, count.min(self.length), true)
+    }
+
+    // pop_max removes and returns the count highest nodes, highest first.
+    pub fn pop_max(&mut self, count: usize) -> Vec<ArcNode> {
+        if count == 0 || self.length == 0 {
+            return vec![];
+        }
+        let start = self.length - count.min(self.length) + 1;
+        let mut res = self.get_by_rank_range(start, self.length, true);
+        res.reverse();
+        res
+    }
+
+    // rand_members returns count distinct random nodes when count is positive, or -count
+    // nodes which may repeat when count is negative.
+    pub fn rand_members(&self, count: isize) -> Vec<ArcNode> {
+        let mut rng = rand::thread_rng();
+        if count >= 0 {
+            return self.dict.values().cloned().choose_multiple(&mut rng, count as usize);
+        }
+
+        let nodes: Vec<&ArcNode> = self.dict.values().collect();
+        (0..count.unsigned_abs())
+            .filter_map(|_| nodes.choose(&mut rng).map(|node| Arc::clone(node)))
+            .collect()
+    }
+
+    fn insert_sortedset_node(&mut self, key: &[u8], value: Bytes, score: Score) -> ArcNode {
         let mut rank = vec![0; SKIPLISTMAXLEVEL];
         let mut update: Vec<ArcNode> = vec![self.header.clone(); SKIPLISTMAXLEVEL];
         let mut x = Arc::clone(&self.header);
@@ -261,7 +495,7 @@ impl SortedSet {
                     next_node = Arc::clone(&forward);
                     let next_node_borrow = next_node.borrow();
                     if next_node_borrow.score > score
-                        || (next_node_borrow.score == score && next_node_borrow.key.as_str() >= key)
+                        || (next_node_borrow.score == score && next_node_borrow.key.as_ref() >= key)
                     {
                         break;
                     }
@@ -317,7 +551,7 @@ impl SortedSet {
         Arc::clone(&x)
     }
 
-    fn delete_node(&mut self, key: &str, score: Score) -> Option<bool> {
+    fn delete_node(&mut self, key: &[u8], score: Score) -> Option<bool> {
         let mut update: Vec<ArcNode> = vec![self.header.clone(); SKIPLISTMAXLEVEL];
         let mut x = Arc::clone(&self.header);
         for i in (0..self.level).rev() {
@@ -328,7 +562,7 @@ impl SortedSet {
                     let next_node_borrow = next_node.borrow();
 
                     if next_node_borrow.score > score
-                        || (next_node_borrow.score == score && next_node_borrow.key.as_str() >= key)
+                        || (next_node_borrow.score == score && next_node_borrow.key.as_ref() >= key)
                     {
                         break;
                     }
@@ -363,7 +597,8 @@ impl SortedSet {
             if update_i_mut.level[i].forward.is_some()
                 && Arc::ptr_eq(update_i_mut.level[i].forward.as_ref().unwrap(), &node)
             {
-                update_i_mut.level[i].span += node.borrow().level[i].span - 1;
+                // the last node of a level has a zero span, so add before subtracting
+                update_i_mut.level[i].span = update_i_mut.level[i].span + node.borrow().level[i].span - 1;
                 update_i_mut.level[i].forward = node.borrow().level[i].forward.clone()
             } else {
                 update_i_mut.level[i].span -= 1;
@@ -472,40 +707,43 @@ mod tests {
     use bytes::Bytes;
     use super::SortedSet;
     use super::Score;
+    use super::ArcNode;
+    use crate::enums::ZAggregate;
+    use std::ops::Bound;
 
     #[test]
     fn test_put_remove() {
         let mut sortedset = SortedSet::new();
 
-        sortedset.put("key1", Bytes::from("value1"), 1.0);
-        sortedset.put("key2", Bytes::from("value2"), 2.0);
-        sortedset.put("key3", Bytes::from("value3"), 3.0);
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
         assert_eq!(sortedset.length(), 3);
 
-        assert!(sortedset.dict.get("key1").unwrap().borrow().key == "key1");
-        assert!(sortedset.dict.get("key1").unwrap().borrow().value == "value1");
-        assert!(sortedset.dict.get("key1").unwrap().borrow().score == 1.0);
+        assert!(sortedset.dict.get(&b"key1"[..]).unwrap().borrow().key == "key1");
+        assert!(sortedset.dict.get(&b"key1"[..]).unwrap().borrow().value == "value1");
+        assert!(sortedset.dict.get(&b"key1"[..]).unwrap().borrow().score == 1.0);
 
-        assert!(sortedset.dict.contains_key("key2"));
-        let remove = sortedset.remove("key2");
+        assert!(sortedset.dict.contains_key(&b"key2"[..]));
+        let remove = sortedset.remove(b"key2");
         assert_eq!(remove.as_ref().unwrap().borrow().key, "key2");
         assert_eq!(remove.as_ref().unwrap().borrow().value, "value2");
         assert_eq!(remove.as_ref().unwrap().borrow().score, 2.0);
 
-        assert!(sortedset.remove("key5").is_none());
+        assert!(sortedset.remove(b"key5").is_none());
     }
 
     #[test]
     fn test_get_by_rank_range() {
         let mut sortedset = SortedSet::new();
-        sortedset.put("key1", Bytes::from("value1"), 1.0);
-        sortedset.put("key2", Bytes::from("value2"), 2.0);
-        sortedset.put("key3", Bytes::from("value3"), 3.0);
-        sortedset.put("key4", Bytes::from("value4"), 4.0);
-        sortedset.put("key5", Bytes::from("value5"), 5.0);
-        sortedset.put("key6", Bytes::from("value6"), 6.0);
-        sortedset.put("key0.5", Bytes::from("value1.5"), 0.5);
-        sortedset.put("key0.7", Bytes::from("value0.5"), 0.7);
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        sortedset.put(b"key4", Bytes::from("value4"), 4.0);
+        sortedset.put(b"key5", Bytes::from("value5"), 5.0);
+        sortedset.put(b"key6", Bytes::from("value6"), 6.0);
+        sortedset.put(b"key0.5", Bytes::from("value1.5"), 0.5);
+        sortedset.put(b"key0.7", Bytes::from("value0.5"), 0.7);
         let nodes = sortedset.get_by_rank_range(2, 5, false);
         assert_eq!(nodes.len(), 4);
         assert_eq!(nodes[0].borrow().key, "key0.7");
@@ -515,7 +753,7 @@ mod tests {
         let iters = 1000;
         for i in 0..iters {
             sortedset.put(
-                format!("key{}", i).as_str(),
+                format!("key{}", i).as_bytes(),
                 Bytes::from(format!("value{}", i)),
                 i as f64,
             );
@@ -531,11 +769,11 @@ mod tests {
     #[test]
     fn test_get_by_rank() {
         let mut sortedset = SortedSet::new();
-        sortedset.put("key1", Bytes::from("value1"), 1.0);
-        sortedset.put("key2", Bytes::from("value2"), 2.0);
-        sortedset.put("key3", Bytes::from("value3"), 3.0);
-        sortedset.put("key0.5", Bytes::from("value0.5"), 0.5);
-        sortedset.put("key0.7", Bytes::from("value0.7"), 0.7);
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        sortedset.put(b"key0.5", Bytes::from("value0.5"), 0.5);
+        sortedset.put(b"key0.7", Bytes::from("value0.7"), 0.7);
 
         let node = sortedset.get_by_rank(2, false);
         assert!(node.is_some());
@@ -545,7 +783,7 @@ mod tests {
         assert_eq!(node.as_ref().unwrap().borrow().key, "key1");
         assert_eq!(node.as_ref().unwrap().borrow().value, "value1");
         assert_eq!(node.as_ref().unwrap().borrow().score, 1.0);
-        assert!(sortedset.dict.get("key1").is_none());
+        assert!(sortedset.dict.get(&b"key1"[..]).is_none());
         let node = sortedset.get_by_rank(3, false);
         assert!(node.is_some());
         assert_eq!(node.as_ref().unwrap().borrow().key, "key2");
@@ -558,10 +796,10 @@ mod tests {
     #[test]
     fn test_get_by_key() {
         let mut sortedset = SortedSet::new();
-        sortedset.put("key1", Bytes::from("value1"), 1.0);
-        sortedset.put("key2", Bytes::from("value2"), 2.0);
-        sortedset.put("key3", Bytes::from("value3"), 3.0);
-        let node = sortedset.get_by_key("key2");
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        let node = sortedset.get_by_key(b"key2");
         assert!(node.is_some());
         assert_eq!(node.unwrap().borrow().score, 2.0);
     }
@@ -569,40 +807,227 @@ mod tests {
     #[test]
     fn test_find_rank() {
         let mut sortedset = SortedSet::new();
-        sortedset.put("key1", Bytes::from("value1"), 1.0);
-        sortedset.put("key2", Bytes::from("value2"), 2.0);
-        sortedset.put("key3", Bytes::from("value3"), 3.0);
-        let rank = sortedset.find_rank("key2");
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        let rank = sortedset.find_rank(b"key2");
         assert!(rank.is_some());
         assert_eq!(rank.unwrap(), 2);
-        let rank = sortedset.find_rank("key5");
+        let rank = sortedset.find_rank(b"key5");
         assert!(rank.is_none());
     }
 
     #[test]
     fn test_find_rev_rank() {
         let mut sortedset = SortedSet::new();
-        sortedset.put("key1", Bytes::from("value1"), 1.0);
-        sortedset.put("key2", Bytes::from("value2"), 2.0);
-        sortedset.put("key3", Bytes::from("value3"), 3.0);
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
 
-        let rev_rank = sortedset.find_rev_rank("key2");
+        let rev_rank = sortedset.find_rev_rank(b"key2");
         assert!(rev_rank.is_some());
         assert_eq!(rev_rank.unwrap(), 2);
-        let rank = sortedset.find_rev_rank("key5");
+        let rank = sortedset.find_rev_rank(b"key5");
         assert!(rank.is_none());
-        let rev_rank = sortedset.find_rev_rank("key3");
+        let rev_rank = sortedset.find_rev_rank(b"key3");
         assert!(rev_rank.is_some());
         assert_eq!(rev_rank.unwrap(), 1);
     }
 
+    #[test]
+    fn test_incr_by() {
+        let mut sortedset = SortedSet::new();
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        assert_eq!(sortedset.incr_by(b"key1", 5.0, |score| Bytes::from(score.to_string())), 6.0);
+        assert_eq!(sortedset.incr_by(b"key3", 1.5, |_| Bytes::from("value3")), 1.5);
+        assert_eq!(sortedset.length(), 3);
+        assert_eq!(sortedset.find_rank(b"key1"), Some(3));
+        assert_eq!(sortedset.score(b"key3"), Some(1.5));
+        assert_eq!(sortedset.get_by_key(b"key1").unwrap().borrow().value, Bytes::from("6"));
+    }
+
+    #[test]
+    fn test_union() {
+        let mut set1 = SortedSet::new();
+        set1.put(b"key1", Bytes::from("value1"), 1.0);
+        set1.put(b"key2", Bytes::from("value2"), 2.0);
+        let mut set2 = SortedSet::new();
+        set2.put(b"key2", Bytes::from("value2"), 3.0);
+        set2.put(b"key3", Bytes::from("value3"), 4.0);
+
+        let union = SortedSet::union(&[&set1, &set2], &[2.0, 1.0], ZAggregate::Sum);
+        assert_eq!(union.length(), 3);
+        assert_eq!(union.score(b"key1"), Some(2.0));
+        assert_eq!(union.score(b"key2"), Some(7.0));
+        assert_eq!(union.score(b"key3"), Some(4.0));
+
+        let union = SortedSet::union(&[&set1, &set2], &[], ZAggregate::Min);
+        assert_eq!(union.score(b"key2"), Some(2.0));
+        let union = SortedSet::union(&[&set1, &set2], &[], ZAggregate::Max);
+        assert_eq!(union.score(b"key2"), Some(3.0));
+        let nodes = union.get_by_score_range(0.0, 10.0, 10, false, false);
+        assert_eq!(nodes[0].borrow().key, "key1");
+        assert_eq!(nodes[2].borrow().key, "key3");
+    }
+
+    #[test]
+    fn test_inter() {
+        let mut set1 = SortedSet::new();
+        set1.put(b"key1", Bytes::from("value1"), 1.0);
+        set1.put(b"key2", Bytes::from("value2"), 2.0);
+        set1.put(b"key3", Bytes::from("value3"), 3.0);
+        let mut set2 = SortedSet::new();
+        set2.put(b"key2", Bytes::from("value2"), 3.0);
+        set2.put(b"key3", Bytes::from("value3"), 4.0);
+        set2.put(b"key4", Bytes::from("value4"), 5.0);
+
+        let inter = SortedSet::inter(&[&set1, &set2], &[1.0, 10.0], ZAggregate::Sum);
+        assert_eq!(inter.length(), 2);
+        assert_eq!(inter.score(b"key2"), Some(32.0));
+        assert_eq!(inter.score(b"key3"), Some(43.0));
+        assert!(inter.get_by_key(b"key1").is_none());
+
+        let inter = SortedSet::inter(&[&set1, &set2], &[], ZAggregate::Max);
+        assert_eq!(inter.score(b"key2"), Some(3.0));
+        let inter = SortedSet::inter(&[&set1, &SortedSet::new()], &[], ZAggregate::Max);
+        assert_eq!(inter.length(), 0);
+    }
+
+    #[test]
+    fn test_get_by_lex_range() {
+        let mut sortedset = SortedSet::new();
+        for key in ["e", "a", "d", "b", "c", "f", "g"] {
+            sortedset.put(key.as_bytes(), Bytes::from(key), 0.0);
+        }
+        let keys = |nodes: Vec<ArcNode>| -> Vec<Bytes> {
+            nodes.iter().map(|node| node.borrow().key.clone()).collect()
+        };
+
+        let nodes = sortedset.get_by_lex_range(Bound::Unbounded, Bound::Included("c".as_bytes()), 10);
+        assert_eq!(keys(nodes), vec!["a", "b", "c"]);
+        let nodes = sortedset.get_by_lex_range(Bound::Unbounded, Bound::Excluded("c".as_bytes()), 10);
+        assert_eq!(keys(nodes), vec!["a", "b"]);
+        let nodes = sortedset.get_by_lex_range(Bound::Excluded("aaa".as_bytes()), Bound::Excluded("g".as_bytes()), 10);
+        assert_eq!(keys(nodes), vec!["b", "c", "d", "e", "f"]);
+        let nodes = sortedset.get_by_lex_range(Bound::Included("b".as_bytes()), Bound::Unbounded, 2);
+        assert_eq!(keys(nodes), vec!["b", "c"]);
+        let nodes = sortedset.get_by_lex_range(Bound::Included("d".as_bytes()), Bound::Included("b".as_bytes()), 10);
+        assert!(nodes.is_empty());
+        let nodes = sortedset.get_by_lex_range(Bound::Excluded("b".as_bytes()), Bound::Excluded("b".as_bytes()), 10);
+        assert!(nodes.is_empty());
+    }
+
+    #[test]
+    fn test_lex_count() {
+        let mut sortedset = SortedSet::new();
+        for key in ["alpha", "alps", "beta", "bet", "gamma"] {
+            sortedset.put(key.as_bytes(), Bytes::from(key), 0.0);
+        }
+        assert_eq!(sortedset.lex_count(Bound::Unbounded, Bound::Unbounded), 5);
+        assert_eq!(
+            sortedset.lex_count(Bound::Included("al".as_bytes()), Bound::Excluded("am".as_bytes())),
+            2
+        );
+        assert_eq!(
+            sortedset.lex_count(Bound::Included("bet".as_bytes()), Bound::Included("bet\u{ff}".as_bytes())),
+            2
+        );
+    }
+
+    #[test]
+    fn test_remove_by_lex_range() {
+        let mut sortedset = SortedSet::new();
+        for key in ["a", "b", "c", "d", "e"] {
+            sortedset.put(key.as_bytes(), Bytes::from(key), 0.0);
+        }
+        let removed = sortedset.remove_by_lex_range(Bound::Excluded("a".as_bytes()), Bound::Included("c".as_bytes()));
+        assert_eq!(removed.len(), 2);
+        assert_eq!(sortedset.length(), 3);
+        assert!(sortedset.get_by_key(b"b").is_none());
+        assert!(sortedset.get_by_key(b"c").is_none());
+        assert_eq!(sortedset.find_rank(b"d"), Some(2));
+        let nodes = sortedset.get_by_rank_range(1, 3, false);
+        assert_eq!(nodes[2].borrow().key, "e");
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut sortedset = SortedSet::new();
+        for i in 0..20 {
+            sortedset.put(format!("key{}", i).as_bytes(), Bytes::from("value"), i as Score);
+        }
+        let mut keys = vec![];
+        let mut cursor = Bytes::new();
+        loop {
+            let (next, nodes) = sortedset.scan(&cursor, Some("key1*"), 5);
+            keys.extend(nodes.iter().map(|node| node.borrow().key.clone()));
+            if next.is_empty() {
+                break;
+            }
+            cursor = next;
+        }
+        keys.sort();
+        assert_eq!(keys.len(), 11);
+        assert_eq!(keys[0], "key1");
+    }
+
+    #[test]
+    fn test_pop_min() {
+        let mut sortedset = SortedSet::new();
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        assert!(sortedset.pop_min(0).is_empty());
+        let nodes = sortedset.pop_min(2);
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].borrow().key, "key1");
+        assert_eq!(nodes[1].borrow().key, "key2");
+        assert_eq!(sortedset.length(), 1);
+        assert!(sortedset.get_by_key(b"key1").is_none());
+        assert_eq!(sortedset.pop_min(5).len(), 1);
+        assert!(sortedset.pop_min(1).is_empty());
+    }
+
+    #[test]
+    fn test_pop_max() {
+        let mut sortedset = SortedSet::new();
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        let nodes = sortedset.pop_max(2);
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].borrow().key, "key3");
+        assert_eq!(nodes[1].borrow().key, "key2");
+        assert_eq!(sortedset.length(), 1);
+        assert_eq!(sortedset.find_rank(b"key1"), Some(1));
+        assert_eq!(sortedset.pop_max(5)[0].borrow().key, "key1");
+        assert!(sortedset.pop_max(1).is_empty());
+    }
+
+    #[test]
+    fn test_rand_members() {
+        let mut sortedset = SortedSet::new();
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        let nodes = sortedset.rand_members(5);
+        assert_eq!(nodes.len(), 3);
+        let mut keys: Vec<Bytes> = nodes.iter().map(|node| node.borrow().key.clone()).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(sortedset.rand_members(-5).len(), 5);
+        assert_eq!(SortedSet::new().rand_members(-5).len(), 0);
+    }
+
     #[test]
     fn test_get_by_score_range() {
         let mut sortedset = SortedSet::new();
-        sortedset.put("key1", Bytes::from("value1"), 1.0);
-        sortedset.put("key2", Bytes::from("value2"), 2.0);
-        sortedset.put("key3", Bytes::from("value3"), 3.0);
-        sortedset.put("key4", Bytes::from("value4"), 4.0);
+        sortedset.put(b"key1", Bytes::from("value1"), 1.0);
+        sortedset.put(b"key2", Bytes::from("value2"), 2.0);
+        sortedset.put(b"key3", Bytes::from("value3"), 3.0);
+        sortedset.put(b"key4", Bytes::from("value4"), 4.0);
         let nodes = sortedset.get_by_score_range(1.0, 3.0, 2, false, false);
         assert_eq!(nodes.len(), 2);
         assert_eq!(nodes[0].borrow().key, "key1");
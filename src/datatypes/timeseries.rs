@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::enums::TsAggregation;
+
+// CompactionRule downsamples a series into dest, one sample per bucket_ms bucket.
+#[derive(Debug, Clone)]
+pub struct CompactionRule {
+    pub dest: String,
+    pub aggregation: TsAggregation,
+    pub bucket_ms: i64,
+    // start of the bucket the last sample was added to, the bucket is written to dest
+    // once a sample of a later bucket arrives.
+    current_bucket: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Series {
+    samples: BTreeMap<i64, f64>,
+    // samples older than the latest one by more than retention_ms are dropped, 0 keeps all
+    retention_ms: i64,
+    rules: Vec<CompactionRule>,
+}
+
+impl Series {
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+// Added is what add_sample changed in a series, see TimeSeries::undo.
+#[derive(Debug)]
+pub struct Added {
+    key: String,
+    created: bool,
+    timestamp: i64,
+    // the sample replaced at timestamp
+    replaced: Option<f64>,
+    // the current bucket of each rule of the series before the sample
+    buckets: Vec<Option<i64>>,
+}
+
+impl Added {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TimeSeries {
+    items: HashMap<String, Series>,
+}
+
+impl TimeSeries {
+    pub fn new() -> Self {
+        TimeSeries {
+            items: HashMap::new(),
+        }
+    }
+
+    // create creates key, or updates its retention if it exists.
+    pub fn create(&mut self, key: &str, retention_ms: i64) {
+        self.items.entry(key.to_string()).or_default().retention_ms = retention_ms;
+    }
+
+    // add adds a sample, replacing the one at the same timestamp, the key is created if
+    // needed. closed buckets of the compaction rules are written to their dest.
+    pub fn add(&mut self, key: &str, timestamp: i64, value: f64) -> i64 {
+        self.add_sample(key, timestamp, value, &mut vec![]);
+        timestamp
+    }
+
+    // add_sample adds a sample like add, recording in added what it changes in key and in
+    // the dest keys of its rules so that undo can take it back.
+    pub fn add_sample(&mut self, key: &str, timestamp: i64, value: f64, added: &mut Vec<Added>) {
+        let created = !self.items.contains_key(key);
+        let series = self.items.entry(key.to_string()).or_default();
+        added.push(Added {
+            key: key.to_owned(),
+            created,
+            timestamp,
+            replaced: series.samples.insert(timestamp, value),
+            buckets: series
+                .rules
+                .iter()
+                .map(|rule| rule.current_bucket)
+                .collect(),
+        });
+
+        let mut compacted = vec![];
+        for rule in series.rules.iter_mut() {
+            let bucket = bucket_start(timestamp, rule.bucket_ms);
+            match rule.current_bucket {
+                Some(current) if bucket > current => {
+                    let values = series
+                        .samples
+                        .range(current..current + rule.bucket_ms)
+                        .map(|(_, value)| *value);
+                    if let Some(aggregated) = aggregate(rule.aggregation, values) {
+                        compacted.push((rule.dest.clone(), current, aggregated));
+                    }
+                    rule.current_bucket = Some(bucket);
+                }
+                None => rule.current_bucket = Some(bucket),
+                _ => {}
+            }
+        }
+        for (dest, timestamp, value) in compacted {
+            self.add_sample(&dest, timestamp, value, added);
+        }
+    }
+
+    // undo takes back the samples recorded by add_sample, latest first.
+    pub fn undo(&mut self, added: Vec<Added>) {
+        for added in added.into_iter().rev() {
+            if added.created {
+                self.items.remove(&added.key);
+                continue;
+            }
+            let Some(series) = self.items.get_mut(&added.key) else {
+                continue;
+            };
+            match added.replaced {
+                Some(value) => series.samples.insert(added.timestamp, value),
+                None => series.samples.remove(&added.timestamp),
+            };
+            for (rule, bucket) in series.rules.iter_mut().zip(added.buckets) {
+                rule.current_bucket = bucket;
+            }
+        }
+    }
+
+    // range returns the samples between from and to (both inclusive), aggregated per
+    // bucket_ms bucket when aggregation is given.
+    pub fn range(
+        &self,
+        key: &str,
+        from: i64,
+        to: i64,
+        aggregation: Option<(TsAggregation, i64)>,
+    ) -> Option<Vec<(i64, f64)>> {
+        let series = self.items.get(key)?;
+        if from > to {
+            return Some(vec![]);
+        }
+        let samples = series.samples.range(from..=to);
+        let Some((aggregation, bucket_ms)) = aggregation else {
+            return Some(
+                samples
+                    .map(|(timestamp, value)| (*timestamp, *value))
+                    .collect(),
+            );
+        };
+
+        let mut buckets: Vec<(i64, Vec<f64>)> = vec![];
+        for (timestamp, value) in samples {
+            let bucket = bucket_start(*timestamp, bucket_ms);
+            match buckets.last_mut() {
+                Some((start, values)) if *start == bucket => values.push(*value),
+                _ => buckets.push((bucket, vec![*value])),
+            }
+        }
+        let res = buckets
+            .into_iter()
+            .filter_map(|(start, values)| {
+                aggregate(aggregation, values.into_iter()).map(|value| (start, value))
+            })
+            .collect();
+        Some(res)
+    }
+
+    // create_rule adds a compaction rule from source to dest, dest is created if needed.
+    // returns none if source does not exist or is dest.
+    pub fn create_rule(
+        &mut self,
+        source: &str,
+        dest: &str,
+        aggregation: TsAggregation,
+        bucket_ms: i64,
+    ) -> Option<usize> {
+        if source == dest || bucket_ms <= 0 || !self.items.contains_key(source) {
+            return None;
+        }
+        self.items.entry(dest.to_string()).or_default();
+        let series = self.items.get_mut(source)?;
+        series.rules.retain(|rule| rule.dest != dest);
+        series.rules.push(CompactionRule {
+            dest: dest.to_string(),
+            aggregation,
+            bucket_ms,
+            current_bucket: None,
+        });
+        Some(series.rules.len())
+    }
+
+    // rules_to returns the compaction rules writing to key, with their source key and their
+    // index among the rules of the source.
+    pub fn rules_to(&self, key: &str) -> Vec<(String, usize, CompactionRule)> {
+        let mut rules = vec![];
+        for (source, series) in self.items.iter() {
+            for (index, rule) in series.rules.iter().enumerate() {
+                if rule.dest == key {
+                    rules.push((source.clone(), index, rule.clone()));
+                }
+            }
+        }
+        rules
+    }
+
+    // put_rules puts back the rules returned by rules_to after remove dropped them.
+    pub fn put_rules(&mut self, rules: Vec<(String, usize, CompactionRule)>) {
+        for (source, index, rule) in rules {
+            if let Some(series) = self.items.get_mut(&source) {
+                series.rules.insert(index.min(series.rules.len()), rule);
+            }
+        }
+    }
+
+    // expired returns the keys with samples out of their retention, with the timestamp the
+    // samples before are out of it.
+    pub fn expired(&self) -> Vec<(String, i64)> {
+        self.items
+            .iter()
+            .filter(|(_, series)| series.retention_ms > 0)
+            .filter_map(|(key, series)| {
+                let oldest = series.samples.keys().next()?;
+                let before = series.samples.keys().next_back()? - series.retention_ms;
+                (*oldest < before).then(|| (key.clone(), before))
+            })
+            .collect()
+    }
+
+    // trim drops the samples of key before the given timestamp, returns the number of dropped
+    // samples.
+    pub fn trim(&mut self, key: &str, before: i64) -> usize {
+        let Some(series) = self.items.get_mut(key) else {
+            return 0;
+        };
+        let kept = series.samples.split_off(&before);
+        let dropped = series.samples.len();
+        series.samples = kept;
+        dropped
+    }
+
+    // apply_retention drops the samples out of the retention of every series, returns the
+    // number of dropped samples.
+    pub fn apply_retention(&mut self) -> usize {
+        self.expired()
+            .into_iter()
+            .map(|(key, before)| self.trim(&key, before))
+            .sum()
+    }
+
+    pub fn len(&self, key: &str) -> Option<usize> {
+        Some(self.items.get(key)?.samples.len())
+    }
+
+    // remove takes key out, with the compaction rules writing to it.
+    pub fn remove(&mut self, key: &str) -> Option<Series> {
+        self.items
+            .values_mut()
+            .for_each(|series| series.rules.retain(|rule| rule.dest != key));
+        self.items.remove(key)
+    }
+
+    pub fn insert(&mut self, key: &str, series: Series) {
+        self.items.insert(key.to_string(), series);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Series> {
+        self.items.get(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.items.keys()
+    }
+}
+
+fn bucket_start(timestamp: i64, bucket_ms: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(bucket_ms)
+}
+
+fn aggregate(aggregation: TsAggregation, values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut count = 0;
+    let mut res: Option<f64> = None;
+    for value in values {
+        count += 1;
+        res = Some(match (aggregation, res) {
+            (_, None) => value,
+            (TsAggregation::Avg | TsAggregation::Sum, Some(sum)) => sum + value,
+            (TsAggregation::Min, Some(min)) => min.min(value),
+            (TsAggregation::Max, Some(max)) => max.max(value),
+            (TsAggregation::Count, Some(_)) => 0.0,
+        });
+    }
+    match aggregation {
+        TsAggregation::Avg => res.map(|sum| sum / count as f64),
+        TsAggregation::Count => res.map(|_| count as f64),
+        _ => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        let mut ts = TimeSeries::new();
+        assert_eq!(ts.add("key1", 10, 1.0), 10);
+        ts.add("key1", 5, 2.0);
+        ts.add("key1", 10, 3.0);
+        assert_eq!(ts.len("key1"), Some(2));
+        assert_eq!(
+            ts.range("key1", 0, 100, None),
+            Some(vec![(5, 2.0), (10, 3.0)])
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        let mut ts = TimeSeries::new();
+        assert_eq!(ts.range("key1", 0, 10, None), None);
+        for (timestamp, value) in [(0, 1.0), (3, 5.0), (9, 3.0), (10, 4.0), (25, 2.0)] {
+            ts.add("key1", timestamp, value);
+        }
+        assert_eq!(
+            ts.range("key1", 3, 10, None),
+            Some(vec![(3, 5.0), (9, 3.0), (10, 4.0)])
+        );
+        let range = |aggregation| ts.range("key1", 0, 30, Some((aggregation, 10))).unwrap();
+        assert_eq!(
+            range(TsAggregation::Avg),
+            vec![(0, 3.0), (10, 4.0), (20, 2.0)]
+        );
+        assert_eq!(
+            range(TsAggregation::Min),
+            vec![(0, 1.0), (10, 4.0), (20, 2.0)]
+        );
+        assert_eq!(
+            range(TsAggregation::Max),
+            vec![(0, 5.0), (10, 4.0), (20, 2.0)]
+        );
+        assert_eq!(
+            range(TsAggregation::Sum),
+            vec![(0, 9.0), (10, 4.0), (20, 2.0)]
+        );
+        assert_eq!(
+            range(TsAggregation::Count),
+            vec![(0, 3.0), (10, 1.0), (20, 1.0)]
+        );
+        assert_eq!(ts.range("key1", 10, 0, None), Some(vec![]));
+    }
+
+    #[test]
+    fn test_create_rule() {
+        let mut ts = TimeSeries::new();
+        assert_eq!(ts.create_rule("key1", "key2", TsAggregation::Max, 10), None);
+        ts.create("key1", 0);
+        assert_eq!(ts.create_rule("key1", "key1", TsAggregation::Max, 10), None);
+        assert_eq!(
+            ts.create_rule("key1", "key2", TsAggregation::Max, 10),
+            Some(1)
+        );
+        assert_eq!(
+            ts.create_rule("key1", "key3", TsAggregation::Sum, 20),
+            Some(2)
+        );
+        for (timestamp, value) in [(1, 1.0), (5, 7.0), (12, 2.0), (21, 3.0), (45, 1.0)] {
+            ts.add("key1", timestamp, value);
+        }
+        // the bucket of the last sample is still open
+        assert_eq!(
+            ts.range("key2", 0, 100, None),
+            Some(vec![(0, 7.0), (10, 2.0), (20, 3.0)])
+        );
+        assert_eq!(
+            ts.range("key3", 0, 100, None),
+            Some(vec![(0, 10.0), (20, 3.0)])
+        );
+        // removing a dest drops the rules writing to it
+        assert!(ts.remove("key2").is_some());
+        ts.add("key1", 60, 1.0);
+        assert_eq!(ts.len("key2"), None);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut ts = TimeSeries::new();
+        ts.create("key1", 0);
+        ts.create_rule("key1", "key2", TsAggregation::Sum, 10);
+        ts.add("key1", 1, 1.0);
+        ts.add("key1", 5, 2.0);
+
+        // the sample closes the first bucket, which creates key2
+        let mut added = vec![];
+        ts.add_sample("key1", 5, 4.0, &mut added);
+        ts.add_sample("key1", 12, 3.0, &mut added);
+        assert_eq!(ts.range("key2", 0, 100, None), Some(vec![(0, 5.0)]));
+        ts.undo(added);
+        assert_eq!(ts.len("key2"), Some(0));
+        assert_eq!(
+            ts.range("key1", 0, 100, None),
+            Some(vec![(1, 1.0), (5, 2.0)])
+        );
+        ts.add("key1", 12, 3.0);
+        assert_eq!(ts.range("key2", 0, 100, None), Some(vec![(0, 3.0)]));
+
+        let rules = ts.rules_to("key2");
+        assert_eq!(rules.len(), 1);
+        ts.remove("key2");
+        assert!(ts.rules_to("key2").is_empty());
+        ts.put_rules(rules);
+        ts.add("key1", 25, 1.0);
+        assert_eq!(ts.range("key2", 0, 100, None), Some(vec![(10, 3.0)]));
+    }
+
+    #[test]
+    fn test_apply_retention() {
+        let mut ts = TimeSeries::new();
+        ts.create("key1", 10);
+        for timestamp in [0, 5, 10, 15, 20] {
+            ts.add("key1", timestamp, 1.0);
+            ts.add("key2", timestamp, 1.0);
+        }
+        assert_eq!(ts.apply_retention(), 2);
+        assert_eq!(ts.range("key1", 0, 100, None).unwrap()[0].0, 10);
+        assert_eq!(ts.len("key2"), Some(5));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+
+use crate::{
+    data::entry::Entry,
+    enums::{self, EntryOperate},
+    errors::DbError,
+    fileio::{block_cache::BlockCache, FDManager, FileIOManagerObject, FileManager},
+    index::{Index, Record},
+    memtable::FlushedStrings,
+    option,
+};
+
+// DataFiles reads the records flushed to the data files of a db, through the block cache
+// when it is enabled. only the sealed files fill the cache, the blocks of a file still
+// written to would miss the records appended to them. a file is opened once, its writer
+// and its readers share the handle so that the reads see the bytes it buffers.
+pub struct DataFiles {
+    dir: PathBuf,
+    rw_mode: enums::RWMode,
+    file_size_mb: u64,
+    fd_manager: Arc<Mutex<FDManager>>,
+    block_cache: Option<Arc<BlockCache>>,
+    bits_per_key: usize,
+    sealed: RwLock<HashSet<u32>>,
+    // the ids of the data files of the dir
+    file_ids: RwLock<BTreeSet<u32>>,
+    files: RwLock<HashMap<u32, FileIOManagerObject>>,
+}
+
+impl DataFiles {
+    pub fn new(
+        opt: &option::Option,
+        fd_manager: Arc<Mutex<FDManager>>,
+        block_cache: Option<Arc<BlockCache>>,
+    ) -> Self {
+        DataFiles {
+            dir: PathBuf::from(opt.dir()),
+            rw_mode: opt.rw_mode(),
+            file_size_mb: opt.dat_file_size_mb(),
+            fd_manager,
+            block_cache,
+            bits_per_key: opt.bloom_bits_per_key(),
+            sealed: RwLock::default(),
+            file_ids: RwLock::default(),
+            files: RwLock::default(),
+        }
+    }
+
+    pub fn block_cache(&self) -> Option<&Arc<BlockCache>> {
+        self.block_cache.as_ref()
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, file_id: u32) -> PathBuf {
+        self.dir.join(format!("{}.dat", file_id))
+    }
+
+    // open returns the handle of the data file file_id, the file is created if it does not
+    // exist.
+    pub fn open(&self, file_id: u32) -> Result<FileIOManagerObject, DbError> {
+        if let Some(file) = self.files.read().get(&file_id) {
+            return Ok(Arc::clone(file));
+        }
+        let mut files = self.files.write();
+        if let Some(file) = files.get(&file_id) {
+            return Ok(Arc::clone(file));
+        }
+        let mut file_manager = FileManager::new(self.rw_mode.clone(), Arc::clone(&self.fd_manager));
+        let file = file_manager
+            .get_fileio_manager(&self.path(file_id).to_string_lossy(), self.file_size_mb)?;
+        files.insert(file_id, Arc::clone(&file));
+        self.file_ids.write().insert(file_id);
+        Ok(file)
+    }
+
+    // read reads the record pointed at by the hint of record, fill_cache is false for the
+    // compaction and the scans so that they do not evict the blocks of the point reads.
+    pub fn read(&self, record: &Record, fill_cache: bool) -> Result<Record, DbError> {
+        let (file_id, offset) = (record.hint.file_id, record.hint.offset);
+        if !self.files.read().contains_key(&file_id) && !self.path(file_id).exists() {
+            return Err(DbError::IOError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("data file {} not found", file_id),
+            )));
+        }
+        let file = self.open(file_id)?;
+        let file = file.read();
+        let mut buf = vec![0u8; record.size()];
+        let n = match &self.block_cache {
+            Some(block_cache) => {
+                let fill_cache = fill_cache && self.sealed.read().contains(&file_id);
+                block_cache.read(file_id as u64, &**file, &mut buf, offset, fill_cache)?
+            }
+            None => file.read(&mut buf, offset)?,
+        };
+        if n < buf.len() {
+            return Err(DbError::OffsetOutOfRange {
+                method: "read".to_owned(),
+                offset,
+            });
+        }
+        Record::decode(&buf)
+    }
+
+    // filters_dir is where the bloom filters of the keys of bucket in the sealed files are
+    // stored.
+    pub fn filters_dir(&self, bucket: &str) -> PathBuf {
+        self.dir.join("filters").join(bucket)
+    }
+
+    // seal seals the data file file_id once its writer has written len bytes to it and builds
+    // the bloom filters of the keys written to it, by bucket, into their index. the file
+    // shrinks to len if it grew past it, its blocks are then cached.
+    pub fn seal(
+        &self,
+        file_id: u32,
+        len: u64,
+        keys: &HashMap<String, Vec<Bytes>>,
+        indexes: &mut HashMap<String, Index>,
+    ) -> Result<(), DbError> {
+        let file = self.open(file_id)?;
+        let mut file = file.write();
+        file.set_data_len(len);
+        file.seal()?;
+        drop(file);
+        for (bucket, keys) in keys {
+            let dir = self.filters_dir(bucket);
+            fs::create_dir_all(&dir)?;
+            let keys = keys.iter().map(|key| key.as_ref());
+            indexes.entry(bucket.to_owned()).or_default().seal_file(
+                &dir,
+                file_id,
+                keys,
+                self.bits_per_key,
+            )?;
+        }
+        self.sealed.write().insert(file_id);
+        Ok(())
+    }
+
+    // next_file_id returns the id after the last data file of the dir.
+    pub fn next_file_id(&self) -> u32 {
+        self.file_ids.read().last().map_or(0, |file_id| file_id + 1)
+    }
+
+    // may_contain tells if key may be in a data file of the bucket of index, it is false once
+    // the filters of the sealed files rule the key out and no file is still written to.
+    pub fn may_contain(&self, index: &Index, key: &[u8]) -> bool {
+        self.file_ids
+            .read()
+            .iter()
+            .any(|file_id| index.may_contain(*file_id, key))
+    }
+
+    // load lists the data files of the dir and loads the bloom filters of the sealed ones into
+    // the index of their bucket when the db is opened, the files with a filter are sealed.
+    // returns how many filters were loaded.
+    pub fn load(&self, indexes: &mut HashMap<String, Index>) -> Result<usize, DbError> {
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_none_or(|ext| ext != "dat") {
+                continue;
+            }
+            if let Some(file_id) = file_id(&path) {
+                self.file_ids.write().insert(file_id);
+            }
+        }
+        let dir = self.dir.join("filters");
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let mut loaded = 0;
+        for bucket_dir in fs::read_dir(dir)? {
+            let path = bucket_dir?.path();
+            let Some(bucket) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let index = indexes.entry(bucket.to_owned()).or_default();
+            loaded += index.load_filters(&path)?;
+            for file in fs::read_dir(&path)? {
+                if let Some(file_id) = file_id(&file?.path()) {
+                    self.sealed.write().insert(file_id);
+                }
+            }
+        }
+        Ok(loaded)
+    }
+
+    // evict drops the cached blocks of file_id once its records are rewritten.
+    pub fn evict(&self, file_id: u32) {
+        if let Some(block_cache) = &self.block_cache {
+            block_cache.evict_file(file_id as u64);
+        }
+    }
+}
+
+// Flushed looks the string keys flushed to the data files up in the index of their bucket
+// and reads their values through the data files.
+pub struct Flushed {
+    index: Arc<RwLock<HashMap<String, Index>>>,
+    data_files: Arc<DataFiles>,
+}
+
+impl Flushed {
+    pub fn new(index: Arc<RwLock<HashMap<String, Index>>>, data_files: Arc<DataFiles>) -> Self {
+        Flushed { index, data_files }
+    }
+
+    // record runs f on the record of key once the filters of the sealed files did not rule
+    // it out.
+    fn record<T>(&self, bucket: &str, key: &str, f: impl FnOnce(&Record) -> T) -> Option<T> {
+        let indexes = self.index.read();
+        let index = indexes.get(bucket)?;
+        if !self.data_files.may_contain(index, key.as_bytes()) {
+            return None;
+        }
+        index.get(key).map(f)
+    }
+}
+
+impl FlushedStrings for Flushed {
+    fn contains(&self, bucket: &str, key: &str) -> bool {
+        self.record(bucket, key, |record| {
+            let meta = match record.held() {
+                true => &record.entry.meta,
+                false => &record.hint.meta,
+            };
+            !meta.is_expired() && meta.operate != EntryOperate::Del as u16
+        })
+        .unwrap_or(false)
+    }
+
+    // get reads the values not held by the index through the block cache, out of the lock
+    // of the index.
+    fn get(&self, bucket: &str, key: &str) -> Result<Option<Entry>, DbError> {
+        let Some(record) = self.record(bucket, key, Record::clone) else {
+            return Ok(None);
+        };
+        match record.held() {
+            true => Ok(Some(record.entry)),
+            false => Ok(Some(self.data_files.read(&record, true)?.entry)),
+        }
+    }
+}
+
+fn file_id(path: &Path) -> Option<u32> {
+    path.file_stem()?.to_str()?.parse().ok()
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::{
+    data::{entry::Entry, meta::Meta},
+    enums::EntryOperate,
+    errors::DbError,
+    index::{Index, Record},
+    memtable::Memtable,
+};
+
+use super::datafile::DataFiles;
+
+// IterOptions bounds the keys of DB::iter, a prefix narrows the bounds to the keys
+// starting with it.
+#[derive(Debug, Clone)]
+pub struct IterOptions {
+    lower: Bound<String>,
+    upper: Bound<String>,
+    prefix: Option<String>,
+    reverse: bool,
+}
+
+impl Default for IterOptions {
+    fn default() -> Self {
+        IterOptions {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+            prefix: None,
+            reverse: false,
+        }
+    }
+}
+
+impl IterOptions {
+    pub fn with_lower_bound(&mut self, key: &str, inclusive: bool) -> Self {
+        self.lower = match inclusive {
+            true => Bound::Included(key.to_owned()),
+            false => Bound::Excluded(key.to_owned()),
+        };
+        self.to_owned()
+    }
+
+    pub fn with_upper_bound(&mut self, key: &str, inclusive: bool) -> Self {
+        self.upper = match inclusive {
+            true => Bound::Included(key.to_owned()),
+            false => Bound::Excluded(key.to_owned()),
+        };
+        self.to_owned()
+    }
+
+    pub fn with_prefix(&mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_owned());
+        self.to_owned()
+    }
+
+    // with_reverse iterates from the last key down to the first one.
+    pub fn with_reverse(&mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self.to_owned()
+    }
+
+    fn bounds(&self) -> (Bound<String>, Bound<String>) {
+        let (mut lower, mut upper) = (self.lower.clone(), self.upper.clone());
+        if let Some(prefix) = &self.prefix {
+            lower = max_lower(lower, Bound::Included(prefix.clone()));
+            upper = min_upper(upper, prefix_end(prefix));
+        }
+        (lower, upper)
+    }
+}
+
+// Version is the value of a key in one of the merged sources.
+enum Version<'a> {
+    Encoded(&'a [u8]),
+    Record(&'a Record),
+    Deleted,
+}
+
+// DBIterator merges the string keys of a bucket in the memtables and the index lazily, a
+// key takes the value of the newest source holding it and is skipped if that value is
+// deleted or expired. the sources are locked for a step only, so that the writers go on
+// between the steps, each step resumes after the key returned by the last one.
+pub struct DBIterator {
+    bucket: String,
+    // newest first
+    memtables: Vec<Arc<RwLock<Memtable>>>,
+    index: Option<Arc<RwLock<HashMap<String, Index>>>>,
+    // the values of the index records not held in it are read from their data files
+    data_files: Option<Arc<DataFiles>>,
+    opts: IterOptions,
+    // the keys left to iterate
+    lower: Bound<String>,
+    upper: Bound<String>,
+}
+
+impl DBIterator {
+    pub fn new(
+        bucket: &str,
+        memtables: Vec<Arc<RwLock<Memtable>>>,
+        index: Option<Arc<RwLock<HashMap<String, Index>>>>,
+        data_files: Option<Arc<DataFiles>>,
+        opts: IterOptions,
+    ) -> Self {
+        let (lower, upper) = opts.bounds();
+        DBIterator {
+            bucket: bucket.to_owned(),
+            memtables,
+            index,
+            data_files,
+            opts,
+            lower,
+            upper,
+        }
+    }
+
+    // seek moves the iterator to the first key at or after key, at or before it in reverse,
+    // keeping the bounds of the options.
+    pub fn seek(&mut self, key: &str) {
+        let (lower, upper) = self.opts.bounds();
+        (self.lower, self.upper) = match self.opts.reverse {
+            true => (lower, min_upper(upper, Bound::Included(key.to_owned()))),
+            false => (max_lower(lower, Bound::Included(key.to_owned())), upper),
+        };
+    }
+}
+
+impl Iterator for DBIterator {
+    type Item = Result<Entry, DbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let memtables: Vec<_> = self
+            .memtables
+            .iter()
+            .map(|memtable| memtable.read())
+            .collect();
+        let indexes = self.index.as_ref().map(|index| index.read());
+        let index = indexes
+            .as_ref()
+            .and_then(|indexes| indexes.get(&self.bucket));
+        let reverse = self.opts.reverse;
+        loop {
+            if is_empty(&self.lower, &self.upper) {
+                return None;
+            }
+            let bounds = (as_str(&self.lower), as_str(&self.upper));
+            // the first key of each source within the bounds, newest first, each memtable
+            // gives its keys then its tombstones
+            let mut firsts = vec![];
+            for memtable in &memtables {
+                let strings = memtable.string_range(&self.bucket, bounds);
+                firsts.extend(
+                    first(strings, reverse)
+                        .map(|(key, value)| (key.as_str(), Version::Encoded(value.as_ref()))),
+                );
+                let tombstones = memtable.tombstone_range(&self.bucket, bounds);
+                firsts
+                    .extend(first(tombstones, reverse).map(|key| (key.as_str(), Version::Deleted)));
+            }
+            if let Some(index) = index {
+                firsts.extend(
+                    first(index.range(bounds), reverse)
+                        .map(|(key, record)| (key.as_str(), Version::Record(record))),
+                );
+            }
+            let keys = firsts.iter().map(|(key, _)| *key);
+            let key = match reverse {
+                true => keys.max()?,
+                false => keys.min()?,
+            };
+            let newest = firsts
+                .into_iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, version)| version);
+            let entry = match newest {
+                Some(Version::Encoded(value)) => Some(value)
+                    .filter(|value| !Meta::parse_entry_header_buf(value).is_expired())
+                    .map(|value| Ok(Entry::decode_unchecked(value))),
+                Some(Version::Record(record)) => {
+                    // the scans leave the block cache to the point reads
+                    let entry = match &self.data_files {
+                        Some(data_files) if !record.held() => {
+                            data_files.read(record, false).map(|read| read.entry)
+                        }
+                        _ => Ok(record.entry.clone()),
+                    };
+                    entry
+                        .map(|entry| {
+                            Some(entry).filter(|entry| {
+                                !entry.is_expired()
+                                    && entry.meta.operate != EntryOperate::Del as u16
+                            })
+                        })
+                        .transpose()
+                }
+                Some(Version::Deleted) | None => None,
+            };
+
+            let key = Bound::Excluded(key.to_owned());
+            match reverse {
+                true => self.upper = key,
+                false => self.lower = key,
+            }
+            if let Some(entry) = entry {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+// first returns the first item of iter, the last one in reverse.
+fn first<I: DoubleEndedIterator>(mut iter: I, reverse: bool) -> Option<I::Item> {
+    match reverse {
+        true => iter.next_back(),
+        false => iter.next(),
+    }
+}
+
+fn as_str(bound: &Bound<String>) -> Bound<&str> {
+    bound.as_ref().map(String::as_str)
+}
+
+// is_empty tells if no key is within the bounds, a BTreeMap range panics on such bounds.
+fn is_empty(lower: &Bound<String>, upper: &Bound<String>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(lower), Bound::Included(upper)) => lower > upper,
+        (
+            Bound::Included(lower) | Bound::Excluded(lower),
+            Bound::Included(upper) | Bound::Excluded(upper),
+        ) => lower >= upper,
+        _ => false,
+    }
+}
+
+// max_lower returns the tighter of two lower bounds.
+fn max_lower(a: Bound<String>, b: Bound<String>) -> Bound<String> {
+    let ordering = match (&a, &b) {
+        (Bound::Unbounded, _) => return b,
+        (_, Bound::Unbounded) => return a,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            x.cmp(y)
+        }
+    };
+    match ordering {
+        std::cmp::Ordering::Less => b,
+        std::cmp::Ordering::Greater => a,
+        std::cmp::Ordering::Equal if matches!(a, Bound::Excluded(_)) => a,
+        std::cmp::Ordering::Equal => b,
+    }
+}
+
+// min_upper returns the tighter of two upper bounds.
+fn min_upper(a: Bound<String>, b: Bound<String>) -> Bound<String> {
+    let ordering = match (&a, &b) {
+        (Bound::Unbounded, _) => return b,
+        (_, Bound::Unbounded) => return a,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            x.cmp(y)
+        }
+    };
+    match ordering {
+        std::cmp::Ordering::Less => a,
+        std::cmp::Ordering::Greater => b,
+        std::cmp::Ordering::Equal if matches!(a, Bound::Excluded(_)) => a,
+        std::cmp::Ordering::Equal => b,
+    }
+}
+
+// prefix_end is the exclusive upper bound of the keys starting with prefix, strings being
+// ordered by code points it is the prefix with its last char incremented.
+fn prefix_end(prefix: &str) -> Bound<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        if let Some(next) = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32) {
+            chars.push(next);
+            return Bound::Excluded(chars.into_iter().collect());
+        }
+    }
+    Bound::Unbounded
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::enums::{self, DataTypes};
+    use crate::fileio::FDManager;
+
+    fn memtable(name: &str) -> Memtable {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        Memtable::new(
+            1,
+            path.to_str().unwrap(),
+            1,
+            enums::RWMode::StdIO,
+            FDManager::new(4),
+        )
+        .unwrap()
+    }
+
+    fn entry(key: &str, value: &str, operate: EntryOperate) -> Entry {
+        Entry::new(
+            Bytes::from("bucket"),
+            Bytes::from(key.to_owned()),
+            Bytes::from(value.to_owned()),
+            operate,
+            DataTypes::String,
+        )
+    }
+
+    fn collect(iter: DBIterator) -> Vec<(String, String)> {
+        iter.map(|entry| {
+            let entry = entry.unwrap();
+            (
+                String::from_utf8(entry.key.to_vec()).unwrap(),
+                String::from_utf8(entry.value.to_vec()).unwrap(),
+            )
+        })
+        .collect()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut index = Index::default();
+        for (key, value) in [("a", "disk"), ("b", "disk"), ("c", "disk"), ("d", "disk")] {
+            let record = Record {
+                entry: entry(key, value, EntryOperate::Put),
+                ..Default::default()
+            };
+            index.put(key.to_owned(), record).unwrap();
+        }
+        let mut old = memtable("arrowdb_iter_old.wal");
+        old.put(entry("b", "old", EntryOperate::Put)).unwrap();
+        old.put(entry("c", "", EntryOperate::Del)).unwrap();
+        old.put(entry("e", "old", EntryOperate::Put)).unwrap();
+        let mut expired = entry("f", "old", EntryOperate::Put);
+        expired.meta.ttl = 1;
+        expired.meta.timestamp -= 10;
+        old.put(expired).unwrap();
+        let mut active = memtable("arrowdb_iter_active.wal");
+        active.put(entry("c", "active", EntryOperate::Put)).unwrap();
+        active.put(entry("d", "", EntryOperate::Del)).unwrap();
+        active.put(entry("e", "", EntryOperate::Del)).unwrap();
+
+        let memtables = vec![Arc::new(RwLock::new(active)), Arc::new(RwLock::new(old))];
+        let index = Arc::new(RwLock::new(HashMap::from([("bucket".to_owned(), index)])));
+        let iter = |opts: IterOptions| {
+            DBIterator::new(
+                "bucket",
+                memtables.clone(),
+                Some(Arc::clone(&index)),
+                None,
+                opts,
+            )
+        };
+        let all = pairs(&[("a", "disk"), ("b", "old"), ("c", "active")]);
+        assert_eq!(collect(iter(IterOptions::default())), all);
+        let reversed: Vec<_> = all.iter().rev().cloned().collect();
+        let opts = IterOptions::default().with_reverse(true);
+        assert_eq!(collect(iter(opts)), reversed);
+
+        let opts = IterOptions::default()
+            .with_lower_bound("a", false)
+            .with_upper_bound("c", false);
+        assert_eq!(collect(iter(opts)), pairs(&[("b", "old")]));
+        let opts = IterOptions::default()
+            .with_lower_bound("c", false)
+            .with_upper_bound("a", true);
+        assert!(collect(iter(opts)).is_empty());
+
+        let mut seeked = iter(IterOptions::default());
+        seeked.seek("b");
+        assert_eq!(collect(seeked), pairs(&[("b", "old"), ("c", "active")]));
+
+        // the writes between the steps are seen
+        let mut stepped = iter(IterOptions::default());
+        assert_eq!(stepped.next().unwrap().unwrap().key, Bytes::from("a"));
+        memtables[0]
+            .write()
+            .put(entry("b", "new", EntryOperate::Put))
+            .unwrap();
+        assert_eq!(collect(stepped), pairs(&[("b", "new"), ("c", "active")]));
+    }
+
+    #[test]
+    fn test_prefix_seek() {
+        let mut active = memtable("arrowdb_iter_prefix.wal");
+        for key in ["ab", "abc", "abd", "ac", "b"] {
+            active.put(entry(key, key, EntryOperate::Put)).unwrap();
+        }
+        let active = vec![Arc::new(RwLock::new(active))];
+        let keys = |iter: DBIterator| -> Vec<String> {
+            collect(iter).into_iter().map(|(key, _)| key).collect()
+        };
+        let opts = IterOptions::default().with_prefix("ab");
+        let iter = DBIterator::new("bucket", active.clone(), None, None, opts.clone());
+        assert_eq!(keys(iter), ["ab", "abc", "abd"]);
+
+        let mut iter = DBIterator::new(
+            "bucket",
+            active.clone(),
+            None,
+            None,
+            opts.clone().with_reverse(true),
+        );
+        iter.seek("abc");
+        assert_eq!(keys(iter), ["abc", "ab"]);
+        // a seek out of the prefix stays in it
+        let mut iter = DBIterator::new("bucket", active.clone(), None, None, opts);
+        iter.seek("a");
+        assert_eq!(keys(iter), ["ab", "abc", "abd"]);
+
+        assert_eq!(prefix_end("a\u{10FFFF}"), Bound::Excluded("b".to_owned()));
+        assert_eq!(
+            prefix_end("\u{D7FF}"),
+            Bound::Excluded("\u{E000}".to_owned())
+        );
+        assert_eq!(prefix_end(""), Bound::Unbounded);
+    }
+}
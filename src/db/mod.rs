@@ -1,12 +1,590 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, fs, io::Write, ops::Bound, path::{Path, PathBuf}, sync::Arc, time::Duration};
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
-use crate::{index::Index, memtable, option, bgworkers::{flush::FlushWorker, index::IndexWorker, compaction::CompactionWorker}};
+use crate::{data::entry::Entry, datatypes::sortedset::ArcNode, index::{Index, Record}, memtable::{batch::WriteBatch, FlushedStrings, Memtable, MemTables}, option, enums::{self, EntryOperate, ListDirection}, errors::DbError, wal::SyncPolicy, fileio::{rate_limiter::RateLimiter, FDManager}, bgworkers::{compaction::{CompactionTask, CompactionWorker}, retention::RetentionWorker, sync::SyncWorker}};
+use self::{datafile::{DataFiles, Flushed}, iter::{DBIterator, IterOptions}, status::Status};
+
+pub mod datafile;
+mod iter;
+pub mod status;
 
 pub struct DB {
     opt: option::Option,
-    index: HashMap<String, Arc<Index>>,
-    mem_tables: Vec<memtable::Memtable>,
-    background_workers: (FlushWorker, IndexWorker, CompactionWorker)
+    // the string keys flushed to the data files, by bucket
+    index: Arc<RwLock<HashMap<String, Index>>>,
+    // oldest first, the active memtable is the last one
+    mem_tables: MemTables,
+    // the fd cache of the files of the db, see option::Option::with_fd_manager
+    fd_manager: Arc<Mutex<FDManager>>,
+    // the data files the index points into, read through the block cache
+    data_files: Arc<DataFiles>,
+    // the string keys of the index as the memtables look them up
+    flushed: Arc<Flushed>,
+    // throttles the writes of the flush and compaction workers, built once and shared with them, see
+    // option::Option::with_rate_limit
+    rate_limiter: Arc<RateLimiter>,
+    // the compactions started by compact, stopped with the db
+    compaction_workers: Mutex<Vec<CompactionWorker>>,
+    // the sticky write error of the db, shared with the background workers
+    status: Arc<Status>,
+    // syncs the active wal, only with SyncPolicy::Periodic
+    sync_worker: Option<SyncWorker>,
+    // drops the time series samples out of their retention
+    retention_worker: RetentionWorker,
+}
+
+impl DB {
+    // open opens the db in the dir of opt, the wals left there are replayed into their memtables oldest first.
+    pub fn open(opt: option::Option) -> Result<Self, DbError> {
+        fs::create_dir_all(opt.dir())?;
+        let fd_manager = opt.fd_manager();
+        let data_files = Arc::new(DataFiles::new(&opt, Arc::clone(&fd_manager), opt.new_block_cache()));
+        let mut index = HashMap::new();
+        data_files.load(&mut index)?;
+        let index = Arc::new(RwLock::new(index));
+        let flushed = Arc::new(Flushed::new(Arc::clone(&index), Arc::clone(&data_files)));
+
+        let mut wal_ids = wal_ids(opt.dir())?;
+        if wal_ids.is_empty() {
+            wal_ids.push(1);
+        }
+        let mut mem_tables: Vec<Arc<RwLock<Memtable>>> = vec![];
+        for wal_id in wal_ids {
+            let mut memtable = new_memtable(&opt, wal_id, &fd_manager)?;
+            match mem_tables.last() {
+                Some(old) => memtable.take_over(&mut old.write(), mem_tables.iter().rev().cloned().collect()),
+                None => memtable.set_flushed(Arc::clone(&flushed) as Arc<dyn FlushedStrings>),
+            }
+            memtable.recover()?;
+            mem_tables.push(Arc::new(RwLock::new(memtable)));
+        }
+        if let Some(active) = mem_tables.last() {
+            active.write().set_active(true);
+        }
+
+        let mem_tables = Arc::new(RwLock::new(mem_tables));
+        let sync_worker = match opt.sync_policy() {
+            SyncPolicy::Periodic(interval) => Some(SyncWorker::new(Arc::clone(&mem_tables), interval)),
+            SyncPolicy::EveryWrite | SyncPolicy::Os => None,
+        };
+        let status: Arc<Status> = Arc::default();
+        let retention_worker = RetentionWorker::new(Arc::clone(&mem_tables), opt.retention_interval(), Arc::clone(&status));
+        Ok(DB {
+            index,
+            mem_tables,
+            data_files,
+            flushed,
+            fd_manager,
+            rate_limiter: opt.new_rate_limiter(),
+            compaction_workers: Mutex::default(),
+            status,
+            sync_worker,
+            retention_worker,
+            opt,
+        })
+    }
+
+    // iter iterates the string keys of bucket lazily, merging the memtables and the index with the newest value of a key winning.
+    // the memtables rotated in after it is created are not iterated.
+    pub fn iter(&self, bucket: &str, opts: IterOptions) -> DBIterator {
+        let memtables = self.mem_tables.read().iter().rev().cloned().collect();
+        DBIterator::new(bucket, memtables, Some(Arc::clone(&self.index)), Some(Arc::clone(&self.data_files)), opts)
+    }
+
+    // get returns the value of a string key, the memtables are looked up newest first then the index. the values not held by
+    // the index are read from their data file through the block cache.
+    pub fn get(&self, bucket: &str, key: &str) -> Result<Option<Entry>, DbError> {
+        let memtables: Vec<_> = self.mem_tables.read().iter().rev().cloned().collect();
+        for memtable in memtables {
+            let memtable = memtable.read();
+            let bounds = (Bound::Included(key), Bound::Included(key));
+            if let Some((_, value)) = memtable.string_range(bucket, bounds).next() {
+                return Ok(Some(Entry::decode_unchecked(value)).filter(|entry| !entry.is_expired()));
+            }
+            if memtable.tombstone_range(bucket, bounds).next().is_some() {
+                return Ok(None);
+            }
+        }
+        // the filters of the sealed files rule a missing key out before the index is looked up
+        let entry = self.flushed.get(bucket, key)?;
+        Ok(entry.filter(|entry| !entry.is_expired() && entry.meta.operate != EntryOperate::Del as u16))
+    }
+
+    // write_batch applies batch to the active memtable as a single wal record and syncs it as sync_policy, or as the option if none.
+    // a periodic sync_policy needs the sync worker of a db opened with one, it fails with DbError::SyncPolicyUnsupported otherwise.
+    // the memtable is locked for the append only, the writers then share the sync of the wal.
+    // it fails with DbError::ReadOnly once a write ran out of disk space, see resume.
+    pub fn write_batch(&self, batch: WriteBatch, sync_policy: Option<SyncPolicy>) -> Result<usize, DbError> {
+        self.status.check_writable()?;
+        let sync_policy = sync_policy.unwrap_or_else(|| self.opt.sync_policy());
+        if matches!(sync_policy, SyncPolicy::Periodic(_)) && self.sync_worker.is_none() {
+            return Err(DbError::SyncPolicyUnsupported { policy: format!("{:?}", sync_policy) });
+        }
+        let (applied, group_commit, logged) = loop {
+            let memtable = self.active_memtable().map_err(|err| self.status.report(err))?;
+            let mut memtable = memtable.write();
+            // rotated out since it was got
+            if !memtable.active() {
+                continue;
+            }
+            let applied = memtable.write_batch(batch).map_err(|err| self.status.report(err))?;
+            break (applied, memtable.group_commit(), memtable.logged());
+        };
+        group_commit.commit(logged, sync_policy).map_err(|err| self.status.report(err))?;
+        Ok(applied)
+    }
+
+    // blpop pops the head of the first non-empty list of keys, blocking until an element is pushed or the timeout expires.
+    // a zero timeout blocks forever, the clients blocked on a key are served across the rotations of the memtables.
+    pub fn blpop(&self, bucket: &str, keys: &[&str], timeout: Duration) -> Result<Option<(String, Entry)>, DbError> {
+        self.status.check_writable()?;
+        Memtable::blpop(&self.mem_tables, bucket, keys, timeout).map_err(|err| self.status.report(err))
+    }
+
+    pub fn brpop(&self, bucket: &str, keys: &[&str], timeout: Duration) -> Result<Option<(String, Entry)>, DbError> {
+        self.status.check_writable()?;
+        Memtable::brpop(&self.mem_tables, bucket, keys, timeout).map_err(|err| self.status.report(err))
+    }
+
+    pub fn blmove(&self, bucket: &str, source: &str, destination: &str, wherefrom: ListDirection, whereto: ListDirection, timeout: Duration) -> Result<Option<Entry>, DbError> {
+        self.status.check_writable()?;
+        Memtable::blmove(&self.mem_tables, bucket, source, destination, wherefrom, whereto, timeout).map_err(|err| self.status.report(err))
+    }
+
+    // bzpopmin pops the lowest member of the first non-empty sorted set of keys, blocking like blpop.
+    pub fn bzpopmin(&self, bucket: &str, keys: &[&str], timeout: Duration) -> Result<Option<(String, ArcNode)>, DbError> {
+        self.status.check_writable()?;
+        Memtable::bzpopmin(&self.mem_tables, bucket, keys, timeout).map_err(|err| self.status.report(err))
+    }
+
+    pub fn bzpopmax(&self, bucket: &str, keys: &[&str], timeout: Duration) -> Result<Option<(String, ArcNode)>, DbError> {
+        self.status.check_writable()?;
+        Memtable::bzpopmax(&self.mem_tables, bucket, keys, timeout).map_err(|err| self.status.report(err))
+    }
+
+    // active_memtable returns the memtable taking the writes, once its wal is full a new memtable replaces it and takes over
+    // its values other than strings and the keyspace, it looks the strings up in the memtables rotated out.
+    fn active_memtable(&self) -> Result<Arc<RwLock<Memtable>>, DbError> {
+        let wal_size = self.opt.memtable_size_mb() * enums::MB;
+        let active = self.mem_tables.read().last().cloned().expect("a db has an active memtable");
+        if active.read().logged() < wal_size {
+            return Ok(active);
+        }
+
+        let mut mem_tables = self.mem_tables.write();
+        let active = mem_tables.last().cloned().expect("a db has an active memtable");
+        let mut old = active.write();
+        // another writer rotated it meanwhile
+        if old.logged() < wal_size || !old.active() {
+            drop(old);
+            return Ok(active);
+        }
+        let mut memtable = new_memtable(&self.opt, old.wal_id() + 1, &self.fd_manager)?;
+        // the wal rotated out is synced and sealed here, the sync worker follows the active one
+        old.seal_wal()?;
+        memtable.take_over(&mut old, mem_tables.iter().rev().cloned().collect());
+        memtable.set_active(true);
+        old.set_active(false);
+        drop(old);
+        let memtable = Arc::new(RwLock::new(memtable));
+        mem_tables.push(Arc::clone(&memtable));
+        Ok(memtable)
+    }
+
+    // status returns the error that made the db read only, none if it accepts writes.
+    pub fn status(&self) -> Option<String> {
+        self.status.background_error()
+    }
+
+    // resume accepts writes again after a disk full error if a probe file can be written to the db dir.
+    pub fn resume(&self) -> Result<(), DbError> {
+        self.status.resume(|| {
+            let probe = Path::new(self.opt.dir()).join(".resume");
+            let mut file = fs::File::create(&probe)?;
+            file.write_all(&[0u8; 4096])?;
+            file.sync_all()?;
+            fs::remove_file(&probe)?;
+            Ok(())
+        })
+    }
+
+    // compact rewrites the live string records of the data files compacted to a new data file in the background, through the
+    // rate limiter of the db. the new file is sealed and the compacted ones evicted from the block cache once the records are
+    // all rewritten. returns the id of the new file.
+    pub fn compact(&self, compacted: Vec<u32>) -> Result<u32, DbError> {
+        let mut compaction_workers = self.compaction_workers.lock();
+        let file_id = self.data_files.next_file_id();
+        let worker = CompactionWorker::new(compaction_workers.len(), file_id, Arc::clone(&self.data_files), Arc::clone(&self.rate_limiter), Arc::clone(&self.index), Arc::clone(&self.status))?;
+        let records: Vec<Record> = self.index.read().values()
+            .flat_map(|index| index.range((Bound::Unbounded, Bound::Unbounded)).map(|(_, record)| record))
+            .filter(|record| compacted.contains(&record.hint.file_id))
+            .cloned()
+            .collect();
+        for record in records {
+            worker.send(CompactionTask::Rewrite(Box::new(record)));
+        }
+        worker.send(CompactionTask::Finish(compacted));
+        compaction_workers.push(worker);
+        Ok(file_id)
+    }
+
+    // set_rate_limit changes the bytes per second of the flush and compaction writes, 0 disables the limit.
+    pub fn set_rate_limit(&self, bytes_per_sec: u64) {
+        self.rate_limiter.set_rate(bytes_per_sec)
+    }
+}
+
+impl Drop for DB {
+    fn drop(&mut self) {
+        if let Some(sync_worker) = &self.sync_worker {
+            sync_worker.stop();
+        }
+        self.retention_worker.stop();
+        self.compaction_workers.lock().iter().for_each(|worker| worker.stop());
+    }
+}
+
+fn wal_path(dir: &str, wal_id: u64) -> PathBuf {
+    Path::new(dir).join(format!("{}.wal", wal_id))
+}
+
+// wal_ids lists the ids of the wals in dir in increasing order.
+fn wal_ids(dir: &str) -> Result<Vec<u64>, DbError> {
+    let mut wal_ids = vec![];
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        if path.extension().is_none_or(|ext| ext != "wal") {
+            continue;
+        }
+        if let Some(wal_id) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse().ok()) {
+            wal_ids.push(wal_id);
+        }
+    }
+    wal_ids.sort_unstable();
+    Ok(wal_ids)
+}
+
+fn new_memtable(opt: &option::Option, wal_id: u64, fd_manager: &Arc<Mutex<FDManager>>) -> Result<Memtable, DbError> {
+    let path = wal_path(opt.dir(), wal_id);
+    Memtable::new(wal_id, &path.to_string_lossy(), opt.memtable_size_mb(), opt.rw_mode(), Arc::clone(fd_manager))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{data::payload, enums::DataTypes, index::Record, memtable::batch::BatchOp};
+
+    fn open(name: &str, opt: option::Option) -> DB {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        DB::open(opt.clone().with_dir(dir.to_str().unwrap())).unwrap()
+    }
+
+    fn entry(key: &str, value: Bytes, operate: EntryOperate, data_type: DataTypes) -> Entry {
+        Entry::new(Bytes::from("bucket"), Bytes::from(key.to_owned()), value, operate, data_type)
+    }
+
+    fn put(db: &DB, key: &str, value: Bytes, sync_policy: Option<SyncPolicy>) {
+        let mut batch = WriteBatch::new();
+        batch.put(entry(key, value, EntryOperate::Put, DataTypes::String));
+        db.write_batch(batch, sync_policy).unwrap();
+    }
+
+    fn rpush(db: &DB, key: &str, value: &str) {
+        let mut batch = WriteBatch::new();
+        batch.push(BatchOp::RPush(entry(key, Bytes::from(value.to_owned()), EntryOperate::LRpush, DataTypes::List)));
+        db.write_batch(batch, None).unwrap();
+    }
+
+    fn keys(db: &DB) -> Vec<Bytes> {
+        db.iter("bucket", IterOptions::default()).map(|entry| entry.unwrap().key).collect()
+    }
+
+    fn active(db: &DB) -> Arc<RwLock<Memtable>> {
+        db.mem_tables.read().last().cloned().unwrap()
+    }
+
+    #[test]
+    fn test_rotation() {
+        let opt = option::Option::default().with_memtable_size_mb(1);
+        let db = open("arrowdb_db_rotation", opt.clone());
+        rpush(&db, "list", "a");
+        for i in 0..8 {
+            put(&db, &format!("key{}", i), Bytes::from(vec![b'v'; 300 * 1024]), None);
+        }
+        rpush(&db, "list", "b");
+        assert!(db.mem_tables.read().len() > 1);
+        assert!(db.mem_tables.read().iter().rev().skip(1).all(|memtable| !memtable.read().active()));
+        // the list moved to the active memtable with the rotations
+        assert_eq!(active(&db).read().llen("bucket", "list").unwrap(), 2);
+        assert_eq!(keys(&db).len(), 8);
+
+        let dir = db.opt.dir().to_owned();
+        let rotated = db.mem_tables.read().len();
+        drop(db);
+        let db = DB::open(opt.clone().with_dir(&dir)).unwrap();
+        assert_eq!(db.mem_tables.read().len(), rotated);
+        assert_eq!(active(&db).read().llen("bucket", "list").unwrap(), 2);
+        assert_eq!(keys(&db).len(), 8);
+    }
+
+    #[test]
+    fn test_keyspace_across_rotation() {
+        let opt = option::Option::default().with_memtable_size_mb(1);
+        let db = open("arrowdb_db_keyspace_rotation", opt.clone());
+        put(&db, "string", Bytes::from("value"), None);
+        for i in 0..8 {
+            put(&db, &format!("key{}", i), Bytes::from(vec![b'v'; 300 * 1024]), None);
+        }
+        assert!(db.mem_tables.read().len() > 1);
+        // the string stays in the memtable rotated out, the active one knows its type
+        assert_eq!(active(&db).read().key_type("bucket", "string"), Some(DataTypes::String));
+        let mut batch = WriteBatch::new();
+        batch.push(BatchOp::RPush(entry("string", Bytes::from("a"), EntryOperate::LRpush, DataTypes::List)));
+        assert!(matches!(db.write_batch(batch, None), Err(DbError::WrongType { .. })));
+        let mut batch = WriteBatch::new();
+        batch.push(BatchOp::Append(entry("string", Bytes::from("!"), EntryOperate::Append, DataTypes::String)));
+        db.write_batch(batch, None).unwrap();
+        assert_eq!(db.get("bucket", "string").unwrap().unwrap().value, Bytes::from("value!"));
+
+        assert_eq!(active(&db).write().del("bucket", &["string", "missing"]).unwrap(), 1);
+        assert!(db.get("bucket", "string").unwrap().is_none());
+        assert_eq!(active(&db).read().exists("bucket", &["string"]), 0);
+        rpush(&db, "string", "a");
+
+        let dir = db.opt.dir().to_owned();
+        drop(db);
+        let db = DB::open(opt.clone().with_dir(&dir)).unwrap();
+        assert_eq!(active(&db).read().key_type("bucket", "string"), Some(DataTypes::List));
+        assert_eq!(active(&db).read().key_type("bucket", "key0"), Some(DataTypes::String));
+    }
+
+    #[test]
+    fn test_concurrent_write_batch() {
+        let db = Arc::new(open("arrowdb_db_concurrent_write_batch", option::Option::default()));
+        let writers: Vec<_> = (0..4)
+            .map(|i| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    for j in 0..50 {
+                        put(&db, &format!("key{}-{}", i, j), Bytes::from("value"), Some(SyncPolicy::EveryWrite));
+                    }
+                })
+            })
+            .collect();
+        writers.into_iter().for_each(|writer| writer.join().unwrap());
+        assert_eq!(keys(&db).len(), 200);
+        let active = active(&db);
+        let active = active.read();
+        assert_eq!(active.group_commit().synced(), active.logged());
+    }
+
+    #[test]
+    fn test_sync_worker() {
+        let opt = option::Option::default()
+            .with_memtable_size_mb(1)
+            .with_sync_policy(SyncPolicy::Periodic(Duration::from_millis(10)));
+        let db = open("arrowdb_db_sync_worker", opt);
+        for i in 0..8 {
+            put(&db, &format!("key{}", i), Bytes::from(vec![b'v'; 300 * 1024]), None);
+        }
+        assert!(db.mem_tables.read().len() > 1);
+        thread::sleep(Duration::from_millis(100));
+        // the worker follows the active wal, the ones rotated out were synced by the rotation
+        for memtable in db.mem_tables.read().iter() {
+            let memtable = memtable.read();
+            assert_eq!(memtable.group_commit().synced(), memtable.logged());
+        }
+    }
+
+    #[test]
+    fn test_periodic_sync_unsupported() {
+        let db = open("arrowdb_db_periodic_unsupported", option::Option::default());
+        let mut batch = WriteBatch::new();
+        batch.put(entry("key", Bytes::from("value"), EntryOperate::Put, DataTypes::String));
+        let res = db.write_batch(batch, Some(SyncPolicy::Periodic(Duration::from_millis(10))));
+        assert!(matches!(res, Err(DbError::SyncPolicyUnsupported { .. })));
+        assert!(db.get("bucket", "key").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_blpop_across_rotation() {
+        let db = Arc::new(open("arrowdb_db_blpop_rotation", option::Option::default().with_memtable_size_mb(1)));
+        let blocked = {
+            let db = Arc::clone(&db);
+            thread::spawn(move || db.blpop("bucket", &["list"], Duration::from_secs(10)).unwrap())
+        };
+        thread::sleep(Duration::from_millis(50));
+        for i in 0..8 {
+            put(&db, &format!("key{}", i), Bytes::from(vec![b'v'; 300 * 1024]), None);
+        }
+        assert!(db.mem_tables.read().len() > 1);
+        rpush(&db, "list", "a");
+
+        let (key, entry) = blocked.join().unwrap().unwrap();
+        assert_eq!((key.as_str(), entry.value), ("list", Bytes::from("a")));
+        assert_eq!(active(&db).read().llen("bucket", "list").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_blmove_unserved() {
+        let db = Arc::new(open("arrowdb_db_blmove_unserved", option::Option::default()));
+        put(&db, "string", Bytes::from("value"), None);
+        let blocked = {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                db.blmove("bucket", "list", "string", ListDirection::Left, ListDirection::Left, Duration::from_millis(200)).unwrap()
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        let popped = {
+            let db = Arc::clone(&db);
+            thread::spawn(move || db.blpop("bucket", &["list"], Duration::from_secs(10)).unwrap())
+        };
+        thread::sleep(Duration::from_millis(50));
+        // the blmove to a string cannot be served, the element goes to the next waiter
+        let mut batch = WriteBatch::new();
+        batch.push(BatchOp::RPush(entry("list", Bytes::from("a"), EntryOperate::LRpush, DataTypes::List)));
+        db.write_batch(batch, None).unwrap();
+
+        let (key, entry) = popped.join().unwrap().unwrap();
+        assert_eq!((key.as_str(), entry.value), ("list", Bytes::from("a")));
+        assert!(blocked.join().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bzpopmin_across_rotation() {
+        let db = Arc::new(open("arrowdb_db_bzpopmin_rotation", option::Option::default().with_memtable_size_mb(1)));
+        let blocked = {
+            let db = Arc::clone(&db);
+            thread::spawn(move || db.bzpopmin("bucket", &["zset"], Duration::from_secs(10)).unwrap())
+        };
+        thread::sleep(Duration::from_millis(50));
+        for i in 0..8 {
+            put(&db, &format!("key{}", i), Bytes::from(vec![b'v'; 300 * 1024]), None);
+        }
+        assert!(db.mem_tables.read().len() > 1);
+        let mut batch = WriteBatch::new();
+        for (score, member) in [(2.0, "b"), (1.0, "a")] {
+            let value = payload::encode_zscore(score, member.as_bytes());
+            batch.push(BatchOp::ZAdd(entry("zset", value, EntryOperate::ZPut, DataTypes::SortedSet)));
+        }
+        db.write_batch(batch, None).unwrap();
+
+        let (key, node) = blocked.join().unwrap().unwrap();
+        assert_eq!((key.as_str(), node.borrow().key.as_ref()), ("zset", b"a".as_ref()));
+    }
+
+    #[test]
+    fn test_retention_worker() {
+        let opt = option::Option::default().with_retention_interval(Duration::from_millis(10));
+        let db = open("arrowdb_db_retention_worker", opt.clone());
+        active(&db).write().ts_create(10, entry("ts", Bytes::new(), EntryOperate::TsCreate, DataTypes::TimeSeries)).unwrap();
+        let mut batch = WriteBatch::new();
+        for timestamp in [0, 5, 10, 15, 20] {
+            batch.push(BatchOp::TsAdd(timestamp, 1.0, entry("ts", Bytes::new(), EntryOperate::TsAdd, DataTypes::TimeSeries)));
+        }
+        db.write_batch(batch, None).unwrap();
+        let samples = |db: &DB| active(db).read().ts_range("bucket", "ts", 0, 100, None).unwrap().len();
+        for _ in 0..100 {
+            if samples(&db) == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(samples(&db), 3);
+
+        // the trims are replayed
+        let dir = db.opt.dir().to_owned();
+        drop(db);
+        let db = DB::open(opt.clone().with_retention_interval(Duration::from_secs(3600)).with_dir(&dir)).unwrap();
+        assert_eq!(samples(&db), 3);
+    }
+
+    #[test]
+    fn test_get_through_block_cache() {
+        let db = open("arrowdb_db_get_through_block_cache", option::Option::default());
+        let mut record = Record { entry: entry("key1", Bytes::from("value"), EntryOperate::Put, DataTypes::String), ..Default::default() };
+        record.hint.key = record.entry.key.clone();
+        record.hint.meta = record.entry.meta.clone();
+        db.data_files.open(0).unwrap().write().write(&record.encode(), 0).unwrap();
+        let keys = HashMap::from([("bucket".to_owned(), vec![record.hint.key.clone()])]);
+        db.data_files.seal(0, record.size() as u64, &keys, &mut db.index.write()).unwrap();
+        // the index holds the hint only
+        let hint = Record { hint: record.hint.clone(), ..Default::default() };
+        db.index.write().entry("bucket".to_owned()).or_default().put("key1".to_owned(), hint).unwrap();
+
+        let block_cache = db.data_files.block_cache().unwrap();
+        let values: Vec<_> = db.iter("bucket", IterOptions::default()).map(|entry| entry.unwrap().value).collect();
+        assert_eq!(values, [Bytes::from("value")]);
+        // the scan does not fill the cache
+        assert_eq!((block_cache.hits(), block_cache.misses()), (0, 1));
+        assert_eq!(db.get("bucket", "key1").unwrap().unwrap().value, Bytes::from("value"));
+        assert_eq!(db.get("bucket", "key1").unwrap().unwrap().value, Bytes::from("value"));
+        assert_eq!((block_cache.hits(), block_cache.misses()), (1, 2));
+        // the keyspace covers the index
+        assert_eq!(active(&db).read().key_type("bucket", "key1"), Some(DataTypes::String));
+
+        // the memtables hide the index
+        let mut batch = WriteBatch::new();
+        batch.put(entry("key1", Bytes::new(), EntryOperate::Del, DataTypes::String));
+        db.write_batch(batch, None).unwrap();
+        assert!(db.get("bucket", "key1").unwrap().is_none());
+        assert_eq!(active(&db).read().key_type("bucket", "key1"), None);
+    }
+
+    #[test]
+    fn test_filters_rule_out() {
+        let opt = option::Option::default();
+        let db = open("arrowdb_db_filters_rule_out", opt.clone());
+        let keys = HashMap::from([("bucket".to_owned(), vec![Bytes::from("key1")])]);
+        db.data_files.open(0).unwrap().write().write(b"records", 0).unwrap();
+        db.data_files.seal(0, 7, &keys, &mut db.index.write()).unwrap();
+        let dir = db.opt.dir().to_owned();
+        drop(db);
+
+        let db = DB::open(opt.clone().with_dir(&dir)).unwrap();
+        let indexes = db.index.read();
+        assert!(db.data_files.may_contain(&indexes["bucket"], b"key1"));
+        assert!(!db.data_files.may_contain(&indexes["bucket"], b"key2"));
+        drop(indexes);
+        // a file still written to may hold any key
+        db.data_files.open(1).unwrap();
+        assert!(db.data_files.may_contain(&db.index.read()["bucket"], b"key2"));
+    }
+
+    #[test]
+    fn test_compact_rate_limited() {
+        let db = open("arrowdb_db_compact_rate_limited", option::Option::default());
+        let mut write_at = 0;
+        for key in ["key1", "key2"] {
+            let mut record = Record { entry: entry(key, Bytes::from("value"), EntryOperate::Put, DataTypes::String), ..Default::default() };
+            record.hint.key = record.entry.key.clone();
+            record.hint.meta = record.entry.meta.clone();
+            record.hint.offset = write_at;
+            write_at += db.data_files.open(0).unwrap().write().write(&record.encode(), write_at).unwrap() as u64;
+            let hint = Record { hint: record.hint.clone(), ..Default::default() };
+            db.index.write().entry("bucket".to_owned()).or_default().put(key.to_owned(), hint).unwrap();
+        }
+
+        // the compaction writes through the limiter of the db, at about a record per second
+        db.set_rate_limit(write_at / 2);
+        let started = std::time::Instant::now();
+        assert_eq!(db.compact(vec![0]).unwrap(), 1);
+        let compacted = |db: &DB| db.index.read()["bucket"].range((Bound::Unbounded, Bound::Unbounded)).all(|(_, record)| record.hint.file_id == 1);
+        for _ in 0..100 {
+            if compacted(&db) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(compacted(&db));
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        assert_eq!(db.get("bucket", "key2").unwrap().unwrap().value, Bytes::from("value"));
+    }
 }
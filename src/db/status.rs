@@ -0,0 +1,86 @@
+use parking_lot::RwLock;
+
+use crate::errors::DbError;
+
+// Status holds the sticky background error of a db. a write failing for lack of disk space
+// switches the db to read only: the writes then fail with DbError::ReadOnly while the reads
+// go on, until resume finds space again.
+#[derive(Debug, Default)]
+pub struct Status {
+    error: RwLock<Option<String>>,
+}
+
+impl Status {
+    // check_writable fails if the db is read only.
+    pub fn check_writable(&self) -> Result<(), DbError> {
+        match self.error.read().as_ref() {
+            Some(reason) => Err(DbError::ReadOnly {
+                reason: reason.to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // report switches the db to read only if err is a disk full error, the first error is
+    // kept. err is given back for the caller to return it.
+    pub fn report(&self, err: DbError) -> DbError {
+        if is_no_space(&err) {
+            self.error.write().get_or_insert_with(|| err.to_string());
+        }
+        err
+    }
+
+    pub fn background_error(&self) -> Option<String> {
+        self.error.read().clone()
+    }
+
+    // resume leaves the read only mode once probe, which writes to the disk, succeeds.
+    pub fn resume(&self, probe: impl FnOnce() -> Result<(), DbError>) -> Result<(), DbError> {
+        if self.error.read().is_none() {
+            return Ok(());
+        }
+        probe().map_err(|err| self.report(err))?;
+        *self.error.write() = None;
+        Ok(())
+    }
+}
+
+pub fn is_no_space(err: &DbError) -> bool {
+    match err {
+        DbError::IOError(err) => err.raw_os_error() == Some(libc::ENOSPC),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_space() -> DbError {
+        DbError::IOError(std::io::Error::from_raw_os_error(libc::ENOSPC))
+    }
+
+    #[test]
+    fn test_read_only() {
+        let status = Status::default();
+        status.report(DbError::IOError(std::io::Error::from_raw_os_error(
+            libc::EIO,
+        )));
+        assert!(status.check_writable().is_ok());
+
+        let err = status.report(no_space());
+        assert!(is_no_space(&err));
+        status.report(no_space());
+        assert!(matches!(
+            status.check_writable(),
+            Err(DbError::ReadOnly { .. })
+        ));
+        assert_eq!(status.background_error(), Some(no_space().to_string()));
+
+        assert!(status.resume(|| Err(no_space())).is_err());
+        assert!(status.check_writable().is_err());
+        status.resume(|| Ok(())).unwrap();
+        assert!(status.check_writable().is_ok());
+        assert_eq!(status.background_error(), None);
+    }
+}
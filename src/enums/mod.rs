@@ -7,7 +7,7 @@ pub static GB: u64 = 1024 * MB;
 
 pub static SEPARATOR: u8 = b'#';
 
-#[derive(Debug, Clone, IntoPrimitive, TryFromPrimitive, Default)]
+#[derive(Debug, Clone, Copy, IntoPrimitive, TryFromPrimitive, Default, PartialEq, Eq)]
 #[repr(usize)]
 pub enum DataTypes {
     #[default]
@@ -15,6 +15,8 @@ pub enum DataTypes {
     List = 2,
     Set = 3,
     SortedSet = 4,
+    Json = 5,
+    TimeSeries = 6,
 }
 
 #[derive(Debug, Clone, IntoPrimitive, TryFromPrimitive, Default)]
@@ -60,6 +62,66 @@ pub enum EntryOperate {
     ZFindRank = 29,
     ZFindRevRank = 30,
     ZGetByScoreRange = 31,
+    Append = 32,
+    GetRange = 33,
+    SetRange = 34,
+    StrLen = 35,
+    ZIncrBy = 36,
+    ZUnion = 37,
+    ZInter = 38,
+    ZRangeByLex = 39,
+    ZLexCount = 40,
+    ZRemRangeByLex = 41,
+    LBlpop = 42,
+    LBrpop = 43,
+    LBlmove = 44,
+    LInsert = 45,
+    LTrim = 46,
+    LMove = 47,
+    SPop = 48,
+    SRandMember = 49,
+    SMove = 50,
+    SInterStore = 51,
+    SUnionStore = 52,
+    SDiffStore = 53,
+    Rename = 54,
+    JsonSet = 55,
+    JsonDel = 56,
+    JsonArrAppend = 57,
+    JsonNumIncrBy = 58,
+    TsCreate = 59,
+    TsAdd = 60,
+    TsCreateRule = 61,
+    Batch = 68,
+    TsTrim = 69,
+}
+
+#[derive(Debug, Clone, Copy, IntoPrimitive, TryFromPrimitive, Default, PartialEq, Eq)]
+#[repr(usize)]
+pub enum ListDirection {
+    #[default]
+    Left = 1,
+    Right = 2,
+}
+
+#[derive(Debug, Clone, Copy, IntoPrimitive, TryFromPrimitive, Default, PartialEq, Eq)]
+#[repr(usize)]
+pub enum ZAggregate {
+    #[default]
+    Sum = 1,
+    Min = 2,
+    Max = 3,
+}
+
+#[derive(Debug, Clone, Copy, IntoPrimitive, TryFromPrimitive, Default, PartialEq, Eq)]
+#[repr(usize)]
+pub enum TsAggregation {
+    #[default]
+    Avg = 1,
+    Min = 2,
+    Max = 3,
+    Sum = 4,
+    Count = 5,
 }
 
 #[derive(Debug, Clone, IntoPrimitive, TryFromPrimitive, Default)]
@@ -77,4 +139,7 @@ pub enum RWMode {
     #[default]
     StdIO = 1,
     MMap = 2,
+    DirectIO = 3,
+    // the files are kept in memory, for tests and ephemeral dbs
+    MemIO = 4,
 }
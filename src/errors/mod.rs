@@ -1,7 +1,7 @@
 use crossbeam_channel::SendError;
 use thiserror::Error;
 
-use crate::index::Record;
+use crate::{enums::DataTypes, index::Record};
 
 #[derive(Debug, Error)]
 pub enum DbError {
@@ -30,12 +30,49 @@ pub enum DbError {
         data_type: u16,
     },
 
+    #[error("WRONGTYPE bucket:{bucket} key:{key} holds a {data_type:?} value")]
+    WrongType {
+        bucket: String,
+        key: String,
+        data_type: DataTypes,
+    },
+
+    #[error("bucket:{bucket} key:{key} invalid json, msg:{msg}")]
+    JsonInvalid {
+        bucket: String,
+        key: String,
+        msg: String,
+    },
+
+    #[error("bucket:{bucket} key:{key} invalid time series operate, msg:{msg}")]
+    TimeSeriesInvalid {
+        bucket: String,
+        key: String,
+        msg: String,
+    },
+
+    #[error("bucket:{bucket} key:{key} value size {size} exceeds the max value size")]
+    ValueTooLarge {
+        bucket: String,
+        key: String,
+        size: usize,
+    },
+
+    #[error("bucket:{bucket} key:{key} not exist")]
+    KeyNotExist { bucket: String, key: String },
+
     #[error("bucket {bucket} not exist")]
     BucketNotExist { bucket: String },
 
     #[error("key contains separator char {separator}")]
     ContainSeparatorChar { separator: char },
 
+    #[error("db is read only after a write error: {reason}")]
+    ReadOnly { reason: String },
+
+    #[error("sync policy {policy} is not run by the db")]
+    SyncPolicyUnsupported { policy: String },
+
     #[error("sender send record error")]
     BackgroundWorkerSendError(#[from] SendError<Record>)
 }
@@ -0,0 +1,229 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::errors::DbError;
+use crate::fileio::FileIOManager;
+
+// files are cached by blocks of this size, aligned on it.
+pub const BLOCK_SIZE: u64 = 4096;
+pub const DEFAULT_BLOCK_CACHE_SHARDS: usize = 16;
+
+struct Shard {
+    blocks: lru::LruCache<(u64, u64), Bytes>,
+    // bytes of the cached blocks
+    used: usize,
+    capacity: usize,
+}
+
+impl Shard {
+    fn insert(&mut self, key: (u64, u64), block: Bytes) {
+        if block.len() > self.capacity {
+            return;
+        }
+        self.used += block.len();
+        if let Some(old) = self.blocks.put(key, block) {
+            self.used -= old.len();
+        }
+        while self.used > self.capacity {
+            match self.blocks.pop_lru() {
+                Some((_, evicted)) => self.used -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+// BlockCache is a LRU cache of file blocks keyed by (file id, block offset), split in
+// shards with their own lock and an equal part of the capacity.
+pub struct BlockCache {
+    shards: Vec<Mutex<Shard>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BlockCache {
+    pub fn new(capacity_bytes: usize, shards: usize) -> Self {
+        let shards = shards.max(1);
+        let shards = (0..shards)
+            .map(|_| {
+                Mutex::new(Shard {
+                    blocks: lru::LruCache::unbounded(),
+                    used: 0,
+                    capacity: capacity_bytes / shards,
+                })
+            })
+            .collect();
+        BlockCache {
+            shards,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, file_id: u64, block_offset: u64) -> Option<Bytes> {
+        let key = (file_id, block_offset);
+        let block = self.shard(key).lock().blocks.get(&key).cloned();
+        match block {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        block
+    }
+
+    pub fn insert(&self, file_id: u64, block_offset: u64, block: Bytes) {
+        let key = (file_id, block_offset);
+        self.shard(key).lock().insert(key, block);
+    }
+
+    // read reads b from file at offset through the cache, the blocks missing are read from
+    // file and cached only if fill_cache, compaction and scans leave it false so that they
+    // do not evict the blocks of the point reads.
+    pub fn read(
+        &self,
+        file_id: u64,
+        file: &dyn FileIOManager,
+        b: &mut [u8],
+        offset: u64,
+        fill_cache: bool,
+    ) -> Result<usize, DbError> {
+        let mut read = 0;
+        while read < b.len() {
+            let pos = offset + read as u64;
+            let block_offset = pos - pos % BLOCK_SIZE;
+            let block = match self.get(file_id, block_offset) {
+                Some(block) => block,
+                None => {
+                    let mut buf = vec![0u8; BLOCK_SIZE as usize];
+                    let n = match file.read(&mut buf, block_offset) {
+                        Err(DbError::OffsetOutOfRange { .. }) => break,
+                        res => res?,
+                    };
+                    buf.truncate(n);
+                    let block = Bytes::from(buf);
+                    if fill_cache && n > 0 {
+                        self.insert(file_id, block_offset, block.clone());
+                    }
+                    block
+                }
+            };
+
+            let start = (pos - block_offset) as usize;
+            if start >= block.len() {
+                break;
+            }
+            let n = (block.len() - start).min(b.len() - read);
+            b[read..read + n].copy_from_slice(&block[start..start + n]);
+            read += n;
+            if block.len() < BLOCK_SIZE as usize {
+                break;
+            }
+        }
+        Ok(read)
+    }
+
+    // evict_file drops the blocks of file_id, it is called when the file is rewritten or
+    // removed.
+    pub fn evict_file(&self, file_id: u64) {
+        for shard in &self.shards {
+            let mut shard = shard.lock();
+            let keys: Vec<(u64, u64)> = shard
+                .blocks
+                .iter()
+                .map(|(key, _)| *key)
+                .filter(|(id, _)| *id == file_id)
+                .collect();
+            for key in keys {
+                if let Some(block) = shard.blocks.pop(&key) {
+                    shard.used -= block.len();
+                }
+            }
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // usage returns the bytes of the cached blocks.
+    pub fn usage(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().used).sum()
+    }
+
+    fn shard(&self, key: (u64, u64)) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums;
+    use crate::fileio::{FDManager, FileManager};
+
+    #[test]
+    fn test_insert_evict() {
+        let cache = BlockCache::new(4 * BLOCK_SIZE as usize, 1);
+        for i in 0..5 {
+            cache.insert(
+                1,
+                i * BLOCK_SIZE,
+                Bytes::from(vec![0u8; BLOCK_SIZE as usize]),
+            );
+        }
+        assert_eq!(cache.usage(), 4 * BLOCK_SIZE as usize);
+        assert!(cache.get(1, 0).is_none());
+        assert!(cache.get(1, 4 * BLOCK_SIZE).is_some());
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        cache.insert(2, 0, Bytes::from("block"));
+        cache.evict_file(1);
+        assert_eq!(cache.usage(), 5);
+        assert!(cache.get(2, 0).is_some());
+    }
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join("arrowdb_block_cache_read");
+        let _ = std::fs::remove_file(&path);
+        let mut file_manager = FileManager::new(enums::RWMode::StdIO, FDManager::new(4));
+        let file = file_manager
+            .get_fileio_manager(path.to_str().unwrap(), 1)
+            .unwrap();
+        let data: Vec<u8> = (0..3 * BLOCK_SIZE).map(|i| (i % 251) as u8).collect();
+        file.write().write(&data, 0).unwrap();
+
+        let cache = BlockCache::new(1024 * 1024, 4);
+        let file = file.read();
+        let mut buf = vec![0u8; BLOCK_SIZE as usize + 10];
+        // a scan does not fill the cache
+        cache.read(1, file.as_ref(), &mut buf, 100, false).unwrap();
+        assert_eq!(cache.usage(), 0);
+
+        let n = cache.read(1, file.as_ref(), &mut buf, 100, true).unwrap();
+        assert_eq!(n, buf.len());
+        assert_eq!(buf, data[100..100 + buf.len()]);
+        assert_eq!(cache.usage(), 2 * BLOCK_SIZE as usize);
+        let misses = cache.misses();
+        cache.read(1, file.as_ref(), &mut buf, 200, true).unwrap();
+        assert_eq!(cache.misses(), misses);
+        assert_eq!(buf, data[200..200 + buf.len()]);
+
+        // a read past the end of the file is short
+        let mut buf = vec![0u8; 10];
+        let end = enums::MB - 4;
+        assert_eq!(
+            cache.read(1, file.as_ref(), &mut buf, end, true).unwrap(),
+            4
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use crate::enums;
+use crate::errors::DbError;
+use crate::fileio::{FDManager, FileIOManager};
+use log::error;
+use parking_lot::Mutex;
+use std::alloc::{self, Layout};
+use std::fs::{File, OpenOptions};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::prelude::FileExt;
+use std::sync::Arc;
+
+// offsets, lengths and memory of O_DIRECT io must be aligned to the logical block size,
+// 4KB covers the devices we run on.
+pub const ALIGN: usize = 4096;
+const WRITE_BUFFER_SIZE: usize = 64 * ALIGN;
+
+// AlignedBuf is a zeroed heap buffer aligned to ALIGN, its length is a multiple of ALIGN.
+pub struct AlignedBuf {
+    ptr: *mut u8,
+    len: usize,
+}
+
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    pub fn new(len: usize) -> Self {
+        let len = align_up(len.max(1) as u64) as usize;
+        let layout = Layout::from_size_align(len, ALIGN).unwrap();
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        AlignedBuf { ptr, len }
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, Layout::from_size_align(self.len, ALIGN).unwrap()) }
+    }
+}
+
+pub fn align_down(offset: u64) -> u64 {
+    offset - offset % ALIGN as u64
+}
+
+pub fn align_up(offset: u64) -> u64 {
+    align_down(offset + ALIGN as u64 - 1)
+}
+
+pub fn open_direct(path: &str) -> Result<File, DbError> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)?;
+    Ok(file)
+}
+
+// DirectFile bypasses the page cache, writes are gathered in an aligned buffer covering
+// consecutive blocks and written out when the buffer is full, when a write lands outside
+// of it, or on sync. reads see the buffered bytes.
+pub struct DirectFile {
+    pub file_path: String,
+    pub file_size_mb: u64,
+    pub fd_manager: Arc<Mutex<FDManager>>,
+    buf: AlignedBuf,
+    // aligned file offset of the first byte of buf
+    buf_offset: u64,
+    // bytes of buf holding file data, from its start
+    buf_len: usize,
+}
+
+impl DirectFile {
+    pub fn new(file_path: &str, file_size_mb: u64, fd_manager: Arc<Mutex<FDManager>>) -> Self {
+        DirectFile {
+            file_path: file_path.to_owned(),
+            file_size_mb,
+            fd_manager,
+            buf: AlignedBuf::new(WRITE_BUFFER_SIZE),
+            buf_offset: 0,
+            buf_len: 0,
+        }
+    }
+
+    fn with_file<T>(&self, f: impl FnOnce(&File) -> Result<T, DbError>) -> Result<T, DbError> {
+        let mut fd_manager = self.fd_manager.lock();
+        if let Some(fd) = fd_manager.fds_cache.get(&self.file_path) {
+            return f(fd);
+        }
+        let file = open_direct(&self.file_path)?;
+        super::preallocate(&file, self.file_size_mb)?;
+        let res = f(&file);
+        fd_manager.fds_cache.push(self.file_path.to_owned(), file);
+        res
+    }
+
+    // flush writes the buffered blocks out, the bytes of the last block past buf_len are
+    // read back from the file first so that they are not overwritten. the last partial
+    // block stays buffered for the following appends.
+    fn flush(&mut self) -> Result<(), DbError> {
+        if self.buf_len == 0 {
+            return Ok(());
+        }
+        let write_len = align_up(self.buf_len as u64) as usize;
+        let tail = align_down(self.buf_len as u64) as usize;
+        if tail < self.buf_len {
+            let mut block = AlignedBuf::new(ALIGN);
+            self.with_file(|fd| Ok(fd.read_at(&mut block, self.buf_offset + tail as u64)?))?;
+            self.buf[self.buf_len..write_len].copy_from_slice(&block[self.buf_len - tail..]);
+        }
+        let (buf, offset) = (&self.buf[..write_len], self.buf_offset);
+        self.with_file(|fd| Ok(fd.write_all_at(buf, offset)?))?;
+
+        self.buf.copy_within(tail..write_len, 0);
+        self.buf_offset += tail as u64;
+        self.buf_len -= tail;
+        Ok(())
+    }
+
+    // buffers tells if a write at offset goes to the buffer, it has to start in or right
+    // after the buffered bytes.
+    fn buffers(&self, offset: u64) -> bool {
+        offset >= self.buf_offset
+            && offset <= self.buf_offset + self.buf_len as u64
+            && offset < self.buf_offset + self.buf.len() as u64
+    }
+
+    // start_buffer points the empty buffer at the block of offset, loading the bytes of the
+    // block before offset.
+    fn start_buffer(&mut self, offset: u64) -> Result<(), DbError> {
+        self.buf_offset = align_down(offset);
+        self.buf_len = (offset - self.buf_offset) as usize;
+        if self.buf_len > 0 {
+            let mut block = AlignedBuf::new(ALIGN);
+            self.with_file(|fd| Ok(fd.read_at(&mut block, self.buf_offset)?))?;
+            self.buf[..self.buf_len].copy_from_slice(&block[..self.buf_len]);
+        }
+        Ok(())
+    }
+}
+
+impl FileIOManager for DirectFile {
+    fn write(&mut self, b: &[u8], offset: u64) -> Result<usize, DbError> {
+        let file_size = self.file_size_mb * enums::MB;
+        if offset >= file_size {
+            return Err(DbError::OffsetOutOfRange {
+                method: "write".to_owned(),
+                offset,
+            });
+        }
+        let b = &b[..b.len().min((file_size - offset) as usize)];
+        let mut written = 0;
+        while written < b.len() {
+            let offset = offset + written as u64;
+            if !self.buffers(offset) {
+                self.flush()?;
+                if !self.buffers(offset) {
+                    self.start_buffer(offset)?;
+                }
+            }
+
+            let start = (offset - self.buf_offset) as usize;
+            let n = (b.len() - written).min(self.buf.len() - start);
+            self.buf[start..start + n].copy_from_slice(&b[written..written + n]);
+            self.buf_len = self.buf_len.max(start + n);
+            written += n;
+            if self.buf_len == self.buf.len() {
+                self.flush()?;
+            }
+        }
+        Ok(written)
+    }
+
+    fn read(&self, b: &mut [u8], offset: u64) -> Result<usize, DbError> {
+        let file_size = self.file_size_mb * enums::MB;
+        if offset >= file_size {
+            return Err(DbError::OffsetOutOfRange {
+                method: "read".to_owned(),
+                offset,
+            });
+        }
+        let len = b.len().min((file_size - offset) as usize);
+        let start = align_down(offset);
+        let mut aligned = AlignedBuf::new((align_up(offset + len as u64) - start) as usize);
+        let read = self.with_file(|fd| Ok(fd.read_at(&mut aligned, start)?))?;
+        let skip = (offset - start) as usize;
+        let len = len.min(read.saturating_sub(skip));
+        b[..len].copy_from_slice(&aligned[skip..skip + len]);
+
+        // the buffered bytes are newer than the file
+        let buf_end = self.buf_offset + self.buf_len as u64;
+        let (from, to) = (
+            offset.max(self.buf_offset),
+            (offset + len as u64).min(buf_end),
+        );
+        if from < to {
+            let (from_b, from_buf) = ((from - offset) as usize, (from - self.buf_offset) as usize);
+            let n = (to - from) as usize;
+            b[from_b..from_b + n].copy_from_slice(&self.buf[from_buf..from_buf + n]);
+        }
+        Ok(len)
+    }
+
+    fn sync(&mut self) -> Result<bool, DbError> {
+        self.flush()?;
+        if let Some(fd) = self.fd_manager.lock().fds_cache.get(&self.file_path) {
+            fd.sync_all()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn sync_handle(&mut self) -> Result<Option<File>, DbError> {
+        self.flush()?;
+        self.with_file(|file| Ok(Some(file.try_clone()?)))
+    }
+
+    // release keeps the fd and the buffered bytes when they can not be written out, so that
+    // a later sync or release may retry.
+    fn release(&mut self) -> bool {
+        if let Err(err) = self.flush() {
+            error!(
+                "direct file {} failed to flush on release: {}",
+                self.file_path, err
+            );
+            return false;
+        }
+        self.buf_len = 0;
+        self.fd_manager
+            .lock()
+            .fds_cache
+            .pop(&self.file_path)
+            .is_some()
+    }
+}
+
+impl Drop for DirectFile {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!(
+                "direct file {} lost {} buffered bytes: {}",
+                self.file_path, self.buf_len, err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::num::NonZeroUsize;
+
+    fn direct_file(name: &str) -> DirectFile {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let fd_manager = Arc::new(Mutex::new(FDManager {
+            fds_cache: lru::LruCache::new(NonZeroUsize::new(2).unwrap()),
+            mem_files: HashMap::new(),
+        }));
+        DirectFile::new(path.to_str().unwrap(), 1, fd_manager)
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut file = direct_file("arrowdb_direct_write_read");
+        let data: Vec<u8> = (0..WRITE_BUFFER_SIZE * 2)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        // unaligned appends crossing blocks and the buffer size
+        let mut offset = 10;
+        for chunk in data.chunks(1000) {
+            assert_eq!(file.write(chunk, offset).unwrap(), chunk.len());
+            offset += chunk.len() as u64;
+        }
+        let mut buf = vec![0u8; data.len()];
+        assert_eq!(file.read(&mut buf, 10).unwrap(), data.len());
+        assert_eq!(buf, data);
+
+        // an overwrite before the buffer keeps the bytes around it
+        file.write(b"direct", 4095).unwrap();
+        file.sync().unwrap();
+        let mut buf = vec![0u8; 8];
+        file.read(&mut buf, 4094).unwrap();
+        assert_eq!(buf[0], data[4084]);
+        assert_eq!(&buf[1..7], b"direct");
+        assert_eq!(buf[7], data[4091]);
+
+        let mut buf = vec![0u8; 4];
+        file.read(&mut buf, 6).unwrap();
+        assert_eq!(buf, [0, 0, 0, 0]);
+        assert!(file.release());
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut file = direct_file("arrowdb_direct_bounds");
+        let size = enums::MB;
+        assert!(file.write(b"x", size).is_err());
+        assert_eq!(file.write(b"tail", size - 2).unwrap(), 2);
+        let mut buf = vec![0u8; 4];
+        assert_eq!(file.read(&mut buf, size - 2).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ta");
+        assert!(file.read(&mut buf, size).is_err());
+    }
+
+    #[test]
+    fn test_release_failed_flush() {
+        let dir = std::env::temp_dir().join("arrowdb_direct_release");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        let fd_manager = Arc::new(Mutex::new(FDManager {
+            fds_cache: lru::LruCache::new(NonZeroUsize::new(2).unwrap()),
+            mem_files: HashMap::new(),
+        }));
+        let mut file = DirectFile::new(path.to_str().unwrap(), 1, fd_manager);
+        file.write(b"buffered", 0).unwrap();
+
+        // the file can not be opened again, the buffered bytes are kept
+        file.fd_manager.lock().fds_cache.clear();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!file.release());
+        assert!(file.sync().is_err());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(file.sync().unwrap());
+        let mut buf = vec![0u8; 8];
+        file.read(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"buffered");
+        assert!(file.release());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
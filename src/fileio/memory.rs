@@ -0,0 +1,88 @@
+use crate::enums;
+use crate::errors::DbError;
+use crate::fileio::FileIOManager;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+// MemData is the content of an in-memory file. the file has a length like a preallocated
+// file on disk, but only the bytes up to the last one written are allocated, the bytes past
+// them read as zeros.
+#[derive(Debug, Default)]
+pub struct MemData {
+    data: Vec<u8>,
+    len: u64,
+    // tells if the file is open, as StdFile tells if its fd is cached. it is shared by the
+    // handles of the file like the fd cache is.
+    open: bool,
+}
+
+impl MemData {
+    // set_len resizes the file like File::set_len.
+    pub fn set_len(&mut self, len: u64) {
+        self.data.truncate(len as usize);
+        self.len = len;
+    }
+
+    // preallocate extends the file to len, a file written past it is not cut.
+    pub fn preallocate(&mut self, len: u64) {
+        if self.len < len {
+            self.set_len(len);
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+}
+
+// MemFile keeps a file in memory, it behaves like StdFile without touching the disk. the
+// files are held by the FDManager of the db so that they live as long as it and are found
+// again when opened by path.
+pub struct MemFile {
+    pub file_path: String,
+    pub file_size_mb: u64,
+    pub data: Arc<RwLock<MemData>>,
+}
+
+impl FileIOManager for MemFile {
+    fn write(&mut self, b: &[u8], offset: u64) -> Result<usize, DbError> {
+        if offset >= self.file_size_mb * enums::MB {
+            return Err(DbError::OffsetOutOfRange {
+                method: "write".to_owned(),
+                offset,
+            });
+        }
+        let mut file = self.data.write();
+        let (start, end) = (offset as usize, offset as usize + b.len());
+        if file.data.len() < end {
+            file.data.resize(end, 0);
+        }
+        file.data[start..end].copy_from_slice(b);
+        file.len = file.len.max(end as u64);
+        file.open = true;
+        Ok(b.len())
+    }
+
+    fn read(&self, b: &mut [u8], offset: u64) -> Result<usize, DbError> {
+        let file = self.data.read();
+        if offset >= file.len {
+            return Ok(0);
+        }
+        let n = b.len().min((file.len - offset) as usize);
+        let start = offset as usize;
+        let written = file.data.len().saturating_sub(start).min(n);
+        if written > 0 {
+            b[..written].copy_from_slice(&file.data[start..start + written]);
+        }
+        b[written..n].fill(0);
+        Ok(n)
+    }
+
+    fn sync(&mut self) -> Result<bool, DbError> {
+        Ok(self.data.read().open)
+    }
+
+    fn release(&mut self) -> bool {
+        std::mem::replace(&mut self.data.write().open, false)
+    }
+}
@@ -3,55 +3,101 @@ use crate::errors::DbError;
 use crate::fileio::{FDManager, FileIOManager};
 use memmap::MmapMut;
 use parking_lot::Mutex;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
 use std::sync::Arc;
 
+// a new file is mapped with this size, the mapping then doubles as the file fills, growing
+// by at most file_size_mb at a time.
+pub const MMAP_INITIAL_SIZE: u64 = 64 * 1024;
+
+// map_file maps file, extending it to size first if it is shorter.
+pub fn map_file(file: &File, size: u64) -> Result<MmapMut, DbError> {
+    if file.metadata()?.len() < size {
+        file.set_len(size)?;
+    }
+    Ok(unsafe { MmapMut::map_mut(file)? })
+}
+
 pub struct MMapFile {
     pub file_path: String,
     pub file_size_mb: u64,
     pub fd_manager: Arc<Mutex<FDManager>>,
     pub mmap: Option<MmapMut>,
+    // bytes of the file holding data, the file is truncated to it when sealed
+    pub len: u64,
+}
+
+impl MMapFile {
+    fn with_file<T>(&self, f: impl FnOnce(&File) -> Result<T, DbError>) -> Result<T, DbError> {
+        let mut fd_manager = self.fd_manager.lock();
+        if let Some(fd) = fd_manager.fds_cache.get(&self.file_path) {
+            return f(fd);
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.file_path)?;
+        let res = f(&file);
+        fd_manager.fds_cache.push(self.file_path.to_owned(), file);
+        res
+    }
+
+    // grow remaps the file so that it holds at least end bytes.
+    fn grow(&mut self, end: u64) -> Result<(), DbError> {
+        let Some(mmap) = self.mmap.take() else {
+            return Err(mmap_not_found());
+        };
+        mmap.flush()?;
+        let max_step = (self.file_size_mb * enums::MB).max(MMAP_INITIAL_SIZE);
+        let mut size = (mmap.len() as u64).max(MMAP_INITIAL_SIZE);
+        drop(mmap);
+        while size < end {
+            size += size.min(max_step);
+        }
+        self.mmap = Some(self.with_file(|file| map_file(file, size))?);
+        Ok(())
+    }
+}
+
+fn mmap_not_found() -> DbError {
+    DbError::IOError(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "mmap file not found",
+    ))
 }
 
 impl FileIOManager for MMapFile {
     fn write(&mut self, b: &[u8], offset: u64) -> Result<usize, DbError> {
-        if offset >= self.file_size_mb * enums::MB {
-            return Err(DbError::OffsetOutOfRange {
-                method: "write".to_owned(),
-                offset,
-            });
+        let Some(mapped) = self.mmap.as_ref().map(|mmap| mmap.len() as u64) else {
+            return Err(mmap_not_found());
+        };
+        let end = offset + b.len() as u64;
+        if end > mapped {
+            self.grow(end)?;
         }
         if let Some(mmap) = self.mmap.as_mut() {
-            (&mut mmap[offset as usize..]).write_all(b)?;
+            mmap[offset as usize..end as usize].copy_from_slice(b);
+            self.len = self.len.max(end);
             return Ok(b.len());
         }
-        Err(DbError::IOError(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "mmap file not found",
-        )))
+        Err(mmap_not_found())
     }
 
     fn read(&self, b: &mut [u8], offset: u64) -> Result<usize, DbError> {
-        if offset >= self.file_size_mb * enums::MB {
+        let Some(mmap) = self.mmap.as_ref() else {
+            return Err(mmap_not_found());
+        };
+        if offset >= mmap.len() as u64 {
             return Err(DbError::OffsetOutOfRange {
                 method: "read".to_owned(),
                 offset,
             });
         }
-        if let Some(mmap) = self.mmap.as_ref() {
-            let end = if offset as usize + b.len() <= mmap.len() {
-                offset as usize + b.len()
-            } else {
-                mmap.len()
-            };
-
-            b.copy_from_slice(&mmap[offset as usize..end]);
-            return Ok(b.len());
-        }
-        Err(DbError::IOError(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "mmap file not found",
-        )))
+        let n = b.len().min(mmap.len() - offset as usize);
+        b[..n].copy_from_slice(&mmap[offset as usize..offset as usize + n]);
+        Ok(n)
     }
 
     fn sync(&mut self) -> Result<bool, DbError> {
@@ -62,6 +108,36 @@ impl FileIOManager for MMapFile {
         Ok(false)
     }
 
+    // the mapping shares the pages of the file, syncing the file writes them back.
+    fn sync_handle(&mut self) -> Result<Option<File>, DbError> {
+        if self.mmap.is_none() {
+            return Ok(None);
+        }
+        self.with_file(|file| Ok(Some(file.try_clone()?)))
+    }
+
+    // seal truncates the file to the bytes written, the file is mapped again with that
+    // size and grows back if written.
+    fn seal(&mut self) -> Result<bool, DbError> {
+        let Some(mmap) = self.mmap.take() else {
+            return Ok(false);
+        };
+        mmap.flush()?;
+        drop(mmap);
+        let len = self.len;
+        let mmap = self.with_file(|file| {
+            file.set_len(len)?;
+            file.sync_all()?;
+            map_file(file, len.max(1))
+        })?;
+        self.mmap = Some(mmap);
+        Ok(true)
+    }
+
+    fn set_data_len(&mut self, len: u64) {
+        self.len = len;
+    }
+
     fn release(&mut self) -> bool {
         self.mmap = None;
         self.fd_manager
@@ -71,3 +147,55 @@ impl FileIOManager for MMapFile {
             .is_some()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn test_grow_seal() {
+        let path = std::env::temp_dir().join("arrowdb_mmap_grow_seal");
+        let _ = std::fs::remove_file(&path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .unwrap();
+        let mmap = map_file(&file, MMAP_INITIAL_SIZE).unwrap();
+        let mut fds_cache = lru::LruCache::new(NonZeroUsize::new(2).unwrap());
+        let path = path.to_str().unwrap().to_owned();
+        fds_cache.push(path.clone(), file);
+        let mut file = MMapFile {
+            file_path: path.clone(),
+            file_size_mb: 1,
+            fd_manager: Arc::new(Mutex::new(FDManager {
+                fds_cache,
+                mem_files: HashMap::new(),
+            })),
+            mmap: Some(mmap),
+            len: 0,
+        };
+        let file_len = || std::fs::metadata(&path).unwrap().len();
+        assert_eq!(file_len(), MMAP_INITIAL_SIZE);
+
+        // doubles up to 1MB, then grows 1MB at a time past file_size_mb
+        file.write(b"head", 0).unwrap();
+        file.write(b"tail", 3 * enums::MB).unwrap();
+        assert_eq!(file_len(), 4 * enums::MB);
+        assert_eq!(file.len, 3 * enums::MB + 4);
+        let mut buf = vec![0u8; 4];
+        file.read(&mut buf, 0).unwrap();
+        assert_eq!(buf, b"head");
+
+        assert!(file.seal().unwrap());
+        assert_eq!(file_len(), 3 * enums::MB + 4);
+        assert_eq!(file.read(&mut buf, 3 * enums::MB + 2).unwrap(), 2);
+        assert_eq!(&buf[..2], b"il");
+        assert!(file.read(&mut buf, 3 * enums::MB + 4).is_err());
+        assert!(file.release());
+    }
+}
@@ -1,20 +1,38 @@
+pub mod block_cache;
+mod direct;
+mod memory;
 mod mmap;
+pub mod rate_limiter;
 mod std_file;
 
 use crate::enums;
 use crate::errors::DbError;
-use memmap::MmapMut;
+use direct::DirectFile;
+use memory::{MemData, MemFile};
 use mmap::MMapFile;
-use once_cell::sync::OnceCell;
 use parking_lot::{Mutex, RwLock};
 use std::fs::OpenOptions;
-use std::{fs::File, num::NonZeroUsize, sync::Arc};
+use std::{collections::HashMap, fs::File, num::NonZeroUsize, sync::Arc};
 use std_file::StdFile;
 
-pub trait FileIOManager {
+pub trait FileIOManager: Send + Sync {
     fn write(&mut self, b: &[u8], offset: u64) -> Result<usize, DbError>;
     fn read(&self, b: &mut [u8], offset: u64) -> Result<usize, DbError>;
     fn sync(&mut self) -> Result<bool, DbError>;
+    // seal is called once the file is full, files that grow give back the space they do
+    // not use.
+    fn seal(&mut self) -> Result<bool, DbError> {
+        self.sync()
+    }
+    // set_data_len tells the file how many of its bytes hold data, it is set by the owner
+    // recovering the file as files that grow are longer than their data until sealed.
+    fn set_data_len(&mut self, _len: u64) {}
+    // sync_handle hands the bytes written to the os and returns a handle of the file that
+    // syncs them, so that the sync runs without the file locked. none if the file is only
+    // synced by sync.
+    fn sync_handle(&mut self) -> Result<Option<File>, DbError> {
+        Ok(None)
+    }
     fn release(&mut self) -> bool;
 }
 
@@ -23,22 +41,22 @@ pub type FileIOManagerObject = Arc<RwLock<Box<dyn FileIOManager>>>;
 #[derive(Debug)]
 pub struct FDManager {
     fds_cache: lru::LruCache<String, File>,
+    // the files of RWMode::MemIO by path, they are dropped with the FDManager
+    mem_files: HashMap<String, Arc<RwLock<MemData>>>,
 }
 
-static GLOBALFDMANAGER: OnceCell<Arc<Mutex<FDManager>>> = OnceCell::new();
+// default size of the fd cache when the option leaves it at 0
+pub const DEFAULT_FD_CACHE_SIZE: usize = 1024;
 
 impl FDManager {
-    pub fn set_fd_manager(fds_cache_cap: NonZeroUsize) {
-        GLOBALFDMANAGER
-            .set(Arc::new(Mutex::new(FDManager {
-                fds_cache: lru::LruCache::new(fds_cache_cap),
-            })))
-            .unwrap();
-    }
-
-    pub fn get_fd_manager() -> Arc<Mutex<FDManager>> {
-        let fd_manager = GLOBALFDMANAGER.get().unwrap();
-        Arc::clone(fd_manager)
+    // new creates the fd cache of a db, several dbs may share one by cloning the Arc.
+    pub fn new(fds_cache_cap: usize) -> Arc<Mutex<FDManager>> {
+        let fds_cache_cap = NonZeroUsize::new(fds_cache_cap)
+            .unwrap_or(NonZeroUsize::new(DEFAULT_FD_CACHE_SIZE).unwrap());
+        Arc::new(Mutex::new(FDManager {
+            fds_cache: lru::LruCache::new(fds_cache_cap),
+            mem_files: HashMap::new(),
+        }))
     }
 }
 
@@ -48,10 +66,10 @@ pub struct FileManager {
 }
 
 impl FileManager {
-    pub fn new(rw_mode: enums::RWMode) -> Self {
+    pub fn new(rw_mode: enums::RWMode, fd_manager: Arc<Mutex<FDManager>>) -> Self {
         FileManager {
             rw_mode,
-            fd_manager: FDManager::get_fd_manager(),
+            fd_manager,
         }
     }
 
@@ -64,14 +82,15 @@ impl FileManager {
         match self.rw_mode {
             enums::RWMode::StdIO => {
                 if let Some(cache_fd) = fd_manager.fds_cache.get(path) {
-                    cache_fd.set_len(file_size_mb * enums::MB)?;
+                    preallocate(cache_fd, file_size_mb)?;
                 } else {
                     let file = OpenOptions::new()
                         .read(true)
                         .write(true)
                         .create(true)
+                        .truncate(false)
                         .open(&path)?;
-                    file.set_len(file_size_mb * enums::MB)?;
+                    preallocate(&file, file_size_mb)?;
                     fd_manager.fds_cache.put(path.to_owned(), file);
                 }
                 Ok(Arc::new(RwLock::new(Box::new(StdFile {
@@ -81,29 +100,85 @@ impl FileManager {
                 }))))
             }
             enums::RWMode::MMap => {
-                let mmap: MmapMut;
-                if let Some(file) = fd_manager.fds_cache.get(path) {
-                    file.set_len(file_size_mb * enums::MB)?;
-                    mmap = unsafe { MmapMut::map_mut(file)? };
-                } else {
+                // the file is not preallocated, it is mapped with the data it holds and grows
+                // as it is written. a file left unsealed is mapped past its data, the owner
+                // sets its data len once recovered
+                if fd_manager.fds_cache.get(path).is_none() {
                     let file = OpenOptions::new()
                         .read(true)
                         .write(true)
                         .create(true)
+                        .truncate(false)
                         .open(&path)?;
-                    file.set_len(file_size_mb * enums::MB)?;
-                    mmap = unsafe { MmapMut::map_mut(&file)? };
                     fd_manager.fds_cache.push(path.to_owned(), file);
                 }
+                let file = fd_manager.fds_cache.get(path).unwrap();
+                let len = file.metadata()?.len();
+                let size = if len == 0 {
+                    mmap::MMAP_INITIAL_SIZE
+                } else {
+                    len
+                };
+                let mmap = mmap::map_file(file, size)?;
                 Ok(Arc::new(RwLock::new(Box::new(MMapFile {
                     file_path: path.to_owned(),
                     file_size_mb,
                     fd_manager: self.fd_manager.clone(),
                     mmap: Some(mmap),
+                    len,
+                }))))
+            }
+            enums::RWMode::DirectIO => {
+                if let Some(cache_fd) = fd_manager.fds_cache.get(path) {
+                    preallocate(cache_fd, file_size_mb)?;
+                } else {
+                    let file = direct::open_direct(path)?;
+                    preallocate(&file, file_size_mb)?;
+                    fd_manager.fds_cache.put(path.to_owned(), file);
+                }
+                Ok(Arc::new(RwLock::new(Box::new(DirectFile::new(
+                    path,
+                    file_size_mb,
+                    self.fd_manager.clone(),
+                )))))
+            }
+            enums::RWMode::MemIO => {
+                let data = fd_manager.mem_files.entry(path.to_owned()).or_default();
+                let mut file = data.write();
+                file.preallocate(file_size_mb * enums::MB);
+                file.open();
+                drop(file);
+                Ok(Arc::new(RwLock::new(Box::new(MemFile {
+                    file_path: path.to_owned(),
+                    file_size_mb,
+                    data: Arc::clone(data),
                 }))))
             }
         }
     }
+
+    // remove closes the file at path and deletes it, the handles still held on it must not
+    // be used anymore.
+    pub fn remove(&mut self, path: &str) -> Result<(), DbError> {
+        let mut fd_manager = self.fd_manager.lock();
+        fd_manager.fds_cache.pop(path);
+        if let enums::RWMode::MemIO = self.rw_mode {
+            fd_manager.mem_files.remove(path);
+            return Ok(());
+        }
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// preallocate extends file to file_size_mb, a file written past it is not cut.
+fn preallocate(file: &File, file_size_mb: u64) -> Result<(), DbError> {
+    if file.metadata()?.len() < file_size_mb * enums::MB {
+        file.set_len(file_size_mb * enums::MB)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -112,9 +187,9 @@ mod tests {
 
     #[test]
     fn test_file() {
-        FDManager::set_fd_manager(NonZeroUsize::new(10).unwrap());
+        let fd_manager = FDManager::new(10);
 
-        let mut std_file_manager = FileManager::new(enums::RWMode::StdIO);
+        let mut std_file_manager = FileManager::new(enums::RWMode::StdIO, fd_manager.clone());
 
         let mut temp = project_root::get_project_root().unwrap();
         temp = temp.join("tempdata/std");
@@ -136,7 +211,7 @@ mod tests {
         assert_eq!(file.release(), true);
         assert_eq!(file.release(), false);
 
-        let mut mmap_file_manager = FileManager::new(enums::RWMode::MMap);
+        let mut mmap_file_manager = FileManager::new(enums::RWMode::MMap, fd_manager);
 
         let mut temp = project_root::get_project_root().unwrap();
         temp = temp.join("tempdata/mmap");
@@ -158,4 +233,63 @@ mod tests {
         assert_eq!(file.release(), true);
         assert_eq!(file.release(), false);
     }
+
+    #[test]
+    fn test_mem_file() {
+        let fd_manager = FDManager::new(10);
+        let mut mem_file_manager = FileManager::new(enums::RWMode::MemIO, fd_manager.clone());
+        let mem_file_io_manager_ob = mem_file_manager.get_fileio_manager("mem", 1).unwrap();
+
+        let mut file = mem_file_io_manager_ob.write();
+        assert_eq!(file.write(b"mem", 10).unwrap(), 3);
+        assert!(file.sync().unwrap());
+        let mut buf = vec![1u8; 16];
+        assert_eq!(file.read(&mut buf, 0).unwrap(), 16);
+        assert_eq!(&buf[..13], b"\0\0\0\0\0\0\0\0\0\0mem");
+        assert_eq!(buf[13..], [0, 0, 0]);
+        // reads stop at the file size, writes fail past it
+        assert_eq!(file.read(&mut buf, enums::MB - 4).unwrap(), 4);
+        assert_eq!(file.read(&mut buf, enums::MB).unwrap(), 0);
+        assert!(file.write(b"mem", enums::MB).is_err());
+        assert_eq!(file.release(), true);
+        assert_eq!(file.release(), false);
+        drop(file);
+
+        // the file is found again by path, not by the other fd managers
+        let file = FileManager::new(enums::RWMode::MemIO, fd_manager)
+            .get_fileio_manager("mem", 1)
+            .unwrap();
+        let mut buf = vec![0u8; 3];
+        file.read().read(&mut buf, 10).unwrap();
+        assert_eq!(buf, b"mem");
+        let file = FileManager::new(enums::RWMode::MemIO, FDManager::new(10))
+            .get_fileio_manager("mem", 1)
+            .unwrap();
+        file.read().read(&mut buf, 10).unwrap();
+        assert_eq!(buf, [0, 0, 0]);
+        assert!(!std::path::Path::new("mem").exists());
+    }
+
+    #[test]
+    fn test_mem_file_handles() {
+        let mut mem_file_manager = FileManager::new(enums::RWMode::MemIO, FDManager::new(10));
+        let file1 = mem_file_manager.get_fileio_manager("mem", 1).unwrap();
+        let file2 = mem_file_manager.get_fileio_manager("mem", 1).unwrap();
+        file1.write().write(b"mem", 0).unwrap();
+
+        // the handles of a path share its open state like the fd cache
+        assert!(file2.write().release());
+        assert!(!file1.write().release());
+        assert!(!file1.write().sync().unwrap());
+        file2.write().write(b"mem", 3).unwrap();
+        assert!(file1.write().sync().unwrap());
+
+        mem_file_manager.remove("mem").unwrap();
+        let file = mem_file_manager.get_fileio_manager("mem", 1).unwrap();
+        let mut buf = vec![1u8; 6];
+        file.read().read(&mut buf, 0).unwrap();
+        assert_eq!(buf, [0; 6]);
+        mem_file_manager.remove("mem").unwrap();
+        assert!(mem_file_manager.fd_manager.lock().mem_files.is_empty());
+    }
 }
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::errors::DbError;
+use crate::fileio::FileIOManager;
+
+struct Bucket {
+    // bytes that can be written without waiting, negative when requests wait for the refill
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+// RateLimiter is a token bucket limiting the bytes per second written by the background
+// workers, it refills continuously and holds at most a second of writes. a request larger
+// than the tokens left takes them in advance and waits for the refill, so the requests that
+// follow wait after it. a rate of 0 disables the limit.
+pub struct RateLimiter {
+    bytes_per_sec: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter {
+            bytes_per_sec: AtomicU64::new(bytes_per_sec),
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_sec as f64,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    // set_rate changes the limit at runtime, the requests already waiting keep their wait.
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        self.bytes_per_sec.store(bytes_per_sec, Ordering::Relaxed);
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bytes_per_sec.load(Ordering::Relaxed)
+    }
+
+    // request blocks until bytes can be written.
+    pub fn request(&self, bytes: usize) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+
+    // write writes b to file at offset once the limiter allows it.
+    pub fn write(
+        &self,
+        file: &mut dyn FileIOManager,
+        b: &[u8],
+        offset: u64,
+    ) -> Result<usize, DbError> {
+        self.request(b.len());
+        file.write(b, offset)
+    }
+
+    // reserve takes bytes tokens at now and returns how long to wait for them.
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let rate = self.rate() as f64;
+        let mut bucket = self.bucket.lock();
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        bucket.refilled_at = now.max(bucket.refilled_at);
+        if rate == 0.0 {
+            return Duration::ZERO;
+        }
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-bucket.tokens / rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        assert!(limiter.reserve(600, start).is_zero());
+        assert_eq!(limiter.reserve(600, start), Duration::from_millis(200));
+        // the next request waits after the previous one
+        assert_eq!(limiter.reserve(100, start), Duration::from_millis(300));
+        assert_eq!(
+            limiter.reserve(100, start + Duration::from_millis(300)),
+            Duration::from_millis(100)
+        );
+        // the bucket holds at most a second of writes
+        assert!(limiter
+            .reserve(1000, start + Duration::from_secs(10))
+            .is_zero());
+        assert!(!limiter
+            .reserve(1, start + Duration::from_secs(10))
+            .is_zero());
+
+        limiter.set_rate(0);
+        assert!(limiter
+            .reserve(usize::MAX, start + Duration::from_secs(10))
+            .is_zero());
+    }
+
+    #[test]
+    fn test_request() {
+        let limiter = RateLimiter::new(10_000);
+        let start = Instant::now();
+        limiter.request(10_000);
+        limiter.request(1_000);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -2,7 +2,7 @@ use crate::enums;
 use crate::errors::DbError;
 use crate::fileio::{FDManager, FileIOManager};
 use parking_lot::Mutex;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::os::unix::prelude::FileExt;
 use std::sync::Arc;
 
@@ -29,8 +29,9 @@ impl FileIOManager for StdFile {
                 .read(true)
                 .write(true)
                 .create(true)
+                .truncate(false)
                 .open(&self.file_path)?;
-            file.set_len(self.file_size_mb * enums::MB)?;
+            super::preallocate(&file, self.file_size_mb)?;
             let size = file.write_at(b, offset)?;
             fd_manager.fds_cache.push(self.file_path.to_owned(), file);
             Ok(size)
@@ -47,8 +48,9 @@ impl FileIOManager for StdFile {
                 .read(true)
                 .write(true)
                 .create(true)
+                .truncate(false)
                 .open(&self.file_path)?;
-            file.set_len(self.file_size_mb * enums::MB)?;
+            super::preallocate(&file, self.file_size_mb)?;
             let size = file.read_at(b, offset)?;
             Ok(size)
         }
@@ -62,6 +64,12 @@ impl FileIOManager for StdFile {
         return Ok(false);
     }
 
+    fn sync_handle(&mut self) -> Result<Option<File>, DbError> {
+        let mut fd_manager = self.fd_manager.lock();
+        let fd = fd_manager.fds_cache.get(&self.file_path);
+        Ok(fd.map(File::try_clone).transpose()?)
+    }
+
     fn release(&mut self) -> bool {
         self.fd_manager
             .lock()
@@ -0,0 +1,154 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crc::{Crc, CRC_32_ISCSI};
+
+use crate::errors::DbError;
+
+pub const DEFAULT_BITS_PER_KEY: usize = 10;
+
+// BloomFilter tells if a key may be in a sealed data file, a miss means the key is not in
+// the file so a lookup does not have to read it.
+//
+//  encoded: | crc u32 | probes u8 | bits |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    probes: u8,
+}
+
+impl BloomFilter {
+    pub fn new(keys: usize, bits_per_key: usize) -> Self {
+        let bits_per_key = bits_per_key.max(1);
+        // ln(2) * bits per key probes minimize the false positive rate
+        let probes = ((bits_per_key as f64 * 0.69) as usize).clamp(1, 30) as u8;
+        let bits = (keys * bits_per_key).max(64);
+        BloomFilter {
+            bits: vec![0; bits.div_ceil(8)],
+            probes,
+        }
+    }
+
+    // build creates the filter of the keys of a data file.
+    pub fn build<'a>(keys: impl ExactSizeIterator<Item = &'a [u8]>, bits_per_key: usize) -> Self {
+        let mut filter = BloomFilter::new(keys.len(), bits_per_key);
+        keys.for_each(|key| filter.insert(key));
+        filter
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        let nbits = self.bits.len() as u64 * 8;
+        for bit in probe_bits(key, self.probes, nbits) {
+            self.bits[(bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        let nbits = self.bits.len() as u64 * 8;
+        probe_bits(key, self.probes, nbits)
+            .all(|bit| self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0; 5 + self.bits.len()];
+        buf[4] = self.probes;
+        buf[5..].copy_from_slice(&self.bits);
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI).checksum(&buf[4..]);
+        buf[0..4].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, DbError> {
+        if buf.len() < 6 {
+            return Err(filter_error("bloom filter too short"));
+        }
+        let crc = Crc::<u32>::new(&CRC_32_ISCSI).checksum(&buf[4..]);
+        if crc != u32::from_le_bytes(buf[0..4].try_into().unwrap()) {
+            return Err(filter_error("bloom filter crc invalid"));
+        }
+        Ok(BloomFilter {
+            bits: buf[5..].to_vec(),
+            probes: buf[4],
+        })
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), DbError> {
+        fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DbError> {
+        BloomFilter::decode(&fs::read(path)?)
+    }
+}
+
+// filter_path is the path of the filter of a data file in dir, the filters dir of a bucket
+// as each bucket has its own filter of the file.
+pub fn filter_path(dir: &Path, file_id: u32) -> PathBuf {
+    dir.join(format!("{}.bloom", file_id))
+}
+
+fn filter_error(msg: &str) -> DbError {
+    DbError::IOError(std::io::Error::new(std::io::ErrorKind::InvalidData, msg))
+}
+
+// probe_bits derives the probes from a single hash by double hashing, the hash has to stay
+// the same across versions since filters are stored.
+fn probe_bits(key: &[u8], probes: u8, nbits: u64) -> impl Iterator<Item = u64> {
+    let mut h = fnv1a(key);
+    let delta = h.rotate_right(17) | 1;
+    (0..probes).map(move |_| {
+        let bit = h % nbits;
+        h = h.wrapping_add(delta);
+        bit
+    })
+}
+
+fn fnv1a(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(range: std::ops::Range<usize>) -> Vec<Vec<u8>> {
+        range.map(|i| format!("key{}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_may_contain() {
+        let present = keys(0..1000);
+        let filter = BloomFilter::build(present.iter().map(|key| key.as_slice()), 10);
+        assert!(present.iter().all(|key| filter.may_contain(key)));
+        let false_positives = keys(1000..11000)
+            .iter()
+            .filter(|key| filter.may_contain(key))
+            .count();
+        // about 1% with 10 bits per key
+        assert!(false_positives < 300, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let filter = BloomFilter::build(keys(0..10).iter().map(|key| key.as_slice()), 8);
+        let mut buf = filter.encode();
+        assert_eq!(BloomFilter::decode(&buf).unwrap(), filter);
+        buf[6] ^= 1;
+        assert!(BloomFilter::decode(&buf).is_err());
+        assert!(BloomFilter::decode(&buf[..3]).is_err());
+    }
+
+    #[test]
+    fn test_store_load() {
+        let dir = std::env::temp_dir().join("arrowdb_bloom");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = filter_path(&dir, 7);
+        let filter = BloomFilter::build(keys(0..10).iter().map(|key| key.as_slice()), 10);
+        filter.store(&path).unwrap();
+        assert_eq!(BloomFilter::load(&path).unwrap(), filter);
+        assert!(path.to_str().unwrap().ends_with("7.bloom"));
+    }
+}
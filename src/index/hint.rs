@@ -45,7 +45,18 @@ impl Hint {
     }
 
     pub fn decode(buf: &[u8]) -> Result<Self, DbError> {
-        let meta = Meta::parse_entry_header_buf(&buf[..ENTRYHEADERSIZE]);
+        let size_at = |at: usize| buf.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+        let size = size_at(12)
+            .zip(size_at(26))
+            .map(|(key_size, bucket_size)| ENTRYHEADERSIZE + bucket_size + key_size + 8);
+        if size != Some(buf.len()) {
+            return Err(DbError::EntryDecodeError {
+                bucket: "".to_owned(),
+                key: "".to_owned(),
+                msg: format!("hint of {} bytes does not match its header", buf.len()),
+            });
+        }
+        let meta = Meta::parse_entry_header_buf(buf);
 
         let file_id = u32::from_le_bytes(buf[0..4].try_into().unwrap());
         let key = buf[(ENTRYHEADERSIZE + meta.bucket_size as usize)
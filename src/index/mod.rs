@@ -1,11 +1,13 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 
 use bytes::{BufMut, Bytes};
 
-use crate::{data::entry::Entry, datatypes::{list::List, set::Set, sortedset::SortedSet}, errors::DbError};
-use self::hint::Hint;
-use std::ops::Bound::Included;
+use crate::{data::{entry::Entry, ENTRYHEADERSIZE}, datatypes::{list::List, set::Set, sortedset::SortedSet}, enums::ListDirection, errors::DbError};
+use self::{bloom::BloomFilter, hint::Hint};
+use std::ops::Bound::{self, Included};
 
+pub mod bloom;
 mod hint;
 
 #[derive(Debug, Clone, Default)]
@@ -19,27 +21,104 @@ pub struct Index {
     kvs: BTreeMap<String, Record>,
     lists: List,
     sets: Set,
-    sorted_sets: SortedSet
+    sorted_sets: SortedSet,
+    // bloom filters of the sealed data files by file id
+    filters: HashMap<u32, BloomFilter>,
 }
 
 impl Record {
+    // | hint size u64 | hint | entry |
     pub fn encode(&self) -> Vec<u8> {
         let mut hint_b = self.hint.encode();
         let mut entry_b = self.entry.encode();
-        hint_b.put_slice(&entry_b);
-        let mut res = vec![0; 8];
-        let value_start_index = hint_b.len() as u64;
-        res[0..8].copy_from_slice(&value_start_index.to_le_bytes());
+        let mut res = Vec::with_capacity(8 + hint_b.len() + entry_b.len());
+        res.put_u64_le(hint_b.len() as u64);
         res.append(&mut hint_b);
         res.append(&mut entry_b);
         res
     }
+
+    pub fn decode(value: &[u8]) -> Result<Self, DbError> {
+        let hint_end = value
+            .get(0..8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .and_then(|size| usize::try_from(size).ok())
+            .and_then(|size| size.checked_add(8))
+            .filter(|end| *end <= value.len())
+            .ok_or_else(|| record_decode_error("short record"))?;
+        let mut record = Record { hint: Hint::decode(&value[8..hint_end])?, entry: Entry::default()};
+        if hint_end < value.len() {
+            record.entry = Entry::decode(&value[hint_end..])?;
+        }
+        Ok(record)
+    }
+
+    // size is the bytes of the record in its data file, known from its hint.
+    pub fn size(&self) -> usize {
+        let meta = &self.hint.meta;
+        8 + self.hint.size() + ENTRYHEADERSIZE + (meta.bucket_size + meta.key_size + meta.value_size) as usize
+    }
+
+    // held is false for a record loaded without its entry, its value is then read from its data file.
+    pub fn held(&self) -> bool {
+        !self.entry.key.is_empty()
+    }
+
+    // rekey points the record at another key, as when it is moved between lists
+    pub fn rekey(&mut self, key: &str) {
+        let key = Bytes::from(key.to_owned());
+        self.hint.key = key.clone();
+        self.hint.meta.key_size = key.len() as u32;
+        self.entry.key = key;
+        self.entry.meta.key_size = self.entry.key.len() as u32;
+    }
+}
+
+fn record_decode_error(msg: &str) -> DbError {
+    DbError::EntryDecodeError {
+        bucket: "".to_owned(),
+        key: "".to_owned(),
+        msg: msg.to_owned(),
+    }
 }
 
 impl Index {
     pub fn get(&self, key: &str) -> Option<&Record> {
         self.kvs.get(key)
     }
+
+    // may_contain is checked before looking a key up, false means the key is not in the file. files without a filter,
+    // the ones still written to included, may contain any key.
+    pub fn may_contain(&self, file_id: u32, key: &[u8]) -> bool {
+        self.filters.get(&file_id).is_none_or(|filter| filter.may_contain(key))
+    }
+
+    // seal_file builds the filter of the keys of the bucket of the index in a data file once it is full, and stores it in
+    // dir, the filters dir of the bucket (see DataFiles::filters_dir).
+    pub fn seal_file<'a>(&mut self, dir: &Path, file_id: u32, keys: impl ExactSizeIterator<Item = &'a [u8]>, bits_per_key: usize) -> Result<(), DbError> {
+        let filter = BloomFilter::build(keys, bits_per_key);
+        filter.store(&bloom::filter_path(dir, file_id))?;
+        self.filters.insert(file_id, filter);
+        Ok(())
+    }
+
+    // load_filters loads the filters stored in dir, the filters dir of the bucket, when the db is opened. returns how
+    // many were loaded.
+    pub fn load_filters(&mut self, dir: &Path) -> Result<usize, DbError> {
+        let mut loaded = 0;
+        for file in std::fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension().is_none_or(|ext| ext != "bloom") {
+                continue;
+            }
+            let Some(file_id) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u32>().ok()) else {
+                continue;
+            };
+            self.filters.insert(file_id, BloomFilter::load(&path)?);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
     
     pub fn put(&mut self, key: String, record: Record) -> Result<usize, DbError>{
         // if key.contains(enums::SEPARATOR as char) {
@@ -52,6 +131,45 @@ impl Index {
         Ok(put_num as usize) 
     }
 
+    // append appends the value of record, which holds the appended bytes only, to the value of key.
+    // returns the new value size.
+    pub fn append(&mut self, key: &str, record: Record) -> usize {
+        let appended = record.entry.value.clone();
+        self.update_value(key, record, |value| value.extend_from_slice(&appended))
+    }
+
+    // setrange overwrites the value of key from offset with the value of record, the value is padded with zero bytes
+    // if offset is past its end. returns the new value size.
+    pub fn setrange(&mut self, key: &str, offset: usize, record: Record) -> usize {
+        if record.entry.value.is_empty() {
+            return self.kvs.get(key).filter(|old| !old.entry.is_expired()).map_or(0, |old| old.entry.value.len());
+        }
+        let b = record.entry.value.clone();
+        self.update_value(key, record, |value| {
+            if value.len() < offset + b.len() {
+                value.resize(offset + b.len(), 0);
+            }
+            value[offset..offset + b.len()].copy_from_slice(&b);
+        })
+    }
+
+    // update_value applies f to the value of key, empty if the key does not exist or is expired, the result is stored at
+    // the place of record on disk. the existing entry keeps its ttl like in the memtable.
+    fn update_value(&mut self, key: &str, record: Record, f: impl FnOnce(&mut Vec<u8>)) -> usize {
+        let mut entry = match self.kvs.get(key) {
+            Some(old) if !old.entry.is_expired() => old.entry.clone(),
+            _ => Entry { value: Bytes::new(), ..record.entry },
+        };
+        let mut value = entry.value.to_vec();
+        f(&mut value);
+        let size = value.len();
+        entry.meta.value_size = size as u32;
+        entry.value = Bytes::from(value);
+        let hint = Hint { meta: entry.meta.clone(), ..record.hint };
+        self.kvs.insert(key.to_owned(), Record { hint, entry });
+        size
+    }
+
     pub fn del(&mut self, key: &str) -> Result<usize, DbError> {
         if self.kvs.remove(key).is_none() {
             return Ok(0);
@@ -64,6 +182,11 @@ impl Index {
         Some(range)
     }
 
+    // range iterates the records within bounds in key order, lazily unlike range_scan.
+    pub fn range(&self, bounds: (Bound<&str>, Bound<&str>)) -> impl DoubleEndedIterator<Item = (&String, &Record)> {
+        self.kvs.range::<str, _>(bounds)
+    }
+
     pub fn lpush(&mut self, key: &str, record: Record) -> Result<usize, DbError>{
         // if key.contains(enums::SEPARATOR as char) {
         //     return Err(DbError::ContainSeparatorChar { separator: enums::SEPARATOR as char});
@@ -102,12 +225,7 @@ impl Index {
             return Ok(None);
         }
         let value = value.unwrap();
-        let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-        let mut record = Record { hint: Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-        record.hint = Hint::decode(&value[0..value_start_index as usize])?;
-        if value_start_index < value.len() as u64 {
-            record.entry = Entry::decode(&value[value_start_index as usize..])?;
-        }
+        let record = Record::decode(&value)?;
         Ok(Some(record))
     }
 
@@ -117,12 +235,7 @@ impl Index {
             return Ok(None);
         }
         let value = value.unwrap();
-        let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-        let mut record = Record { hint: Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-        record.hint = Hint::decode(&value[0..value_start_index as usize])?;
-        if value_start_index < value.len() as u64 {
-            record.entry = Entry::decode(&value[value_start_index as usize..])?;
-        }
+        let record = Record::decode(&value)?;
         Ok(Some(record))
     }
 
@@ -136,17 +249,18 @@ impl Index {
 
     pub fn lindex(&self, key: &str, index: usize) -> Result<Option<Record>, DbError>{
         if let Some(b) = self.lists.lindex(key, index) {
-            let value_start_index = u64::from_le_bytes(b[0..8].try_into().unwrap());
-            let mut record = Record { hint: Hint::decode(&b[0..value_start_index as usize])?, entry: Entry::default()};
-            record.hint = Hint::decode(&b[0..value_start_index as usize])?;
-            if value_start_index < b.len() as u64 {
-                record.entry = Entry::decode(&b[value_start_index as usize..])?;
-            }
+            let record = Record::decode(&b)?;
             return Ok(Some(record))
         }
         Ok(None)
     }
 
+    pub fn lpos(&self, key: &str, element: &[u8]) -> Option<usize> {
+        self.lists.lpos(key, |value| {
+            Record::decode(value).map(|record| record.entry.value == element).unwrap_or(false)
+        })
+    }
+
     pub fn lrange(
         &self,
         key: &str,
@@ -155,12 +269,7 @@ impl Index {
     ) -> Result<Option<Vec<Record>>, DbError>{
         if let Some(bs) = self.lists.lrange(key, start, end) {
             let records: Result<Option<Vec<Record>>, DbError> = bs.iter().map(|value| {
-                let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-                let mut record = Record { hint:  Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-                record.hint = Hint::decode(&value[0..value_start_index as usize])?;
-                if value_start_index < value.len() as u64 {
-                    record.entry = Entry::decode(&value[value_start_index as usize..])?;
-                }
+                let record = Record::decode(value)?;
                 Ok(Some(record))
             }).collect();
             return records;
@@ -168,13 +277,64 @@ impl Index {
         Ok(None)
     }
 
+    pub fn lrem(&mut self, key: &str, count: isize, element: &[u8]) -> usize {
+        self.lists.lrem(key, count, |value| {
+            Record::decode(value).map(|record| record.entry.value == element).unwrap_or(false)
+        }).unwrap_or(0)
+    }
+
+    pub fn linsert(&mut self, key: &str, before: bool, pivot: &[u8], record: Record) -> Option<usize> {
+        self.lists.linsert(key, before, |value| {
+            Record::decode(value).map(|record| record.entry.value == pivot).unwrap_or(false)
+        }, record.encode().into())
+    }
+
+    pub fn ltrim(&mut self, key: &str, start: isize, end: isize) -> Option<usize> {
+        self.lists.ltrim(key, start, end)
+    }
+
+    pub fn lmove(
+        &mut self,
+        source: &str,
+        destination: &str,
+        wherefrom: ListDirection,
+        whereto: ListDirection,
+    ) -> Result<Option<Record>, DbError> {
+        let popped = match wherefrom {
+            ListDirection::Left => self.lists.lpop(source),
+            ListDirection::Right => self.lists.rpop(source),
+        };
+        let Some(value) = popped else {
+            return Ok(None);
+        };
+        let mut record = match Record::decode(&value) {
+            Ok(record) => record,
+            Err(err) => {
+                // put the element back where it was popped from
+                match wherefrom {
+                    ListDirection::Left => self.lists.lpush(source, vec![value]),
+                    ListDirection::Right => self.lists.rpush(source, vec![value]),
+                };
+                return Err(err);
+            }
+        };
+        record.rekey(destination);
+        let moved = Bytes::from(record.encode());
+        match whereto {
+            ListDirection::Left => self.lists.lpush(destination, vec![moved]),
+            ListDirection::Right => self.lists.rpush(destination, vec![moved]),
+        };
+        Ok(Some(record))
+    }
+
+    // set members are the entry values, the encoded record is kept alongside each of them
     pub fn sadd(&mut self, key: &str, members: Vec<Record>)->Option<usize> {
-        let records = members.iter().map(|member| member.encode().into()).collect::<Vec<Bytes>>();
+        let records = members.iter().map(|member| (member.entry.value.clone(), member.encode().into())).collect::<Vec<(Bytes, Bytes)>>();
         self.sets.sadd(key, records)
     }
 
     pub fn srem(&mut self, key: &str, members: Vec<Record>) -> Option<usize>{
-        let records = members.iter().map(|member| member.encode().into()).collect::<Vec<Bytes>>();
+        let records = members.into_iter().map(|member| member.entry.value).collect::<Vec<Bytes>>();
         self.sets.srem(key, records)
     }
 
@@ -185,12 +345,7 @@ impl Index {
         }
         let sets = sets.unwrap();
         let records: Result<Option<Vec<Record>>, DbError> = sets.iter().map(|value| {
-            let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-                let mut record = Record { hint:  Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-                record.hint = Hint::decode(&value[0..value_start_index as usize])?;
-                if value_start_index < value.len() as u64 {
-                    record.entry = Entry::decode(&value[value_start_index as usize..])?;
-                }
+            let record = Record::decode(value)?;
                 Ok(Some(record))
         }).collect();
         return records;
@@ -203,12 +358,7 @@ impl Index {
         }
         let sets = sets.unwrap();
         let records: Result<Option<Vec<Record>>, DbError> = sets.iter().map(|value| {
-            let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-                let mut record = Record { hint:  Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-                record.hint = Hint::decode(&value[0..value_start_index as usize])?;
-                if value_start_index < value.len() as u64 {
-                    record.entry = Entry::decode(&value[value_start_index as usize..])?;
-                }
+            let record = Record::decode(value)?;
                 Ok(Some(record))
         }).collect();
         return records;
@@ -221,12 +371,7 @@ impl Index {
         }
         let sets = sets.unwrap();
         let records: Result<Option<Vec<Record>>, DbError> = sets.iter().map(|value| {
-            let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-                let mut record = Record { hint:  Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-                record.hint = Hint::decode(&value[0..value_start_index as usize])?;
-                if value_start_index < value.len() as u64 {
-                    record.entry = Entry::decode(&value[value_start_index as usize..])?;
-                }
+            let record = Record::decode(value)?;
                 Ok(Some(record))
         }).collect();
         return records;
@@ -234,8 +379,7 @@ impl Index {
 
     pub fn sismember(&self, record: &Record) -> Option<bool>{
         let key_str = std::str::from_utf8(&record.hint.key).unwrap();
-        let member = Bytes::from(record.encode());
-        self.sets.sismember(key_str, member)
+        self.sets.sismember(key_str, record.entry.value.clone())
     }
 
     pub fn smembers(&self, key: &str) -> Result<Option<Vec<Record>>, DbError>{
@@ -245,12 +389,7 @@ impl Index {
         }
         let members = members.unwrap();
         let records: Result<Option<Vec<Record>>, DbError> = members.iter().map(|value| {
-            let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-                let mut record = Record { hint:  Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-                record.hint = Hint::decode(&value[0..value_start_index as usize])?;
-                if value_start_index < value.len() as u64 {
-                    record.entry = Entry::decode(&value[value_start_index as usize..])?;
-                }
+            let record = Record::decode(value)?;
                 Ok(Some(record))
         }).collect();
         return records;
@@ -260,21 +399,33 @@ impl Index {
         self.sets.scard(key)
     }
 
+    pub fn smove(&mut self, source: &str, destination: &str, member: &[u8]) -> Option<usize> {
+        self.sets.smove(source, destination, member)
+    }
+
+    pub fn sdiffstore(&mut self, destination: &str, key: &str, keys: Vec<&str>) -> Option<usize> {
+        self.sets.sdiffstore(destination, key, keys)
+    }
+
+    pub fn sinterstore(&mut self, destination: &str, key: &str, keys: Vec<&str>) -> Option<usize> {
+        self.sets.sinterstore(destination, key, keys)
+    }
+
+    pub fn suionstore(&mut self, destination: &str, key: &str, keys: Vec<&str>) -> Option<usize> {
+        self.sets.suionstore(destination, key, keys)
+    }
+
     pub fn zadd(&mut self, record:Record, score: f64) -> Option<usize>{
-        Some(self.sorted_sets.put(std::str::from_utf8(&record.hint.key).unwrap(), Bytes::from(record.encode()), score))
+        Some(self.sorted_sets.put(&record.hint.key, Bytes::from(record.encode()), score))
     }
 
-    pub fn zrem(&mut self, key: &str) -> Result<Option<Record>, DbError>{
+    pub fn zrem(&mut self, key: &[u8]) -> Result<Option<Record>, DbError>{
         let node = self.sorted_sets.remove(key);
         if node.is_none() {
             return Ok(None);
         }
         let value = node.unwrap().borrow().value.clone();
-        let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-        let mut record = Record { hint: Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-        if value_start_index < value.len() as u64 {
-            record.entry = Entry::decode(&value[value_start_index as usize..])?;
-        }
+        let record = Record::decode(&value)?;
         Ok(Some(record))
     }
 
@@ -286,11 +437,7 @@ impl Index {
     ) -> Result<Vec<Record>, DbError>{
         let records: Result<Vec<Record>, DbError> = self.sorted_sets.get_by_rank_range(start, end, remove).iter().map(|node| {
             let value = node.borrow().value.clone();
-            let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-            let mut record = Record { hint: Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-            if value_start_index < value.len() as u64 {
-                record.entry = Entry::decode(&value[value_start_index as usize..])?;
-            }
+            let record = Record::decode(&value)?;
             Ok(record)
         }).collect();
         records
@@ -306,25 +453,17 @@ impl Index {
             return Ok(None);
         }
         let value = node.unwrap().borrow().value.clone();
-        let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-        let mut record = Record { hint: Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-        if value_start_index < value.len() as u64 {
-            record.entry = Entry::decode(&value[value_start_index as usize..])?;
-        }
+        let record = Record::decode(&value)?;
         Ok(Some(record))
     }
 
-    pub fn get_by_key(&self, key: &str) -> Result<Option<Record>, DbError>{
+    pub fn get_by_key(&self, key: &[u8]) -> Result<Option<Record>, DbError>{
         let node = self.sorted_sets.get_by_key(key);
         if node.is_none() {
             return Ok(None);
         }
         let value = node.unwrap().borrow().value.clone();
-        let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-        let mut record = Record { hint: Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-        if value_start_index < value.len() as u64 {
-            record.entry = Entry::decode(&value[value_start_index as usize..])?;
-        }
+        let record = Record::decode(&value)?;
         Ok(Some(record))
     }
 
@@ -338,13 +477,94 @@ impl Index {
     ) -> Result<Vec<Record>, DbError>{
         let records: Result<Vec<Record>, DbError> = self.sorted_sets.get_by_score_range(start, end, limit, exclude_start, exclude_end).iter().map(|node| {
             let value = node.borrow().value.clone();
-            let value_start_index = u64::from_le_bytes(value[0..8].try_into().unwrap());
-            let mut record = Record { hint: Hint::decode(&value[0..value_start_index as usize])?, entry: Entry::default()};
-            if value_start_index < value.len() as u64 {
-                record.entry = Entry::decode(&value[value_start_index as usize..])?;
-            }
+            let record = Record::decode(&value)?;
             Ok(record)
         }).collect();
         records
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::{DataTypes, EntryOperate};
+
+    fn record(key: &str, value: &[u8], operate: EntryOperate) -> Record {
+        let entry = Entry::new(
+            Bytes::from("bucket"),
+            Bytes::from(key.to_owned()),
+            Bytes::copy_from_slice(value),
+            operate,
+            DataTypes::String,
+        );
+        Record {
+            hint: Hint::new(entry.key.clone(), 1, 0, entry.meta.clone()),
+            entry,
+        }
+    }
+
+    #[test]
+    fn test_record_decode() {
+        let encoded = record("key1", b"value1", EntryOperate::Put).encode();
+        let decoded = Record::decode(&encoded).unwrap();
+        assert_eq!(decoded.hint.key, Bytes::from("key1"));
+        assert_eq!(decoded.hint.file_id, 1);
+        assert_eq!(decoded.entry.value, Bytes::from("value1"));
+
+        assert!(Record::decode(&[]).is_err());
+        assert!(Record::decode(&encoded[..6]).is_err());
+        assert!(Record::decode(&encoded[..20]).is_err());
+        assert!(Record::decode(&encoded[..encoded.len() - 1]).is_err());
+        let mut huge = encoded.clone();
+        huge[0..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Record::decode(&huge).is_err());
+    }
+
+    #[test]
+    fn test_lmove() {
+        let mut index = Index::default();
+        index.rpush("src", record("src", b"a", EntryOperate::LRpush)).unwrap();
+        index.rpush("src", record("src", b"b", EntryOperate::LRpush)).unwrap();
+        let moved = index.lmove("src", "dst", ListDirection::Left, ListDirection::Right).unwrap().unwrap();
+        assert_eq!(moved.entry.value, Bytes::from("a"));
+        assert_eq!(moved.hint.key, Bytes::from("dst"));
+
+        let stored = index.lindex("dst", 0).unwrap().unwrap();
+        assert_eq!(stored.hint.key, Bytes::from("dst"));
+        assert_eq!(stored.hint.meta.key_size, 3);
+        assert_eq!(stored.entry.key, Bytes::from("dst"));
+        assert_eq!(stored.entry.value, Bytes::from("a"));
+        assert_eq!(index.llen("src"), Some(1));
+        assert_eq!(index.lrem("dst", 0, b"a"), 1);
+    }
+
+    #[test]
+    fn test_lpos() {
+        let mut index = Index::default();
+        for value in [b"a", b"b", b"b"] {
+            index.rpush("key1", record("key1", value, EntryOperate::LRpush)).unwrap();
+        }
+        // elements pushed at different times still match by value
+        assert_eq!(index.lpos("key1", b"b"), Some(1));
+        assert_eq!(index.lpos("key1", b"c"), None);
+        assert_eq!(index.lpos("key2", b"a"), None);
+    }
+
+    #[test]
+    fn test_append_setrange() {
+        let mut index = Index::default();
+        index.put("key1".to_owned(), record("key1", b"hello", EntryOperate::Put)).unwrap();
+        // the wal entries carry the appended bytes and the overwritten range only
+        assert_eq!(index.append("key1", record("key1", b" world", EntryOperate::Append)), 11);
+        assert_eq!(index.setrange("key1", 6, record("key1", b"redis", EntryOperate::SetRange)), 11);
+        let stored = index.get("key1").unwrap();
+        assert_eq!(stored.entry.value, Bytes::from("hello redis"));
+        assert_eq!(stored.hint.meta.value_size, 11);
+
+        assert_eq!(index.setrange("key2", 2, record("key2", b"ab", EntryOperate::SetRange)), 4);
+        assert_eq!(index.get("key2").unwrap().entry.value, Bytes::from_static(b"\0\0ab"));
+        assert_eq!(index.setrange("key3", 2, record("key3", b"", EntryOperate::SetRange)), 0);
+        assert!(index.get("key3").is_none());
+        assert_eq!(index.append("key3", record("key3", b"abc", EntryOperate::Append)), 3);
+    }
+}
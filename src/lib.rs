@@ -19,7 +19,6 @@ mod option;
 // valuelogs
 mod valuelogs;
 // wal
-mod consts;
 mod wal;
 mod db;
 mod tx;
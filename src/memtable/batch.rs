@@ -0,0 +1,765 @@
+use bytes::{Bytes, BytesMut};
+
+use super::{batch_entry, json_path, keyspace::Value, zscore_payload, Memtable};
+use crate::{
+    data::{entry::Entry, meta::Meta},
+    datatypes::{
+        json::{JsonValue, PathSegment},
+        quicklist::QuickList,
+        timeseries::{Added, CompactionRule},
+    },
+    enums::{DataTypes, ListDirection},
+    errors::DbError,
+};
+
+// BatchOp is an operate of a write batch, it takes the arguments of the memtable method it
+// is applied with.
+pub enum BatchOp {
+    // an entry with the Del operate deletes the string key
+    Put(Entry),
+    Append(Entry),
+    SetRange(usize, Entry),
+    LPush(Entry),
+    RPush(Entry),
+    LPop(Entry),
+    RPop(Entry),
+    LSet(usize, Entry),
+    LRem(isize, Entry),
+    LTrim(isize, isize, Entry),
+    // inserts before (true) or after the pivot
+    LInsert(bool, Bytes, Entry),
+    // moves an element from the key of entry to destination
+    LMove(String, ListDirection, ListDirection, Entry),
+    SAdd(Entry),
+    SRem(Entry),
+    // moves the member from the key of entry to destination
+    SMove(String, Entry),
+    ZAdd(Entry),
+    ZRem(Entry),
+    ZIncrBy(Entry),
+    JsonSet(String, Entry),
+    JsonDel(String, Entry),
+    TsAdd(i64, f64, Entry),
+    // deletes a key of any data type, by bucket and key
+    Del(String, String),
+}
+
+impl BatchOp {
+    // key returns the bucket and the key the operate writes, the source of a move.
+    fn key(&self) -> (&[u8], &[u8]) {
+        match self {
+            BatchOp::Put(entry)
+            | BatchOp::Append(entry)
+            | BatchOp::SetRange(_, entry)
+            | BatchOp::LPush(entry)
+            | BatchOp::RPush(entry)
+            | BatchOp::LPop(entry)
+            | BatchOp::RPop(entry)
+            | BatchOp::LSet(_, entry)
+            | BatchOp::LRem(_, entry)
+            | BatchOp::LTrim(_, _, entry)
+            | BatchOp::LInsert(_, _, entry)
+            | BatchOp::LMove(_, _, _, entry)
+            | BatchOp::SAdd(entry)
+            | BatchOp::SRem(entry)
+            | BatchOp::SMove(_, entry)
+            | BatchOp::ZAdd(entry)
+            | BatchOp::ZRem(entry)
+            | BatchOp::ZIncrBy(entry)
+            | BatchOp::JsonSet(_, entry)
+            | BatchOp::JsonDel(_, entry)
+            | BatchOp::TsAdd(_, _, entry) => (entry.meta.bucket.as_ref(), entry.key.as_ref()),
+            BatchOp::Del(bucket, key) => (bucket.as_bytes(), key.as_bytes()),
+        }
+    }
+}
+
+// WriteBatch gathers operates applied together by Memtable::write_batch.
+#[derive(Default)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        WriteBatch::default()
+    }
+
+    pub fn push(&mut self, op: BatchOp) -> &mut Self {
+        self.ops.push(op);
+        self
+    }
+
+    pub fn put(&mut self, entry: Entry) -> &mut Self {
+        self.push(BatchOp::Put(entry))
+    }
+
+    pub fn del(&mut self, bucket: &str, key: &str) -> &mut Self {
+        self.push(BatchOp::Del(bucket.to_owned(), key.to_owned()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+// Undo takes back what an operate changed in a key, it holds only what the operate
+// replaced or removed.
+enum Undo {
+    // the string of this memtable as it was, with its tombstone
+    String {
+        value: Option<BytesMut>,
+        tombstone: bool,
+    },
+    // the bytes overwritten from offset of a string, which is cut back to size
+    StringRange {
+        size: usize,
+        offset: usize,
+        old: Bytes,
+    },
+    // the list elements removed, with their indexes
+    ListInsert(Vec<(usize, Bytes)>),
+    // the index of a list element added
+    ListRemove(usize),
+    ListSet(usize, Bytes),
+    // a set member with its data, none if it was not a member
+    Member(Bytes, Option<Bytes>),
+    // a sorted set member with its value and score, none if it was not a member
+    Score(Bytes, Option<(Bytes, f64)>),
+    // the json value at path, none if there was none
+    JsonSet(Vec<PathSegment>, Option<JsonValue>),
+    JsonDel(Vec<PathSegment>, JsonValue),
+    Samples(Vec<Added>),
+    // the compaction rules writing to a deleted time series
+    Rules(Vec<(String, usize, CompactionRule)>),
+    Value(Value),
+}
+
+// BatchState is the write batch being applied by a memtable.
+#[derive(Default)]
+pub(super) struct BatchState {
+    // the wal records of the operates, logged as one record at the end of the batch
+    pub(super) records: Vec<Vec<u8>>,
+    // how to take back the operates applied, by bucket and key, in the order applied
+    undo: Vec<(String, String, Undo)>,
+    // the keys pushed to, their blocked clients are served once the batch is logged
+    pub(super) list_keys: Vec<(String, String)>,
+    pub(super) zset_keys: Vec<(String, String)>,
+}
+
+impl Memtable {
+    // write_batch applies the operates of batch and logs them as a single wal record, the
+    // caller syncs it through the group commit of the wal. each operate records what it
+    // changes, if an operate or the wal write fails the operates applied are undone, latest
+    // first, so that the batch leaves no trace. the clients blocked on the keys are served
+    // once the batch is logged. returns the number of operates applied.
+    pub fn write_batch(&mut self, batch: WriteBatch) -> Result<usize, DbError> {
+        let applied = batch.ops.len();
+        self.batch = Some(BatchState::default());
+        let res = batch.ops.into_iter().try_for_each(|op| self.apply(op));
+        let state = self.batch.take().unwrap_or_default();
+        let res = res.and_then(|_| match state.records.is_empty() {
+            true => Ok(0),
+            false => self
+                .wal
+                .write(batch_entry(&state.records).encode().as_ref()),
+        });
+        if let Err(err) = res {
+            for (bucket, key, undo) in state.undo.into_iter().rev() {
+                self.undo(&bucket, &key, undo);
+            }
+            return Err(err);
+        }
+
+        for (bucket, key) in state.list_keys {
+            self.serve_list_waiters(&bucket, &key)?;
+        }
+        for (bucket, key) in state.zset_keys {
+            self.serve_zset_waiters(&bucket, &key)?;
+        }
+        Ok(applied)
+    }
+
+    // apply applies op, what it changes is read from the keys before and kept once op
+    // succeeds.
+    fn apply(&mut self, op: BatchOp) -> Result<(), DbError> {
+        let (bucket, key) = op.key();
+        let bucket = String::from_utf8_lossy(bucket).into_owned();
+        let key = String::from_utf8_lossy(key).into_owned();
+        let undo = match op {
+            BatchOp::Put(entry) => {
+                let undo = self.string_undo(&bucket, &key);
+                self.put(entry)?;
+                vec![(key, undo)]
+            }
+            BatchOp::Append(entry) => {
+                let undo = self.range_undo(&bucket, &key, usize::MAX, 0);
+                self.append(entry)?;
+                vec![(key, undo)]
+            }
+            BatchOp::SetRange(offset, entry) => {
+                let undo = self.range_undo(&bucket, &key, offset, entry.value.len());
+                self.setrange(offset, entry)?;
+                vec![(key, undo)]
+            }
+            BatchOp::LPush(entry) => {
+                self.lpush(entry)?;
+                vec![(key, Undo::ListRemove(0))]
+            }
+            BatchOp::RPush(entry) => {
+                let len = self.llen(&bucket, &key)?;
+                self.rpush(entry)?;
+                vec![(key, Undo::ListRemove(len))]
+            }
+            BatchOp::LPop(entry) => {
+                let undo = self.head_undo(&bucket, &key);
+                self.lpop(entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::RPop(entry) => {
+                let undo = self.tail_undo(&bucket, &key);
+                self.rpop(entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::LSet(index, entry) => {
+                let old = self
+                    .elements(&bucket, &key)
+                    .and_then(|list| list.get(index));
+                let undo = old.map(|old| Undo::ListSet(index, old.clone()));
+                self.lset(index, entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::LRem(count, entry) => {
+                let removed = self.list.get(&bucket).map(|list| {
+                    list.lrem_matches(&key, count, |item| {
+                        Entry::encoded_value(item) == entry.value
+                    })
+                });
+                self.lrem(count, entry)?;
+                vec![(key, Undo::ListInsert(removed.unwrap_or_default()))]
+            }
+            BatchOp::LTrim(start, end, entry) => {
+                let removed = self
+                    .list
+                    .get(&bucket)
+                    .map(|list| list.ltrim_removed(&key, start, end));
+                self.ltrim(start, end, entry)?;
+                vec![(key, Undo::ListInsert(removed.unwrap_or_default()))]
+            }
+            BatchOp::LInsert(before, pivot, entry) => {
+                let found = self
+                    .list
+                    .get(&bucket)
+                    .and_then(|list| list.lpos(&key, |item| Entry::encoded_value(item) == pivot));
+                let undo = found.map(|index| Undo::ListRemove(index + !before as usize));
+                self.linsert(before, pivot, entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::LMove(destination, wherefrom, whereto, entry) => {
+                let popped = match wherefrom {
+                    ListDirection::Left => self.head_undo(&bucket, &key),
+                    ListDirection::Right => self.tail_undo(&bucket, &key),
+                };
+                // the element is pushed once popped, source may be destination
+                let pushed = match whereto {
+                    ListDirection::Left => 0,
+                    ListDirection::Right => self
+                        .llen(&bucket, &destination)?
+                        .saturating_sub((destination == key) as usize),
+                };
+                self.lmove(&destination, wherefrom, whereto, entry)?;
+                match popped {
+                    Some(popped) => vec![(key, popped), (destination, Undo::ListRemove(pushed))],
+                    None => vec![],
+                }
+            }
+            BatchOp::SAdd(entry) => {
+                let undo = self.member_undo(&bucket, &key, &entry.value);
+                self.sadd(entry)?;
+                vec![(key, undo)]
+            }
+            BatchOp::SRem(entry) => {
+                let undo = self.member_undo(&bucket, &key, &entry.value);
+                self.srem(entry)?;
+                vec![(key, undo)]
+            }
+            BatchOp::SMove(destination, entry) => {
+                let source = self.member_undo(&bucket, &key, &entry.value);
+                let dest = self.member_undo(&bucket, &destination, &entry.value);
+                self.smove(&destination, entry)?;
+                vec![(key, source), (destination, dest)]
+            }
+            BatchOp::ZAdd(entry) => {
+                let undo = self.score_undo(&bucket, &key, &entry);
+                self.zadd(entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::ZIncrBy(entry) => {
+                let undo = self.score_undo(&bucket, &key, &entry);
+                self.zincrby(entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::ZRem(entry) => {
+                let undo = self.member_score_undo(&bucket, &key, &entry.value);
+                self.zrem(entry)?;
+                vec![(key, undo)]
+            }
+            BatchOp::JsonSet(path, entry) => {
+                let undo = json_path(&entry, &path).ok().map(|segments| {
+                    let old = self
+                        .json
+                        .get(&bucket)
+                        .and_then(|json| json.get(&key, &segments))
+                        .cloned();
+                    Undo::JsonSet(segments, old)
+                });
+                self.json_set(&path, entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::JsonDel(path, entry) => {
+                let undo = json_path(&entry, &path).ok().and_then(|segments| {
+                    let json = self.json.get(&bucket)?;
+                    let (segments, old) = json.removed(&key, &segments)?;
+                    Some(Undo::JsonDel(segments, old))
+                });
+                self.json_del(&path, entry)?;
+                undo.map(|undo| (key, undo)).into_iter().collect()
+            }
+            BatchOp::TsAdd(timestamp, value, entry) => {
+                let added = self.ts_add_sample(timestamp, value, entry)?;
+                vec![(key, Undo::Samples(added))]
+            }
+            BatchOp::Del(_, _) => {
+                let string = self.string_undo(&bucket, &key);
+                let rules = self
+                    .timeseries
+                    .get(&bucket)
+                    .map(|timeseries| timeseries.rules_to(&key))
+                    .unwrap_or_default();
+                match self.remove_key(&bucket, &key)? {
+                    Some(Value::String(_)) => vec![(key, string)],
+                    Some(value) => {
+                        vec![(key.clone(), Undo::Value(value)), (key, Undo::Rules(rules))]
+                    }
+                    None => vec![],
+                }
+            }
+        };
+        if let Some(state) = self.batch.as_mut() {
+            state.undo.extend(
+                undo.into_iter()
+                    .map(|(key, undo)| (bucket.clone(), key, undo)),
+            );
+        }
+        Ok(())
+    }
+
+    fn elements(&self, bucket: &str, key: &str) -> Option<&QuickList> {
+        self.list.get(bucket)?.get(key)
+    }
+
+    fn head_undo(&self, bucket: &str, key: &str) -> Option<Undo> {
+        let head = self.elements(bucket, key)?.get(0)?;
+        Some(Undo::ListInsert(vec![(0, head.clone())]))
+    }
+
+    fn tail_undo(&self, bucket: &str, key: &str) -> Option<Undo> {
+        let list = self.elements(bucket, key)?;
+        let tail = list.iter().next_back()?;
+        Some(Undo::ListInsert(vec![(list.len() - 1, tail.clone())]))
+    }
+
+    fn string_undo(&self, bucket: &str, key: &str) -> Undo {
+        Undo::String {
+            value: self.kvs.get(bucket).and_then(|kvs| kvs.get(key)).cloned(),
+            tombstone: self
+                .tombstones
+                .get(bucket)
+                .is_some_and(|tombstones| tombstones.contains(key)),
+        }
+    }
+
+    // range_undo keeps the len bytes from offset of a live string of this memtable, which
+    // is changed in place. any other string is replaced, it is kept whole.
+    fn range_undo(&self, bucket: &str, key: &str, offset: usize, len: usize) -> Undo {
+        let own = self.kvs.get(bucket).and_then(|kvs| kvs.get(key));
+        match own {
+            Some(entry_bytes) if !Meta::parse_entry_header_buf(entry_bytes).is_expired() => {
+                let value = Entry::encoded_value(entry_bytes);
+                let offset = offset.min(value.len());
+                let end = offset.saturating_add(len).min(value.len());
+                Undo::StringRange {
+                    size: value.len(),
+                    offset,
+                    old: Bytes::copy_from_slice(&value[offset..end]),
+                }
+            }
+            _ => self.string_undo(bucket, key),
+        }
+    }
+
+    fn member_undo(&self, bucket: &str, key: &str, member: &Bytes) -> Undo {
+        let data = self
+            .set
+            .get(bucket)
+            .and_then(|set| set.members(key))
+            .and_then(|members| members.get(member));
+        Undo::Member(member.clone(), data.cloned())
+    }
+
+    // score_undo keeps the member of a zadd or zincrby entry, none if the entry is invalid.
+    fn score_undo(&self, bucket: &str, key: &str, entry: &Entry) -> Option<Undo> {
+        let (_, member) = zscore_payload(entry).ok()?;
+        Some(self.member_score_undo(bucket, key, member))
+    }
+
+    fn member_score_undo(&self, bucket: &str, key: &str, member: &[u8]) -> Undo {
+        let node = self.zset(bucket, key).and_then(|sorted_set| {
+            let node = sorted_set.get_by_key(member)?;
+            let node = node.borrow();
+            Some((node.value.clone(), node.score))
+        });
+        Undo::Score(Bytes::copy_from_slice(member), node)
+    }
+
+    // undo takes back what an operate changed in key, without logging.
+    fn undo(&mut self, bucket: &str, key: &str, undo: Undo) {
+        let data_type = match undo {
+            Undo::String { value, tombstone } => {
+                let kvs = self.kvs.entry(bucket.to_owned()).or_default();
+                match value {
+                    Some(value) => kvs.insert(key.to_owned(), value),
+                    None => kvs.remove(key),
+                };
+                let tombstones = self.tombstones.entry(bucket.to_owned()).or_default();
+                match tombstone {
+                    true => tombstones.insert(key.to_owned()),
+                    false => tombstones.remove(key),
+                };
+                DataTypes::String
+            }
+            Undo::StringRange { size, offset, old } => {
+                if let Some(entry_bytes) = self.kvs.get_mut(bucket).and_then(|kvs| kvs.get_mut(key))
+                {
+                    Entry::set_range_encoded(entry_bytes, offset, &old);
+                    Entry::truncate_encoded(entry_bytes, size);
+                }
+                DataTypes::String
+            }
+            Undo::ListInsert(elements) => {
+                let list = self.list.entry(bucket.to_owned()).or_default();
+                list.put_back(key, elements);
+                DataTypes::List
+            }
+            Undo::ListRemove(index) => {
+                if let Some(list) = self.list.get_mut(bucket) {
+                    list.remove_at(key, index);
+                }
+                DataTypes::List
+            }
+            Undo::ListSet(index, old) => {
+                if let Some(list) = self.list.get_mut(bucket) {
+                    list.lset(key, index, old);
+                }
+                DataTypes::List
+            }
+            Undo::Member(member, data) => {
+                let set = self.set.entry(bucket.to_owned()).or_default();
+                set.put_back(key, member, data);
+                DataTypes::Set
+            }
+            Undo::Score(member, node) => {
+                let zsets = self.sorted_set.entry(bucket.to_owned()).or_default();
+                let sorted_set = zsets.entry(key.to_owned()).or_default();
+                match node {
+                    Some((value, score)) => {
+                        sorted_set.put(&member, value, score);
+                    }
+                    None => {
+                        sorted_set.remove(&member);
+                    }
+                }
+                DataTypes::SortedSet
+            }
+            Undo::JsonSet(path, old) => {
+                let json = self.json.entry(bucket.to_owned()).or_default();
+                match old {
+                    Some(old) => {
+                        json.set(key, &path, old);
+                    }
+                    None => {
+                        json.del(key, &path);
+                    }
+                }
+                DataTypes::Json
+            }
+            Undo::JsonDel(path, old) => {
+                let json = self.json.entry(bucket.to_owned()).or_default();
+                json.put_back(key, &path, old);
+                DataTypes::Json
+            }
+            Undo::Samples(added) => {
+                let keys: Vec<String> =
+                    added.iter().map(|sample| sample.key().to_owned()).collect();
+                if let Some(timeseries) = self.timeseries.get_mut(bucket) {
+                    timeseries.undo(added);
+                }
+                for key in keys {
+                    self.touch(bucket, &key, DataTypes::TimeSeries);
+                }
+                return;
+            }
+            Undo::Rules(rules) => {
+                if let Some(timeseries) = self.timeseries.get_mut(bucket) {
+                    timeseries.put_rules(rules);
+                }
+                return;
+            }
+            Undo::Value(value) => return self.put_back(bucket, key, value),
+        };
+        self.touch(bucket, key, data_type);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{
+        data::payload,
+        enums::{self, EntryOperate},
+        fileio::FDManager,
+    };
+
+    fn memtable(name: &str) -> Memtable {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        Memtable::new(
+            1,
+            path.to_str().unwrap(),
+            1,
+            enums::RWMode::StdIO,
+            FDManager::new(4),
+        )
+        .unwrap()
+    }
+
+    fn entry(key: &str, value: &str, operate: EntryOperate, data_type: DataTypes) -> Entry {
+        Entry::new(
+            Bytes::from("bucket"),
+            Bytes::from(key.to_owned()),
+            Bytes::from(value.to_owned()),
+            operate,
+            data_type,
+        )
+    }
+
+    #[test]
+    fn test_failed_batch() {
+        let mut memtable = memtable("arrowdb_failed_batch.wal");
+        memtable
+            .put(entry("a", "1", EntryOperate::Put, DataTypes::String))
+            .unwrap();
+        let logged = memtable.logged();
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put(entry("a", "2", EntryOperate::Put, DataTypes::String))
+            .put(entry("b", "1", EntryOperate::Put, DataTypes::String))
+            .push(BatchOp::RPush(entry(
+                "c",
+                "1",
+                EntryOperate::LRpush,
+                DataTypes::List,
+            )))
+            // b is a string, the push fails
+            .push(BatchOp::LPush(entry(
+                "b",
+                "1",
+                EntryOperate::LLpush,
+                DataTypes::List,
+            )));
+        assert!(memtable.write_batch(batch).is_err());
+
+        let a = memtable.get("bucket", "a").unwrap().unwrap();
+        assert_eq!(a.value, Bytes::from("1"));
+        assert!(memtable.get("bucket", "b").unwrap().is_none());
+        assert_eq!(memtable.llen("bucket", "c").unwrap(), 0);
+        assert_eq!(memtable.logged(), logged);
+
+        let mut batch = WriteBatch::new();
+        batch.put(entry("a", "2", EntryOperate::Put, DataTypes::String));
+        assert_eq!(memtable.write_batch(batch).unwrap(), 1);
+        let a = memtable.get("bucket", "a").unwrap().unwrap();
+        assert_eq!(a.value, Bytes::from("2"));
+        assert!(memtable.logged() > logged);
+    }
+
+    #[test]
+    fn test_failed_batch_undo() {
+        let mut memtable = memtable("arrowdb_failed_batch_undo.wal");
+        let zscore = |score: f64, member: &[u8]| {
+            let mut entry = entry("z", "", EntryOperate::ZPut, DataTypes::SortedSet);
+            entry.value = payload::encode_zscore(score, member);
+            entry.meta.value_size = entry.value.len() as u32;
+            entry
+        };
+        let values = |items: Vec<Bytes>| -> Vec<Bytes> {
+            items
+                .iter()
+                .map(|item| Bytes::copy_from_slice(Entry::encoded_value(item)))
+                .collect()
+        };
+        let list =
+            |memtable: &Memtable, key: &str| values(memtable.lrange("bucket", key, 0, 10).unwrap());
+        let members =
+            |memtable: &Memtable, key: &str| values(memtable.smembers("bucket", key).unwrap());
+        memtable
+            .put(entry("a", "hello", EntryOperate::Put, DataTypes::String))
+            .unwrap();
+        for value in ["x", "y", "z"] {
+            memtable
+                .rpush(entry("l", value, EntryOperate::LRpush, DataTypes::List))
+                .unwrap();
+        }
+        memtable
+            .sadd(entry("s", "m", EntryOperate::SAdd, DataTypes::Set))
+            .unwrap();
+        memtable.zadd(zscore(1.0, b"m")).unwrap();
+        let doc = entry(
+            "j",
+            r#"{"a":[1,2]}"#,
+            EntryOperate::JsonSet,
+            DataTypes::Json,
+        );
+        memtable.json_set("$", doc).unwrap();
+        let ts = |timestamp| {
+            let ts = entry("t", "", EntryOperate::TsAdd, DataTypes::TimeSeries);
+            BatchOp::TsAdd(timestamp, 1.0, ts)
+        };
+
+        let mut batch = WriteBatch::new();
+        batch
+            .push(BatchOp::Append(entry(
+                "a",
+                " world",
+                EntryOperate::Append,
+                DataTypes::String,
+            )))
+            .push(BatchOp::SetRange(
+                0,
+                entry("a", "J", EntryOperate::SetRange, DataTypes::String),
+            ))
+            .push(BatchOp::LInsert(
+                true,
+                Bytes::from("y"),
+                entry("l", "w", EntryOperate::LInsert, DataTypes::List),
+            ))
+            .push(BatchOp::LMove(
+                "l".to_owned(),
+                ListDirection::Left,
+                ListDirection::Right,
+                entry("l", "", EntryOperate::LMove, DataTypes::List),
+            ))
+            .push(BatchOp::LMove(
+                "l2".to_owned(),
+                ListDirection::Right,
+                ListDirection::Left,
+                entry("l", "", EntryOperate::LMove, DataTypes::List),
+            ))
+            .push(BatchOp::LRem(
+                0,
+                entry("l", "y", EntryOperate::LRem, DataTypes::List),
+            ))
+            .push(BatchOp::LSet(
+                0,
+                entry("l", "v", EntryOperate::LSet, DataTypes::List),
+            ))
+            .push(BatchOp::LTrim(
+                1,
+                1,
+                entry("l", "", EntryOperate::LTrim, DataTypes::List),
+            ))
+            .push(BatchOp::SMove(
+                "s2".to_owned(),
+                entry("s", "m", EntryOperate::SMove, DataTypes::Set),
+            ))
+            .push(BatchOp::SAdd(entry(
+                "s",
+                "n",
+                EntryOperate::SAdd,
+                DataTypes::Set,
+            )))
+            .push(BatchOp::ZIncrBy(zscore(2.0, b"m")))
+            .push(BatchOp::ZAdd(zscore(1.0, b"n")))
+            .push(BatchOp::ZRem(entry(
+                "z",
+                "m",
+                EntryOperate::ZRem,
+                DataTypes::SortedSet,
+            )))
+            .push(BatchOp::JsonDel(
+                "$.a[-2]".to_owned(),
+                entry("j", "", EntryOperate::JsonDel, DataTypes::Json),
+            ))
+            .push(BatchOp::JsonSet(
+                "$.b".to_owned(),
+                entry("j", "3", EntryOperate::JsonSet, DataTypes::Json),
+            ))
+            .push(ts(1))
+            .push(ts(2))
+            .del("bucket", "a")
+            .del("bucket", "z")
+            // s is a set, the push fails
+            .push(BatchOp::LPush(entry(
+                "s",
+                "1",
+                EntryOperate::LLpush,
+                DataTypes::List,
+            )));
+        assert!(memtable.write_batch(batch).is_err());
+
+        let a = memtable.get("bucket", "a").unwrap().unwrap();
+        assert_eq!(a.value, Bytes::from("hello"));
+        assert_eq!(list(&memtable, "l"), vec!["x", "y", "z"]);
+        assert_eq!(memtable.key_type("bucket", "l2"), None);
+        assert_eq!(members(&memtable, "s"), vec!["m"]);
+        assert_eq!(memtable.key_type("bucket", "s2"), None);
+        let sorted_set = memtable.zset("bucket", "z").unwrap();
+        assert_eq!(sorted_set.score(b"m"), Some(1.0));
+        assert_eq!(sorted_set.length(), 1);
+        assert_eq!(
+            memtable.json_get("bucket", "j", "$").unwrap().unwrap(),
+            r#"{"a":[1,2]}"#
+        );
+        assert_eq!(memtable.key_type("bucket", "t"), None);
+
+        let mut batch = WriteBatch::new();
+        batch
+            .push(BatchOp::LInsert(
+                false,
+                Bytes::from("y"),
+                entry("l", "w", EntryOperate::LInsert, DataTypes::List),
+            ))
+            .push(BatchOp::LMove(
+                "l2".to_owned(),
+                ListDirection::Left,
+                ListDirection::Right,
+                entry("l", "", EntryOperate::LMove, DataTypes::List),
+            ))
+            .push(BatchOp::SMove(
+                "s2".to_owned(),
+                entry("s", "m", EntryOperate::SMove, DataTypes::Set),
+            ));
+        assert_eq!(memtable.write_batch(batch).unwrap(), 3);
+        assert_eq!(list(&memtable, "l"), vec!["y", "w", "z"]);
+        assert_eq!(list(&memtable, "l2"), vec!["x"]);
+        assert_eq!(memtable.key_type("bucket", "s"), None);
+        assert_eq!(members(&memtable, "s2"), vec!["m"]);
+    }
+}
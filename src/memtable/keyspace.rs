@@ -0,0 +1,371 @@
+use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use super::Memtable;
+use crate::{
+    bgworkers::bgworker::BgWorker,
+    data::entry::Entry,
+    datatypes::{json::JsonValue, quicklist::QuickList, sortedset::SortedSet, timeseries::Series},
+    enums::{DataTypes, EntryOperate},
+    errors::DbError,
+};
+
+// values with more elements than this are dropped by the lazy free worker on unlink.
+const LAZY_FREE_THRESHOLD: usize = 64;
+
+lazy_static! {
+    // LAZY_FREE drops the large values unlinked by every memtable on a single thread.
+    static ref LAZY_FREE: BgWorker<Vec<Value>> = BgWorker::new("lazy-free", |values: Vec<Value>| {
+        drop(values);
+        Ok(Bytes::new())
+    });
+}
+
+// Value is the value of a key taken out of the keyspace.
+pub(super) enum Value {
+    String(BytesMut),
+    List(QuickList),
+    Set(BTreeMap<Bytes, Bytes>),
+    SortedSet(SortedSet),
+    Json(JsonValue),
+    TimeSeries(Series),
+}
+
+impl Value {
+    fn len(&self) -> usize {
+        match self {
+            Value::String(_) | Value::Json(_) => 1,
+            Value::List(list) => list.len(),
+            Value::Set(members) => members.len(),
+            Value::SortedSet(sorted_set) => sorted_set.length(),
+            Value::TimeSeries(series) => series.len(),
+        }
+    }
+}
+
+// the keyspace of a bucket maps each of its keys, in key order, to the data type of its
+// value. a key holds a single data type, writing it as another one fails with
+// DbError::WrongType. the active memtable holds the keyspace of all the memtables, the
+// string keys flushed to the data files are looked up through FlushedStrings.
+impl Memtable {
+    pub fn key_type(&self, bucket: &str, key: &str) -> Option<DataTypes> {
+        match self
+            .keyspace
+            .get(bucket)
+            .and_then(|keyspace| keyspace.get(key))
+        {
+            // an expired string is kept until it is overwritten or deleted
+            Some(DataTypes::String) | None => {
+                self.string_exists(bucket, key).then_some(DataTypes::String)
+            }
+            Some(data_type) => Some(*data_type),
+        }
+    }
+
+    // exists returns how many of keys exist, a key given twice is counted twice.
+    pub fn exists(&self, bucket: &str, keys: &[&str]) -> usize {
+        keys.iter()
+            .filter(|key| self.key_type(bucket, key).is_some())
+            .count()
+    }
+
+    pub fn del(&mut self, bucket: &str, keys: &[&str]) -> Result<usize, DbError> {
+        let mut removed = 0;
+        for key in keys {
+            if self.remove_key(bucket, key)?.is_some() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    // unlink removes keys like del, large values are dropped on a background thread.
+    pub fn unlink(&mut self, bucket: &str, keys: &[&str]) -> Result<usize, DbError> {
+        let mut removed = 0;
+        let mut large = vec![];
+        for key in keys {
+            if let Some(value) = self.remove_key(bucket, key)? {
+                removed += 1;
+                if value.len() > LAZY_FREE_THRESHOLD {
+                    large.push(value);
+                }
+            }
+        }
+        if !large.is_empty() {
+            LAZY_FREE.send(large);
+        }
+        Ok(removed)
+    }
+
+    // rename moves key to newkey, replacing newkey whatever its type. the elements of a
+    // list or a set keep the key they were written with.
+    pub fn rename(&mut self, bucket: &str, key: &str, newkey: &str) -> Result<(), DbError> {
+        let data_type = self
+            .key_type(bucket, key)
+            .ok_or_else(|| DbError::KeyNotExist {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+            })?;
+        if key == newkey {
+            return Ok(());
+        }
+        let entry = Entry::new(
+            Bytes::from(bucket.to_owned()),
+            Bytes::from(key.to_owned()),
+            Bytes::from(newkey.to_owned()),
+            EntryOperate::Rename,
+            data_type,
+        );
+        self.log(entry.encode().as_ref())?;
+
+        self.take(bucket, newkey);
+        // the value of a string rotated out or flushed is read before it is hidden
+        let value = match data_type {
+            DataTypes::String => {
+                let value = self.string_value(bucket, key)?.map(Cow::into_owned);
+                self.take(bucket, key);
+                value.map(Value::String)
+            }
+            _ => self.take(bucket, key),
+        };
+        match value {
+            Some(Value::String(value)) => {
+                let mut renamed = Entry::decode_unchecked(value.as_ref());
+                renamed.key = Bytes::from(newkey.to_owned());
+                renamed.meta.key_size = renamed.key.len() as u32;
+                self.kvs
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(newkey.to_owned(), BytesMut::from(&renamed.encode()[..]));
+            }
+            Some(Value::List(list)) => {
+                self.list
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(newkey, list);
+                self.serve_list_waiters(bucket, newkey)?;
+            }
+            Some(Value::Set(members)) => {
+                self.set
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(newkey, members);
+            }
+            Some(Value::Json(doc)) => {
+                self.json
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(newkey, doc);
+            }
+            Some(Value::TimeSeries(series)) => {
+                self.timeseries
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(newkey, series);
+            }
+            Some(Value::SortedSet(sorted_set)) => {
+                self.sorted_set
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(newkey.to_owned(), sorted_set);
+                self.serve_zset_waiters(bucket, newkey)?;
+            }
+            None => {}
+        }
+        self.touch(bucket, newkey, data_type);
+        Ok(())
+    }
+
+    // take_over moves the values other than strings of old into this memtable as it replaces
+    // old as the active one, with the clients blocked on them and the whole keyspace. the
+    // memtables rotated out only keep strings, this memtable looks them up in older, newest
+    // first. the wal of old has to be kept as long as the values it logged live on.
+    pub fn take_over(&mut self, old: &mut Memtable, older: Vec<Arc<RwLock<Memtable>>>) {
+        self.list = std::mem::take(&mut old.list);
+        self.set = std::mem::take(&mut old.set);
+        self.json = std::mem::take(&mut old.json);
+        self.timeseries = std::mem::take(&mut old.timeseries);
+        self.sorted_set = std::mem::take(&mut old.sorted_set);
+        self.list_waiters = std::mem::take(&mut old.list_waiters);
+        self.zset_waiters = std::mem::take(&mut old.zset_waiters);
+        self.keyspace = std::mem::take(&mut old.keyspace);
+        self.older = older;
+        self.flushed = old.flushed.clone();
+    }
+
+    pub(super) fn check_type(&self, entry: &Entry, data_type: DataTypes) -> Result<(), DbError> {
+        let bucket = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let key = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        self.check_key_type(bucket, key, data_type)
+    }
+
+    pub(super) fn check_key_type(
+        &self,
+        bucket: &str,
+        key: &str,
+        data_type: DataTypes,
+    ) -> Result<(), DbError> {
+        match self.key_type(bucket, key) {
+            Some(key_type) if key_type != data_type => Err(DbError::WrongType {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+                data_type: key_type,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // remove_key logs the deletion of key and takes its value out of the keyspace.
+    pub(super) fn remove_key(&mut self, bucket: &str, key: &str) -> Result<Option<Value>, DbError> {
+        let Some(data_type) = self.key_type(bucket, key) else {
+            return Ok(None);
+        };
+        let entry = Entry::new(
+            Bytes::from(bucket.to_owned()),
+            Bytes::from(key.to_owned()),
+            Bytes::new(),
+            EntryOperate::Del,
+            data_type,
+        );
+        self.log(entry.encode().as_ref())?;
+        Ok(self.take(bucket, key))
+    }
+
+    fn take(&mut self, bucket: &str, key: &str) -> Option<Value> {
+        let data_type = self.key_type(bucket, key)?;
+        let value = self.take_value(bucket, key, data_type);
+        self.touch(bucket, key, data_type);
+        value
+    }
+
+    fn take_value(&mut self, bucket: &str, key: &str, data_type: DataTypes) -> Option<Value> {
+        match data_type {
+            DataTypes::String => {
+                // a string rotated out or flushed is hidden by the tombstone, it is not read
+                let value = self.kvs.get_mut(bucket).and_then(|kvs| kvs.remove(key));
+                self.tombstones
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(key.to_owned());
+                Some(Value::String(value.unwrap_or_default()))
+            }
+            DataTypes::List => self.list.get_mut(bucket)?.remove(key).map(Value::List),
+            DataTypes::Set => self.set.get_mut(bucket)?.remove(key).map(Value::Set),
+            DataTypes::SortedSet => {
+                let sorted_set = self.sorted_set.get_mut(bucket)?.remove(key);
+                sorted_set.map(Value::SortedSet)
+            }
+            DataTypes::Json => self.json.get_mut(bucket)?.remove(key).map(Value::Json),
+            DataTypes::TimeSeries => {
+                let series = self.timeseries.get_mut(bucket)?.remove(key);
+                series.map(Value::TimeSeries)
+            }
+        }
+    }
+
+    // put_back puts a value taken by remove_key back under key, the tombstone of a string is
+    // left to the caller.
+    pub(super) fn put_back(&mut self, bucket: &str, key: &str, value: Value) {
+        let data_type = match value {
+            Value::String(value) => {
+                if !value.is_empty() {
+                    self.kvs
+                        .entry(bucket.to_owned())
+                        .or_default()
+                        .insert(key.to_owned(), value);
+                }
+                DataTypes::String
+            }
+            Value::List(list) => {
+                self.list
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(key, list);
+                DataTypes::List
+            }
+            Value::Set(members) => {
+                self.set
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(key, members);
+                DataTypes::Set
+            }
+            Value::SortedSet(sorted_set) => {
+                self.sorted_set
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(key.to_owned(), sorted_set);
+                DataTypes::SortedSet
+            }
+            Value::Json(doc) => {
+                self.json
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(key, doc);
+                DataTypes::Json
+            }
+            Value::TimeSeries(series) => {
+                self.timeseries
+                    .entry(bucket.to_owned())
+                    .or_default()
+                    .insert(key, series);
+                DataTypes::TimeSeries
+            }
+        };
+        self.touch(bucket, key, data_type);
+    }
+
+    // touch records that key holds a value of data_type after it has been written, or drops
+    // it from the keyspace once that value is gone. every write of a key goes through it.
+    pub(super) fn touch(&mut self, bucket: &str, key: &str, data_type: DataTypes) {
+        if self.has_value(bucket, key, data_type) {
+            let keyspace = self.keyspace.entry(bucket.to_owned()).or_default();
+            if keyspace.get(key) != Some(&data_type) {
+                keyspace.insert(key.to_owned(), data_type);
+            }
+        } else if let Some(keyspace) = self.keyspace.get_mut(bucket) {
+            if keyspace.get(key) == Some(&data_type) {
+                keyspace.remove(key);
+            }
+        }
+    }
+
+    // has_value tells whether key holds a non-empty value in the map of data_type.
+    fn has_value(&self, bucket: &str, key: &str, data_type: DataTypes) -> bool {
+        match data_type {
+            DataTypes::String => self.string_exists(bucket, key),
+            DataTypes::List => {
+                self.list
+                    .get(bucket)
+                    .and_then(|list| list.llen(key))
+                    .unwrap_or(0)
+                    > 0
+            }
+            DataTypes::Set => {
+                self.set
+                    .get(bucket)
+                    .and_then(|set| set.scard(key))
+                    .unwrap_or(0)
+                    > 0
+            }
+            DataTypes::SortedSet => self
+                .sorted_set
+                .get(bucket)
+                .and_then(|zsets| zsets.get(key))
+                .is_some_and(|sorted_set| sorted_set.length() > 0),
+            DataTypes::Json => self
+                .json
+                .get(bucket)
+                .and_then(|json| json.get(key, &[]))
+                .is_some(),
+            DataTypes::TimeSeries => self
+                .timeseries
+                .get(bucket)
+                .and_then(|ts| ts.len(key))
+                .is_some(),
+        }
+    }
+}
@@ -1,32 +1,84 @@
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use crate::{
-    consts::ZESTKEYVALSPLITCHAR,
     datatypes::{
+        json::{self, Json, JsonValue, PathSegment},
         list::List,
+        scan,
         set::Set,
         sortedset::{ArcNode, SortedSet},
+        timeseries::{Added, TimeSeries},
     },
-    errors::DbError, index::Record,
+    errors::DbError,
+    fileio::FDManager,
+    index::Record,
 };
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use lazy_static::lazy_static;
+use log::warn;
 use num_enum::TryFromPrimitive;
+use parking_lot::{Mutex, RwLock};
 
 use crate::{
-    data::entry::Entry,
-    enums::{self, DataTypes, EntryOperate},
+    data::{entry::Entry, meta::Meta, payload, MAX_VALUE_SIZE},
+    enums::{self, DataTypes, EntryOperate, ListDirection, TsAggregation, ZAggregate},
     errors,
-    wal::Wal,
+    wal::{GroupCommit, Wal},
 };
-use std::ops::Bound::Included;
+use batch::BatchState;
+use std::ops::Bound::{self, Included};
+use std::sync::Arc;
+use std::time::Duration;
+use waiters::{ListWaiter, ListWaiters, ZSetWaiter, ZSetWaiters};
+
+pub mod batch;
+mod keyspace;
+mod replay;
+mod waiters;
+
+// MemTables are the memtables of a db, oldest first, the active memtable is the last one.
+pub type MemTables = Arc<RwLock<Vec<Arc<RwLock<Memtable>>>>>;
+
+// FlushedStrings looks up the string keys flushed out of the memtables to the data files,
+// the memtables fall back to it for the keys they do not hold.
+pub trait FlushedStrings: Send + Sync {
+    // contains tells whether key is flushed and neither deleted nor expired, it reads no
+    // data file.
+    fn contains(&self, bucket: &str, key: &str) -> bool;
+
+    // get reads the value of key, expired or deleted keys included.
+    fn get(&self, bucket: &str, key: &str) -> Result<Option<Entry>, DbError>;
+}
+
+lazy_static! {
+    static ref EMPTY_SORTED_SET: SortedSet = SortedSet::new();
+}
 
 pub struct Memtable {
     active: bool,
-    kvs: HashMap<String, BTreeMap<String, Bytes>>,
+    // the keys of each bucket in key order with the data type of their value
+    keyspace: HashMap<String, BTreeMap<String, DataTypes>>,
+    kvs: HashMap<String, BTreeMap<String, BytesMut>>,
+    // string keys deleted while in this memtable, they hide the older versions of the keys
+    // in the other memtables and on disk unless written again here
+    tombstones: HashMap<String, BTreeSet<String>>,
+    // the memtables rotated out before this one, newest first, and the string keys flushed
+    // to the data files. the strings this memtable does not hold are looked up in them.
+    older: Vec<Arc<RwLock<Memtable>>>,
+    flushed: Option<Arc<dyn FlushedStrings>>,
     list: HashMap<String, List>,
     set: HashMap<String, Set>,
-    sorted_set: HashMap<String, SortedSet>,
+    json: HashMap<String, Json>,
+    timeseries: HashMap<String, TimeSeries>,
+    sorted_set: HashMap<String, HashMap<String, SortedSet>>,
+    list_waiters: ListWaiters,
+    zset_waiters: ZSetWaiters,
     wal: Wal,
+    // the write batch being applied, see batch::BatchState
+    batch: Option<BatchState>,
+    // set while the wal is replayed by recover, the replayed operates are not logged again
+    replaying: bool,
     live_key_ratio: f64,
 }
 
@@ -36,14 +88,25 @@ impl Memtable {
         wal_path: &str,
         file_size_mb: u64,
         rw_mode: enums::RWMode,
+        fd_manager: Arc<Mutex<FDManager>>,
     ) -> Result<Self, errors::DbError> {
         Ok(Self {
             active: false,
+            keyspace: HashMap::new(),
             kvs: HashMap::new(),
+            tombstones: HashMap::new(),
+            older: vec![],
+            flushed: None,
             list: HashMap::new(),
             set: HashMap::new(),
+            json: HashMap::new(),
+            timeseries: HashMap::new(),
             sorted_set: HashMap::new(),
-            wal: Wal::new(file_id, wal_path, file_size_mb, rw_mode)?,
+            list_waiters: ListWaiters::default(),
+            zset_waiters: ZSetWaiters::default(),
+            wal: Wal::new(file_id, wal_path, file_size_mb, rw_mode, fd_manager)?,
+            batch: None,
+            replaying: false,
             live_key_ratio: 1.0,
         })
     }
@@ -56,10 +119,37 @@ impl Memtable {
         self.active = active
     }
 
+    // set_flushed makes the string keys of flushed visible to the memtable and to the ones
+    // taking over from it.
+    pub fn set_flushed(&mut self, flushed: Arc<dyn FlushedStrings>) {
+        self.flushed = Some(flushed)
+    }
+
+    pub fn wal_id(&self) -> u64 {
+        self.wal.file_id
+    }
+
+    // group_commit syncs the wal of the memtable, writers commit through it once they have
+    // released the memtable.
+    pub fn group_commit(&self) -> Arc<GroupCommit> {
+        Arc::clone(&self.wal.group_commit)
+    }
+
+    // seal_wal syncs the wal of the memtable once it is rotated out, see Wal::seal.
+    pub fn seal_wal(&self) -> Result<(), DbError> {
+        self.wal.seal()
+    }
+
+    // logged returns the wal offset after the last write, a write is durable once the wal is
+    // synced up to it.
+    pub fn logged(&self) -> u64 {
+        self.wal.write_at
+    }
+
     pub fn get(&self, bucket: &str, key: &str) -> Result<Option<Entry>, DbError> {
         if let Some(bucket) = self.kvs.get(bucket) {
             if let Some(entry_bytes) = bucket.get(key) {
-                let entry = Entry::decode(entry_bytes)?;
+                let entry = Entry::decode_unchecked(entry_bytes);
                 if entry.is_expired() {
                     return Ok(None);
                 }
@@ -73,16 +163,73 @@ impl Memtable {
         let mut res = vec![];
         if let Some(bucket) = self.kvs.get(bucket) {
             for (_, value) in bucket.range((Included(start.to_owned()), Included(end.to_owned()))) {
-                let entry = Entry::decode(value)?;
-                res.push(entry);
+                res.push(Entry::decode_unchecked(value));
             }
         }
         Ok(res)
     }
 
+    // string_range iterates the string keys of bucket within bounds in key order, the
+    // expired ones included.
+    pub fn string_range<'a>(
+        &'a self,
+        bucket: &str,
+        bounds: (Bound<&str>, Bound<&str>),
+    ) -> impl DoubleEndedIterator<Item = (&'a String, &'a BytesMut)> {
+        self.kvs
+            .get(bucket)
+            .map(|kvs| kvs.range::<str, _>(bounds))
+            .into_iter()
+            .flatten()
+    }
+
+    // tombstone_range iterates the string keys of bucket deleted within bounds in key order.
+    pub fn tombstone_range<'a>(
+        &'a self,
+        bucket: &str,
+        bounds: (Bound<&str>, Bound<&str>),
+    ) -> impl DoubleEndedIterator<Item = &'a String> {
+        self.tombstones
+            .get(bucket)
+            .map(|tombstones| tombstones.range::<str, _>(bounds))
+            .into_iter()
+            .flatten()
+    }
+
+    // scan iterates the keys of bucket in key order from cursor, only the keys of data_type
+    // if given. it returns the key to resume from, empty to start and once complete, see
+    // scan::scan.
+    pub fn scan(
+        &self,
+        bucket: &str,
+        cursor: &str,
+        pattern: Option<&str>,
+        count: usize,
+        data_type: Option<DataTypes>,
+    ) -> Result<(String, Vec<String>), DbError> {
+        let Some(keyspace) = self.keyspace.get(bucket) else {
+            return Ok((String::new(), vec![]));
+        };
+        let items = keyspace
+            .range::<str, _>((Bound::Included(cursor), Bound::Unbounded))
+            .filter(|(key, key_type)| {
+                data_type.is_none_or(|data_type| **key_type == data_type)
+                    && self.key_type(bucket, key).is_some()
+            })
+            .map(|(key, _)| (key.as_bytes(), key.clone()));
+        let (next, keys) = scan::scan(items, pattern, count);
+        let next = next.map(|next| String::from_utf8_lossy(next).into_owned());
+        Ok((next.unwrap_or_default(), keys))
+    }
+
     pub fn put(&mut self, entry: Entry) -> Result<&str, DbError> {
+        if entry.meta.data_type == DataTypes::String as u16
+            && entry.meta.operate != EntryOperate::Del as u16
+        {
+            self.check_type(&entry, DataTypes::String)?;
+        }
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
 
@@ -94,14 +241,17 @@ impl Memtable {
                     .kvs
                     .entry(bucket_name.clone())
                     .or_insert(BTreeMap::new());
+                let key = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
                 if entry.meta.operate == EntryOperate::Del as u16 {
-                    bucket.remove(&bucket_name);
-                    return Ok("ok");
+                    bucket.remove(key);
+                    self.tombstones
+                        .entry(bucket_name.clone())
+                        .or_default()
+                        .insert(key.to_owned());
+                } else {
+                    bucket.insert(key.to_owned(), BytesMut::from(&entry_bytes[..]));
                 }
-                bucket.insert(
-                    String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned()),
-                    Bytes::from(entry_bytes),
-                );
+                self.touch(&bucket_name, key, DataTypes::String);
             }
             _ => {
                 return Err(DbError::EntryDataTypeOpInvalid {
@@ -116,90 +266,509 @@ impl Memtable {
         Ok("ok")
     }
 
+    pub fn append(&mut self, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::String)?;
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
+        let len = self.strlen(&bucket_name, &entry_key_name)?;
+        check_value_size(&entry, len + entry.value.len())?;
+        // only the appended bytes are logged, the stored value is extended in place
+        let entry_bytes = entry.encode();
+        self.log(entry_bytes.as_ref())?;
+
+        self.own_string(&bucket_name, &entry_key_name)?;
+        let bucket = self.kvs.entry(bucket_name.clone()).or_default();
+        let len = match bucket.get_mut(&entry_key_name) {
+            Some(value_bytes) if !Meta::parse_entry_header_buf(value_bytes).is_expired() => {
+                Entry::append_encoded(value_bytes, &entry.value)
+            }
+            _ => {
+                bucket.insert(entry_key_name.clone(), BytesMut::from(&entry_bytes[..]));
+                entry.value.len()
+            }
+        };
+        self.touch(&bucket_name, &entry_key_name, DataTypes::String);
+        Ok(len)
+    }
+
+    pub fn setrange(&mut self, offset: usize, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::String)?;
+        if !entry.value.is_empty() {
+            check_value_size(&entry, offset.saturating_add(entry.value.len()))?;
+        }
+        self.write_wal_with_value(&entry, payload::encode_setrange(offset, &entry.value))?;
+
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
+        self.own_string(&bucket_name, &entry_key_name)?;
+        let bucket = self.kvs.entry(bucket_name.clone()).or_default();
+        let size = match bucket.get_mut(&entry_key_name) {
+            Some(value_bytes) if !Meta::parse_entry_header_buf(value_bytes).is_expired() => {
+                Entry::set_range_encoded(value_bytes, offset, &entry.value)
+            }
+            _ => {
+                if entry.value.is_empty() {
+                    return Ok(0);
+                }
+                let value = entry.value.clone();
+                let mut new_entry = entry;
+                new_entry.meta.value_size = 0;
+                new_entry.value = Bytes::new();
+                let mut value_bytes = BytesMut::from(&new_entry.encode()[..]);
+                let size = Entry::set_range_encoded(&mut value_bytes, offset, &value);
+                bucket.insert(entry_key_name.clone(), value_bytes);
+                size
+            }
+        };
+        self.touch(&bucket_name, &entry_key_name, DataTypes::String);
+        Ok(size)
+    }
+
+    pub fn getrange(
+        &self,
+        bucket: &str,
+        key: &str,
+        start: isize,
+        end: isize,
+    ) -> Result<Bytes, DbError> {
+        if let Some(entry_bytes) = self.string_value(bucket, key)? {
+            return Ok(Bytes::copy_from_slice(Entry::encoded_value_range(
+                &entry_bytes,
+                start,
+                end,
+            )));
+        }
+        Ok(Bytes::new())
+    }
+
+    pub fn strlen(&self, bucket: &str, key: &str) -> Result<usize, DbError> {
+        if let Some(entry_bytes) = self.string_value(bucket, key)? {
+            return Ok(Entry::encoded_value(&entry_bytes).len());
+        }
+        Ok(0)
+    }
+
+    // string_value returns the live value of a string key, held by this memtable or else by
+    // the ones rotated out before it or flushed, unless one of them deleted it.
+    fn string_value(&self, bucket: &str, key: &str) -> Result<Option<Cow<'_, BytesMut>>, DbError> {
+        let live = |entry_bytes: &BytesMut| !Meta::parse_entry_header_buf(entry_bytes).is_expired();
+        if let Some(entry_bytes) = self.own_version(bucket, key) {
+            return Ok(entry_bytes
+                .filter(|entry_bytes| live(entry_bytes))
+                .map(Cow::Borrowed));
+        }
+        for memtable in &self.older {
+            let memtable = memtable.read();
+            if let Some(entry_bytes) = memtable.own_version(bucket, key) {
+                return Ok(entry_bytes
+                    .filter(|entry_bytes| live(entry_bytes))
+                    .cloned()
+                    .map(Cow::Owned));
+            }
+        }
+        let Some(flushed) = &self.flushed else {
+            return Ok(None);
+        };
+        Ok(flushed
+            .get(bucket, key)?
+            .filter(|entry| !entry.is_expired() && entry.meta.operate != EntryOperate::Del as u16)
+            .map(|entry| Cow::Owned(BytesMut::from(&entry.encode()[..]))))
+    }
+
+    // string_exists tells whether a string key is live like string_value, without reading
+    // the data files.
+    fn string_exists(&self, bucket: &str, key: &str) -> bool {
+        let live = |entry_bytes: &BytesMut| !Meta::parse_entry_header_buf(entry_bytes).is_expired();
+        if let Some(entry_bytes) = self.own_version(bucket, key) {
+            return entry_bytes.is_some_and(live);
+        }
+        for memtable in &self.older {
+            if let Some(entry_bytes) = memtable.read().own_version(bucket, key) {
+                return entry_bytes.is_some_and(live);
+            }
+        }
+        self.flushed
+            .as_ref()
+            .is_some_and(|flushed| flushed.contains(bucket, key))
+    }
+
+    // own_version returns the version of a string key written to this memtable, none inside
+    // if it was deleted here, none if it was not written here.
+    fn own_version(&self, bucket: &str, key: &str) -> Option<Option<&BytesMut>> {
+        if let Some(entry_bytes) = self.kvs.get(bucket).and_then(|kvs| kvs.get(key)) {
+            return Some(Some(entry_bytes));
+        }
+        self.tombstones
+            .get(bucket)
+            .is_some_and(|tombstones| tombstones.contains(key))
+            .then_some(None)
+    }
+
+    // own_string copies the live value of a string key held by an older memtable or flushed
+    // into this memtable, so that it can be changed in place.
+    fn own_string(&mut self, bucket: &str, key: &str) -> Result<(), DbError> {
+        if self.own_version(bucket, key).is_some() {
+            return Ok(());
+        }
+        if let Some(entry_bytes) = self.string_value(bucket, key)? {
+            let entry_bytes = entry_bytes.into_owned();
+            self.kvs
+                .entry(bucket.to_owned())
+                .or_default()
+                .insert(key.to_owned(), entry_bytes);
+        }
+        Ok(())
+    }
+
     pub fn lpush(&mut self, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.list.entry(bucket_name).or_insert(List::new());
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
         bucket.lpush(&entry_key_name, vec![entry_bytes.into()]);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
+        self.serve_list_waiters(&bucket_name, &entry_key_name)?;
         Ok(1)
     }
 
     pub fn lpushx(&mut self, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.list.entry(bucket_name).or_insert(List::new());
-        Ok(bucket
-            .rpushx(&entry_key_name, vec![entry_bytes.into()])
-            .unwrap_or(0))
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
+        let pushed = bucket
+            .lpushx(&entry_key_name, vec![entry_bytes.into()])
+            .unwrap_or(0);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
+        self.serve_list_waiters(&bucket_name, &entry_key_name)?;
+        Ok(pushed)
     }
 
     pub fn rpush(&mut self, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.list.entry(bucket_name).or_insert(List::new());
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
         bucket.rpush(&entry_key_name, vec![entry_bytes.into()]);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
+        self.serve_list_waiters(&bucket_name, &entry_key_name)?;
 
         Ok(1)
     }
 
     pub fn rpushx(&mut self, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.list.entry(bucket_name).or_insert(List::new());
-        Ok(bucket
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
+        let pushed = bucket
             .rpushx(&entry_key_name, vec![entry_bytes.into()])
-            .unwrap_or(0))
+            .unwrap_or(0);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
+        self.serve_list_waiters(&bucket_name, &entry_key_name)?;
+        Ok(pushed)
     }
 
     pub fn lpop(&mut self, entry: Entry) -> Result<Option<Entry>, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.list.entry(bucket_name).or_insert(List::new());
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
+        let popped = bucket.lpop(&entry_key_name);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
 
-        match bucket.lpop(&entry_key_name) {
+        match popped {
             Some(entry_bytes) => Ok(Some(Entry::decode(entry_bytes.as_ref())?)),
             None => Ok(None),
         }
     }
 
     pub fn rpop(&mut self, entry: Entry) -> Result<Option<Entry>, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.list.entry(bucket_name).or_insert(List::new());
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
+        let popped = bucket.rpop(&entry_key_name);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
 
-        match bucket.rpop(&entry_key_name) {
+        match popped {
             Some(entry_bytes) => Ok(Some(Entry::decode(entry_bytes.as_ref())?)),
             None => Ok(None),
         }
     }
 
+    pub fn lrem(&mut self, count: isize, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
+        self.write_wal_with_value(&entry, payload::encode_lrem(count, &entry.value))?;
+
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let removed = match self.list.get_mut(bucket_name) {
+            Some(bucket) => bucket
+                .lrem(entry_key_name, count, |item| {
+                    Entry::encoded_value(item) == entry.value
+                })
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.touch(bucket_name, entry_key_name, DataTypes::List);
+        Ok(removed)
+    }
+
+    pub fn linsert(
+        &mut self,
+        before: bool,
+        pivot: Bytes,
+        entry: Entry,
+    ) -> Result<Option<usize>, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
+        self.write_wal_with_value(
+            &entry,
+            payload::encode_linsert(before, &pivot, &entry.value),
+        )?;
+
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let len = self.list.get_mut(bucket_name).and_then(|bucket| {
+            bucket.linsert(
+                entry_key_name,
+                before,
+                |item| Entry::encoded_value(item) == pivot,
+                Bytes::from(entry.encode()),
+            )
+        });
+        self.touch(bucket_name, entry_key_name, DataTypes::List);
+        Ok(len)
+    }
+
+    pub fn ltrim(&mut self, start: isize, end: isize, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
+        self.write_wal_with_value(&entry, payload::encode_ltrim(start, end))?;
+
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let removed = match self.list.get_mut(bucket_name) {
+            Some(bucket) => bucket.ltrim(entry_key_name, start, end).unwrap_or(0),
+            None => 0,
+        };
+        self.touch(bucket_name, entry_key_name, DataTypes::List);
+        Ok(removed)
+    }
+
+    // lmove pops an element from source, the key of entry, and pushes it to destination.
+    pub fn lmove(
+        &mut self,
+        destination: &str,
+        wherefrom: ListDirection,
+        whereto: ListDirection,
+        entry: Entry,
+    ) -> Result<Option<Entry>, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        self.check_key_type(&bucket_name, destination, DataTypes::List)?;
+        self.write_wal_with_value(
+            &entry,
+            payload::encode_lmove(destination, wherefrom, whereto),
+        )?;
+
+        let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
+        let popped = match wherefrom {
+            ListDirection::Left => bucket.lpop(&entry_key_name),
+            ListDirection::Right => bucket.rpop(&entry_key_name),
+        };
+        let Some(entry_bytes) = popped else {
+            return Ok(None);
+        };
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
+
+        let mut moved = Entry::decode(entry_bytes.as_ref())?;
+        moved.key = Bytes::from(destination.to_owned());
+        moved.meta.key_size = moved.key.len() as u32;
+        let moved_bytes = Bytes::from(moved.encode());
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
+        match whereto {
+            ListDirection::Left => bucket.lpush(destination, vec![moved_bytes]),
+            ListDirection::Right => bucket.rpush(destination, vec![moved_bytes]),
+        };
+        self.touch(&bucket_name, destination, DataTypes::List);
+        self.serve_list_waiters(&bucket_name, destination)?;
+        Ok(Some(moved))
+    }
+
+    // blpop pops the head of the first non-empty list of keys, blocking until an element is
+    // pushed or the timeout expires. a zero timeout blocks forever.
+    pub fn blpop(
+        mem_tables: &MemTables,
+        bucket: &str,
+        keys: &[&str],
+        timeout: Duration,
+    ) -> Result<Option<(String, Entry)>, DbError> {
+        Self::block_pop(mem_tables, bucket, keys, ListDirection::Left, None, timeout)
+    }
+
+    pub fn brpop(
+        mem_tables: &MemTables,
+        bucket: &str,
+        keys: &[&str],
+        timeout: Duration,
+    ) -> Result<Option<(String, Entry)>, DbError> {
+        Self::block_pop(
+            mem_tables,
+            bucket,
+            keys,
+            ListDirection::Right,
+            None,
+            timeout,
+        )
+    }
+
+    pub fn blmove(
+        mem_tables: &MemTables,
+        bucket: &str,
+        source: &str,
+        destination: &str,
+        wherefrom: ListDirection,
+        whereto: ListDirection,
+        timeout: Duration,
+    ) -> Result<Option<Entry>, DbError> {
+        let dest = Some((destination.to_owned(), whereto));
+        let moved = Self::block_pop(mem_tables, bucket, &[source], wherefrom, dest, timeout)?;
+        Ok(moved.map(|(_, entry)| entry))
+    }
+
+    // block_pop waits on the active memtable of mem_tables, the waiters move with the
+    // lists to the memtable that replaces it.
+    fn block_pop(
+        mem_tables: &MemTables,
+        bucket: &str,
+        keys: &[&str],
+        wherefrom: ListDirection,
+        dest: Option<(String, ListDirection)>,
+        timeout: Duration,
+    ) -> Result<Option<(String, Entry)>, DbError> {
+        let (waiter, receiver) = ListWaiter::new(wherefrom, dest);
+        let popped = with_active(mem_tables, |memtable| {
+            for key in keys {
+                if memtable.llen(bucket, key)? > 0 {
+                    let popped = memtable.waiter_pop(bucket, key, &waiter)?;
+                    return Ok(Some(popped.map(|entry| (key.to_string(), entry))));
+                }
+            }
+            memtable.list_waiters.register(bucket, keys, &waiter);
+            Ok::<_, DbError>(None)
+        })?;
+        if let Some(popped) = popped {
+            return Ok(popped);
+        }
+
+        let popped = if timeout.is_zero() {
+            receiver.recv().ok()
+        } else {
+            receiver.recv_timeout(timeout).ok()
+        };
+
+        // the waiter is only served under the memtable lock, so once it is unregistered
+        // nothing can be sent to it anymore
+        with_active(mem_tables, |memtable| {
+            memtable.list_waiters.unregister(bucket, keys, &waiter)
+        });
+        Ok(popped.or_else(|| receiver.try_recv().ok()))
+    }
+
+    // serve_list_waiters hands the elements of the list to the clients blocked on it,
+    // longest waiting first. within a write batch they are served once it is logged. the
+    // waiters that could not be served, like a blmove to a key of another type, go on
+    // waiting in front.
+    fn serve_list_waiters(&mut self, bucket: &str, key: &str) -> Result<(), DbError> {
+        if let Some(batch) = self.batch.as_mut() {
+            batch.list_keys.push((bucket.to_owned(), key.to_owned()));
+            return Ok(());
+        }
+        let mut unserved = vec![];
+        while self.llen(bucket, key).unwrap_or(0) > 0 {
+            let Some(waiter) = self.list_waiters.pop_front(bucket, key) else {
+                break;
+            };
+            match self.waiter_pop(bucket, key, &waiter) {
+                Ok(Some(entry)) => waiter.serve(key.to_owned(), entry),
+                Ok(None) => unserved.push(waiter),
+                // the write that woke the waiters succeeded, the error is the waiter's
+                Err(err) => {
+                    warn!("blocked client on {} {} not served: {}", bucket, key, err);
+                    unserved.push(waiter);
+                }
+            }
+        }
+        self.list_waiters.requeue(bucket, key, unserved);
+        Ok(())
+    }
+
+    fn waiter_pop(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        waiter: &ListWaiter,
+    ) -> Result<Option<Entry>, DbError> {
+        let bucket_bytes = Bytes::from(bucket.to_owned());
+        let key_bytes = Bytes::from(key.to_owned());
+        if let Some((dest, whereto)) = &waiter.dest {
+            let entry = Entry::new(
+                bucket_bytes,
+                key_bytes,
+                Bytes::new(),
+                EntryOperate::LMove,
+                DataTypes::List,
+            );
+            return self.lmove(dest, waiter.wherefrom, *whereto, entry);
+        }
+
+        match waiter.wherefrom {
+            ListDirection::Left => self.lpop(Entry::new(
+                bucket_bytes,
+                key_bytes,
+                Bytes::new(),
+                EntryOperate::LLpop,
+                DataTypes::List,
+            )),
+            ListDirection::Right => self.rpop(Entry::new(
+                bucket_bytes,
+                key_bytes,
+                Bytes::new(),
+                EntryOperate::LRpop,
+                DataTypes::List,
+            )),
+        }
+    }
+
     pub fn lset(&mut self, index: usize, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::List)?;
+        self.write_wal_with_value(&entry, payload::encode_lset(index, &entry.value))?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.list.entry(bucket_name).or_insert(List::new());
+        let bucket = self.list.entry(bucket_name.clone()).or_insert(List::new());
         bucket.lset(&entry_key_name, index, Bytes::from(entry_bytes));
-
+        self.touch(&bucket_name, &entry_key_name, DataTypes::List);
         Ok(1)
     }
 
@@ -222,6 +791,13 @@ impl Memtable {
         Ok(None)
     }
 
+    // lpos returns the index of the first element of the list whose value is value
+    pub fn lpos(&self, bucket: &str, key: &str, value: &[u8]) -> Option<usize> {
+        self.list
+            .get(bucket)?
+            .lpos(key, |item| Entry::encoded_value(item) == value)
+    }
+
     pub fn lrange(
         &self,
         bucket: &str,
@@ -236,27 +812,32 @@ impl Memtable {
     }
 
     pub fn sadd(&mut self, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::Set)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.set.entry(bucket_name).or_insert(Set::new());
-        bucket.sadd(&entry_key_name, vec![Bytes::from(entry_bytes)]);
-
-        Ok(1)
+        let bucket = self.set.entry(bucket_name.clone()).or_insert(Set::new());
+        let member = (entry.value.clone(), Bytes::from(entry_bytes));
+        let added = bucket.sadd(&entry_key_name, vec![member]).unwrap_or(0);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::Set);
+        Ok(added)
     }
 
     pub fn srem(&mut self, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::Set)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
         let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let bucket = self.set.entry(bucket_name).or_insert(Set::new());
-        bucket.srem(&entry_key_name, vec![Bytes::from(entry_bytes)]);
-
-        Ok(1)
+        let bucket = self.set.entry(bucket_name.clone()).or_insert(Set::new());
+        let removed = bucket
+            .srem(&entry_key_name, vec![entry.value.clone()])
+            .unwrap_or(0);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::Set);
+        Ok(removed)
     }
 
     pub fn suion(&self, bucket: &str, key: &str, keys: Vec<&str>) -> Result<Vec<Bytes>, DbError> {
@@ -282,12 +863,11 @@ impl Memtable {
     }
 
     pub fn sismember(&self, entry: Entry) -> Result<bool, DbError> {
-        let entry_bytes = Bytes::from(entry.encode());
         let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
         let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
         if let Some(bucket) = self.set.get(bucket_name) {
             return Ok(bucket
-                .sismember(entry_key_name, entry_bytes)
+                .sismember(entry_key_name, entry.value)
                 .unwrap_or(false));
         }
         Ok(false)
@@ -308,95 +888,986 @@ impl Memtable {
         Ok(0)
     }
 
+    // spop removes count random members of the key of entry, they are chosen first and logged
+    // as removed in a single wal record so that the wal replays the same result, then removed.
+    pub fn spop(&mut self, count: usize, entry: Entry) -> Result<Vec<Entry>, DbError> {
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let chosen = self
+            .set
+            .get(bucket_name)
+            .and_then(|set| {
+                set.srandmember(entry_key_name, isize::try_from(count).unwrap_or(isize::MAX))
+            })
+            .unwrap_or_default();
+        if chosen.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut popped = vec![];
+        for member in chosen {
+            let mut member_entry = Entry::decode(member.as_ref())?;
+            member_entry.meta.operate = EntryOperate::SRem as u16;
+            // a member keeps the key it was added with, it is removed from the current one
+            member_entry.key = entry.key.clone();
+            member_entry.meta.key_size = entry.key.len() as u32;
+            popped.push(member_entry);
+        }
+        self.log_all(popped.iter().map(|member| member.encode()).collect())?;
+
+        if let Some(set) = self.set.get_mut(bucket_name) {
+            set.srem(
+                entry_key_name,
+                popped.iter().map(|member| member.value.clone()).collect(),
+            );
+        }
+        self.touch(bucket_name, entry_key_name, DataTypes::Set);
+        Ok(popped)
+    }
+
+    pub fn srandmember(
+        &self,
+        bucket: &str,
+        key: &str,
+        count: isize,
+    ) -> Result<Vec<Bytes>, DbError> {
+        if let Some(bucket) = self.set.get(bucket) {
+            return Ok(bucket.srandmember(key, count).unwrap_or(vec![]));
+        }
+        Ok(vec![])
+    }
+
+    pub fn sscan(
+        &self,
+        bucket: &str,
+        key: &str,
+        cursor: &[u8],
+        pattern: Option<&str>,
+        count: usize,
+    ) -> Result<(Bytes, Vec<Bytes>), DbError> {
+        if let Some(bucket) = self.set.get(bucket) {
+            return Ok(bucket
+                .sscan(key, cursor, pattern, count)
+                .unwrap_or_default());
+        }
+        Ok((Bytes::new(), vec![]))
+    }
+
+    // smove moves the member that is the value of entry from the key of entry to destination.
+    pub fn smove(&mut self, destination: &str, entry: Entry) -> Result<usize, DbError> {
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        self.check_key_type(bucket_name, destination, DataTypes::Set)?;
+        self.write_wal_with_value(&entry, payload::encode_smove(destination, &entry.value))?;
+
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let moved = match self.set.get_mut(bucket_name) {
+            Some(bucket) => bucket
+                .smove(entry_key_name, destination, &entry.value)
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.touch(bucket_name, entry_key_name, DataTypes::Set);
+        self.touch(bucket_name, destination, DataTypes::Set);
+        Ok(moved)
+    }
+
+    pub fn sdiffstore(
+        &mut self,
+        bucket: &str,
+        destination: &str,
+        key: &str,
+        keys: Vec<&str>,
+    ) -> Result<usize, DbError> {
+        self.check_key_type(bucket, destination, DataTypes::Set)?;
+        self.write_sstore_wal(bucket, destination, key, &keys, EntryOperate::SDiffStore)?;
+        let stored = self
+            .set
+            .entry(bucket.to_owned())
+            .or_insert(Set::new())
+            .sdiffstore(destination, key, keys)
+            .unwrap_or(0);
+        self.touch(bucket, destination, DataTypes::Set);
+        Ok(stored)
+    }
+
+    pub fn sinterstore(
+        &mut self,
+        bucket: &str,
+        destination: &str,
+        key: &str,
+        keys: Vec<&str>,
+    ) -> Result<usize, DbError> {
+        self.check_key_type(bucket, destination, DataTypes::Set)?;
+        self.write_sstore_wal(bucket, destination, key, &keys, EntryOperate::SInterStore)?;
+        let stored = self
+            .set
+            .entry(bucket.to_owned())
+            .or_insert(Set::new())
+            .sinterstore(destination, key, keys)
+            .unwrap_or(0);
+        self.touch(bucket, destination, DataTypes::Set);
+        Ok(stored)
+    }
+
+    pub fn suionstore(
+        &mut self,
+        bucket: &str,
+        destination: &str,
+        key: &str,
+        keys: Vec<&str>,
+    ) -> Result<usize, DbError> {
+        self.check_key_type(bucket, destination, DataTypes::Set)?;
+        self.write_sstore_wal(bucket, destination, key, &keys, EntryOperate::SUnionStore)?;
+        let stored = self
+            .set
+            .entry(bucket.to_owned())
+            .or_insert(Set::new())
+            .suionstore(destination, key, keys)
+            .unwrap_or(0);
+        self.touch(bucket, destination, DataTypes::Set);
+        Ok(stored)
+    }
+
+    // write_sstore_wal logs the source keys of a *store operate rather than its result,
+    // replaying it recomputes the result from the same state.
+    fn write_sstore_wal(
+        &mut self,
+        bucket: &str,
+        destination: &str,
+        key: &str,
+        keys: &[&str],
+        operate: EntryOperate,
+    ) -> Result<usize, DbError> {
+        let mut source_keys = vec![key];
+        source_keys.extend_from_slice(keys);
+        let entry = Entry::new(
+            Bytes::from(bucket.to_owned()),
+            Bytes::from(destination.to_owned()),
+            payload::encode_sstore(&source_keys),
+            operate,
+            DataTypes::Set,
+        );
+        self.log(entry.encode().as_ref())
+    }
+
+    // the sorted set entries carry the score and the member in their value, see
+    // payload::encode_zscore. the nodes of a sorted set hold the entry of their member.
     pub fn zadd(&mut self, entry: Entry) -> Result<usize, DbError> {
-        let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let key_score: Vec<&str> = entry_key_name.split(ZESTKEYVALSPLITCHAR).collect();
-        let key = key_score[0];
-        let score = key_score[1].parse::<f64>().unwrap_or(0.0);
+        self.check_type(&entry, DataTypes::SortedSet)?;
+        let (score, member) = zscore_payload(&entry)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
-        let bucket = self
+        let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
+        let added = self
             .sorted_set
-            .entry(bucket_name)
-            .or_insert(SortedSet::new());
-        Ok(bucket.put(key, Bytes::from(entry_bytes), score))
+            .entry(bucket_name.clone())
+            .or_default()
+            .entry(entry_key_name.clone())
+            .or_default()
+            .put(member, Bytes::from(entry_bytes), score);
+        self.touch(&bucket_name, &entry_key_name, DataTypes::SortedSet);
+        self.serve_zset_waiters(&bucket_name, &entry_key_name)?;
+        Ok(added)
     }
 
+    // zrem removes the member that is the value of entry.
     pub fn zrem(&mut self, entry: Entry) -> Result<Option<ArcNode>, DbError> {
-        let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
-        let key_score: Vec<&str> = entry_key_name.split(ZESTKEYVALSPLITCHAR).collect();
-        let key = key_score[0];
+        self.check_type(&entry, DataTypes::SortedSet)?;
         let entry_bytes = entry.encode();
-        self.wal.write(entry_bytes.as_ref())?;
+        self.log(entry_bytes.as_ref())?;
 
         let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
-        if let Some(bucket) = self.sorted_set.get_mut(bucket_name) {
-            return Ok(bucket.remove(key));
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let removed = self
+            .zset_mut(bucket_name, entry_key_name)
+            .and_then(|sorted_set| sorted_set.remove(entry.value.as_ref()));
+        self.touch(bucket_name, entry_key_name, DataTypes::SortedSet);
+        Ok(removed)
+    }
+
+    // zincrby takes the increment from the entry value payload, see payload::encode_zscore.
+    // the increment is logged, the node holds an entry carrying the resulting score.
+    pub fn zincrby(&mut self, entry: Entry) -> Result<f64, DbError> {
+        self.check_type(&entry, DataTypes::SortedSet)?;
+        let (increment, member) = zscore_payload(&entry)?;
+        self.log(entry.encode().as_ref())?;
+
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned());
+        let sorted_set = self
+            .sorted_set
+            .entry(bucket_name.clone())
+            .or_default()
+            .entry(entry_key_name.clone())
+            .or_default();
+        let score = sorted_set.incr_by(member, increment, |score| {
+            let mut stored = entry.clone();
+            stored.meta.operate = EntryOperate::ZPut as u16;
+            stored.value = payload::encode_zscore(score, member);
+            stored.meta.value_size = stored.value.len() as u32;
+            Bytes::from(stored.encode())
+        });
+        self.touch(&bucket_name, &entry_key_name, DataTypes::SortedSet);
+        self.serve_zset_waiters(&bucket_name, &entry_key_name)?;
+        Ok(score)
+    }
+
+    // zpopmin removes the count lowest members of the sorted set key, they are logged as
+    // removed so that the wal replays the same result.
+    pub fn zpopmin(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        count: usize,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        self.zpop(bucket, key, count, false)
+    }
+
+    // zpopmax removes the count highest members, highest first.
+    pub fn zpopmax(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        count: usize,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        self.zpop(bucket, key, count, true)
+    }
+
+    // bzpopmin pops the lowest member of the first non-empty sorted set of keys, blocking
+    // until a member is added or the timeout expires. a zero timeout blocks forever.
+    pub fn bzpopmin(
+        mem_tables: &MemTables,
+        bucket: &str,
+        keys: &[&str],
+        timeout: Duration,
+    ) -> Result<Option<(String, ArcNode)>, DbError> {
+        Self::block_zpop(mem_tables, bucket, keys, false, timeout)
+    }
+
+    pub fn bzpopmax(
+        mem_tables: &MemTables,
+        bucket: &str,
+        keys: &[&str],
+        timeout: Duration,
+    ) -> Result<Option<(String, ArcNode)>, DbError> {
+        Self::block_zpop(mem_tables, bucket, keys, true, timeout)
+    }
+
+    pub fn zmscore(
+        &self,
+        bucket: &str,
+        key: &str,
+        members: &[&[u8]],
+    ) -> Result<Vec<Option<f64>>, DbError> {
+        let sorted_set = self.zset(bucket, key);
+        Ok(members
+            .iter()
+            .map(|member| sorted_set.and_then(|sorted_set| sorted_set.score(member)))
+            .collect())
+    }
+
+    // zrandmember returns count distinct random members when count is positive, or -count
+    // members which may repeat when count is negative.
+    pub fn zrandmember(
+        &self,
+        bucket: &str,
+        key: &str,
+        count: isize,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        if let Some(sorted_set) = self.zset(bucket, key) {
+            return Ok(sorted_set.rand_members(count));
         }
+        Ok(vec![])
+    }
 
-        Ok(None)
+    fn zpop(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        count: usize,
+        max: bool,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        let victims = match self.zset_mut(bucket, key) {
+            Some(sorted_set) if count > 0 && sorted_set.length() > 0 => {
+                let len = sorted_set.length();
+                let count = count.min(len);
+                match max {
+                    true => {
+                        let mut victims = sorted_set.get_by_rank_range(len - count + 1, len, false);
+                        victims.reverse();
+                        victims
+                    }
+                    false => sorted_set.get_by_rank_range(1, count, false),
+                }
+            }
+            _ => vec![],
+        };
+        self.zrem_nodes(bucket, key, victims)
+    }
+
+    // block_zpop waits on the active memtable of mem_tables like block_pop.
+    fn block_zpop(
+        mem_tables: &MemTables,
+        bucket: &str,
+        keys: &[&str],
+        max: bool,
+        timeout: Duration,
+    ) -> Result<Option<(String, ArcNode)>, DbError> {
+        let (waiter, receiver) = ZSetWaiter::new(max);
+        let popped = with_active(mem_tables, |memtable| {
+            for key in keys {
+                if let Some(node) = memtable.waiter_zpop(bucket, key, &waiter)? {
+                    return Ok(Some((key.to_string(), node)));
+                }
+            }
+            memtable.zset_waiters.register(bucket, keys, &waiter);
+            Ok::<_, DbError>(None)
+        })?;
+        if popped.is_some() {
+            return Ok(popped);
+        }
+
+        let popped = if timeout.is_zero() {
+            receiver.recv().ok()
+        } else {
+            receiver.recv_timeout(timeout).ok()
+        };
+
+        with_active(mem_tables, |memtable| {
+            memtable.zset_waiters.unregister(bucket, keys, &waiter)
+        });
+        Ok(popped.or_else(|| receiver.try_recv().ok()))
+    }
+
+    // serve_zset_waiters hands the lowest or highest members of the sorted set to the
+    // clients blocked on it, longest waiting first, once the write batch if any is logged.
+    fn serve_zset_waiters(&mut self, bucket: &str, key: &str) -> Result<(), DbError> {
+        if let Some(batch) = self.batch.as_mut() {
+            batch.zset_keys.push((bucket.to_owned(), key.to_owned()));
+            return Ok(());
+        }
+        let mut unserved = vec![];
+        while self.zcard(bucket, key) > 0 {
+            let Some(waiter) = self.zset_waiters.pop_front(bucket, key) else {
+                break;
+            };
+            match self.waiter_zpop(bucket, key, &waiter) {
+                Ok(Some(node)) => waiter.serve(key.to_owned(), node),
+                Ok(None) => unserved.push(waiter),
+                // the write that woke the waiters succeeded, the error is the waiter's
+                Err(err) => {
+                    warn!("blocked client on {} {} not served: {}", bucket, key, err);
+                    unserved.push(waiter);
+                }
+            }
+        }
+        self.zset_waiters.requeue(bucket, key, unserved);
+        Ok(())
+    }
+
+    fn waiter_zpop(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        waiter: &ZSetWaiter,
+    ) -> Result<Option<ArcNode>, DbError> {
+        Ok(self.zpop(bucket, key, 1, waiter.max)?.pop())
+    }
+
+    fn zcard(&self, bucket: &str, key: &str) -> usize {
+        self.zset(bucket, key)
+            .map_or(0, |sorted_set| sorted_set.length())
+    }
+
+    fn zset(&self, bucket: &str, key: &str) -> Option<&SortedSet> {
+        self.sorted_set.get(bucket)?.get(key)
+    }
+
+    fn zset_mut(&mut self, bucket: &str, key: &str) -> Option<&mut SortedSet> {
+        self.sorted_set.get_mut(bucket)?.get_mut(key)
+    }
+
+    pub fn zunion(
+        &self,
+        bucket: &str,
+        keys: Vec<&str>,
+        weights: Vec<f64>,
+        aggregate: ZAggregate,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        let mut union = SortedSet::union(&self.sorted_sets(bucket, &keys), &weights, aggregate);
+        Ok(union.get_by_rank_range(1, union.length(), false))
+    }
+
+    pub fn zinter(
+        &self,
+        bucket: &str,
+        keys: Vec<&str>,
+        weights: Vec<f64>,
+        aggregate: ZAggregate,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        let mut inter = SortedSet::inter(&self.sorted_sets(bucket, &keys), &weights, aggregate);
+        Ok(inter.get_by_rank_range(1, inter.length(), false))
+    }
+
+    pub fn zunionstore(
+        &mut self,
+        bucket: &str,
+        dest: &str,
+        keys: Vec<&str>,
+        weights: Vec<f64>,
+        aggregate: ZAggregate,
+    ) -> Result<usize, DbError> {
+        self.check_key_type(bucket, dest, DataTypes::SortedSet)?;
+        let union = SortedSet::union(&self.sorted_sets(bucket, &keys), &weights, aggregate);
+        self.zstore(bucket, dest, union)
+    }
+
+    pub fn zinterstore(
+        &mut self,
+        bucket: &str,
+        dest: &str,
+        keys: Vec<&str>,
+        weights: Vec<f64>,
+        aggregate: ZAggregate,
+    ) -> Result<usize, DbError> {
+        self.check_key_type(bucket, dest, DataTypes::SortedSet)?;
+        let inter = SortedSet::inter(&self.sorted_sets(bucket, &keys), &weights, aggregate);
+        self.zstore(bucket, dest, inter)
+    }
+
+    fn sorted_sets(&self, bucket: &str, keys: &[&str]) -> Vec<&SortedSet> {
+        let empty: &SortedSet = &EMPTY_SORTED_SET;
+        keys.iter()
+            .map(|key| self.zset(bucket, key).unwrap_or(empty))
+            .collect()
+    }
+
+    // zstore replaces the dest sorted set with the given one, the old set is logged as deleted
+    // and every member is logged as a put into dest, all in a single wal record.
+    fn zstore(
+        &mut self,
+        bucket: &str,
+        dest: &str,
+        mut sorted_set: SortedSet,
+    ) -> Result<usize, DbError> {
+        let del_entry = Entry::new(
+            Bytes::from(bucket.to_owned()),
+            Bytes::from(dest.to_owned()),
+            Bytes::new(),
+            EntryOperate::Del,
+            DataTypes::SortedSet,
+        );
+        let mut records = vec![del_entry.encode()];
+
+        let mut dest_set = SortedSet::new();
+        for node in sorted_set.get_by_rank_range(1, sorted_set.length(), false) {
+            let node_b = node.borrow();
+            let entry = Entry::new(
+                del_entry.meta.bucket.clone(),
+                del_entry.key.clone(),
+                payload::encode_zscore(node_b.score, &node_b.key),
+                EntryOperate::ZPut,
+                DataTypes::SortedSet,
+            );
+            let entry_bytes = entry.encode();
+            dest_set.put(&node_b.key, Bytes::from(entry_bytes.clone()), node_b.score);
+            records.push(entry_bytes);
+        }
+        self.log_all(records)?;
+
+        let len = dest_set.length();
+        self.sorted_set
+            .entry(bucket.to_owned())
+            .or_default()
+            .insert(dest.to_owned(), dest_set);
+        self.touch(bucket, dest, DataTypes::SortedSet);
+        self.serve_zset_waiters(bucket, dest)?;
+        Ok(len)
+    }
+
+    pub fn zrangebylex(
+        &self,
+        bucket: &str,
+        key: &str,
+        min: Bound<&[u8]>,
+        max: Bound<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        if let Some(sorted_set) = self.zset(bucket, key) {
+            return Ok(sorted_set.get_by_lex_range(min, max, limit));
+        }
+        Ok(vec![])
+    }
+
+    pub fn zlexcount(
+        &self,
+        bucket: &str,
+        key: &str,
+        min: Bound<&[u8]>,
+        max: Bound<&[u8]>,
+    ) -> Result<usize, DbError> {
+        if let Some(sorted_set) = self.zset(bucket, key) {
+            return Ok(sorted_set.lex_count(min, max));
+        }
+        Ok(0)
+    }
+
+    pub fn zremrangebylex(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        min: Bound<&[u8]>,
+        max: Bound<&[u8]>,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        let Some(sorted_set) = self.zset(bucket, key) else {
+            return Ok(vec![]);
+        };
+        let removed = sorted_set.get_by_lex_range(min, max, usize::MAX);
+        self.zrem_nodes(bucket, key, removed)
+    }
+
+    pub fn zscan(
+        &self,
+        bucket: &str,
+        key: &str,
+        cursor: &[u8],
+        pattern: Option<&str>,
+        count: usize,
+    ) -> Result<(Bytes, Vec<ArcNode>), DbError> {
+        if let Some(sorted_set) = self.zset(bucket, key) {
+            return Ok(sorted_set.scan(cursor, pattern, count));
+        }
+        Ok((Bytes::new(), vec![]))
     }
 
     pub fn get_by_rank_range(
         &mut self,
         bucket: &str,
+        key: &str,
         start: usize,
         end: usize,
         remove: bool,
     ) -> Result<Vec<ArcNode>, DbError> {
-        if let Some(bucket) = self.sorted_set.get_mut(bucket) {
-            let rank_items = bucket.get_by_rank_range(start, end, remove);
-            for node in &rank_items {
-                if remove {
-                    self.wal.write(node.borrow().value.as_ref())?;
-                }
-            }
-            return Ok(rank_items);
+        let Some(sorted_set) = self.zset_mut(bucket, key) else {
+            return Ok(vec![]);
+        };
+        let rank_items = sorted_set.get_by_rank_range(start, end, false);
+        match remove {
+            true => self.zrem_nodes(bucket, key, rank_items),
+            false => Ok(rank_items),
         }
-        Ok(vec![])
     }
 
     pub fn get_by_rank(
         &mut self,
         bucket: &str,
+        key: &str,
         rank: usize,
         remove: bool,
     ) -> Result<Option<ArcNode>, DbError> {
-        if let Some(bucket) = self.sorted_set.get_mut(bucket) {
-            if let Some(node) = bucket.get_by_rank(rank, remove) {
-                if remove {
-                    self.wal.write(node.borrow().value.as_ref())?;
-                }
-                return Ok(Some(node));
-            }
+        let Some(node) = self
+            .zset_mut(bucket, key)
+            .and_then(|sorted_set| sorted_set.get_by_rank(rank, false))
+        else {
+            return Ok(None);
+        };
+        match remove {
+            true => Ok(self.zrem_nodes(bucket, key, vec![node])?.pop()),
+            false => Ok(Some(node)),
         }
-
-        Ok(None)
     }
 
-    pub fn get_by_key(&self, bucket: &str, key: &str) -> Result<Option<ArcNode>, DbError> {
-        if let Some(bucket) = self.sorted_set.get(bucket) {
-            return Ok(bucket.get_by_key(key));
+    pub fn get_by_key(
+        &self,
+        bucket: &str,
+        key: &str,
+        member: &[u8],
+    ) -> Result<Option<ArcNode>, DbError> {
+        if let Some(sorted_set) = self.zset(bucket, key) {
+            return Ok(sorted_set.get_by_key(member));
         }
         Ok(None)
     }
 
+    // get_by_score_range returns the members scored from start to end, each bound is a score
+    // and whether it is excluded.
     pub fn get_by_score_range(
         &self,
         bucket: &str,
-        start: f64,
-        end: f64,
+        key: &str,
+        (start, exclude_start): (f64, bool),
+        (end, exclude_end): (f64, bool),
         limit: usize,
-        exclude_start: bool,
-        exclude_end: bool,
     ) -> Result<Vec<ArcNode>, DbError> {
-        if let Some(bucket) = self.sorted_set.get(bucket) {
-            return Ok(bucket.get_by_score_range(start, end, limit, exclude_start, exclude_end));
+        if let Some(sorted_set) = self.zset(bucket, key) {
+            return Ok(sorted_set.get_by_score_range(
+                start,
+                end,
+                limit,
+                exclude_start,
+                exclude_end,
+            ));
         }
         Ok(vec![])
     }
+
+    // zrem_nodes logs the removal of nodes from the sorted set of key, then removes them. the
+    // set is left untouched if the wal write fails.
+    fn zrem_nodes(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        nodes: Vec<ArcNode>,
+    ) -> Result<Vec<ArcNode>, DbError> {
+        self.log_zrem(bucket, key, &nodes)?;
+        if let Some(sorted_set) = self.zset_mut(bucket, key) {
+            for node in &nodes {
+                let member = node.borrow().key.clone();
+                sorted_set.remove(&member);
+            }
+        }
+        self.touch(bucket, key, DataTypes::SortedSet);
+        Ok(nodes)
+    }
+
+    // log_zrem logs the removal of the members of nodes from the sorted set key, in a single
+    // wal record.
+    fn log_zrem(&mut self, bucket: &str, key: &str, nodes: &[ArcNode]) -> Result<(), DbError> {
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        let records = nodes
+            .iter()
+            .map(|node| zrem_entry(bucket, key, node.borrow().key.clone()).encode())
+            .collect();
+        self.log_all(records).map(|_| ())
+    }
+
+    // json_set sets the json text value of entry at path of the key of entry, only path and
+    // value are logged rather than the whole document.
+    pub fn json_set(&mut self, path: &str, entry: Entry) -> Result<bool, DbError> {
+        self.check_type(&entry, DataTypes::Json)?;
+        let segments = json_path(&entry, path)?;
+        let value = json_value(&entry, &entry.value)?;
+        self.write_wal_with_value(&entry, payload::encode_json(path, &entry.value))?;
+
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let bucket = self.json.entry(bucket_name.clone()).or_default();
+        let set = bucket.set(entry_key_name, &segments, value);
+        self.touch(&bucket_name, entry_key_name, DataTypes::Json);
+        Ok(set)
+    }
+
+    // json_get returns the json text of the value at path.
+    pub fn json_get(&self, bucket: &str, key: &str, path: &str) -> Result<Option<String>, DbError> {
+        let segments = json::parse_path(path).map_err(|msg| DbError::JsonInvalid {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            msg,
+        })?;
+        if let Some(bucket) = self.json.get(bucket) {
+            return Ok(bucket.get(key, &segments).map(|value| value.to_string()));
+        }
+        Ok(None)
+    }
+
+    pub fn json_del(&mut self, path: &str, entry: Entry) -> Result<usize, DbError> {
+        self.check_type(&entry, DataTypes::Json)?;
+        let segments = json_path(&entry, path)?;
+        self.write_wal_with_value(&entry, payload::encode_json(path, &[]))?;
+
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let deleted = match self.json.get_mut(bucket_name) {
+            Some(bucket) => bucket.del(entry_key_name, &segments),
+            None => 0,
+        };
+        self.touch(bucket_name, entry_key_name, DataTypes::Json);
+        Ok(deleted)
+    }
+
+    // json_arrappend appends the json text value of entry to the array at path, returns the
+    // new length of the array or none if there is no array at path.
+    pub fn json_arrappend(&mut self, path: &str, entry: Entry) -> Result<Option<usize>, DbError> {
+        self.check_type(&entry, DataTypes::Json)?;
+        let segments = json_path(&entry, path)?;
+        let value = json_value(&entry, &entry.value)?;
+        self.write_wal_with_value(&entry, payload::encode_json(path, &entry.value))?;
+
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        if let Some(bucket) = self.json.get_mut(bucket_name) {
+            return Ok(bucket.arr_append(entry_key_name, &segments, vec![value]));
+        }
+        Ok(None)
+    }
+
+    // json_numincrby adds the json number value of entry to the number at path, returns the
+    // new number or none if there is no number at path.
+    pub fn json_numincrby(&mut self, path: &str, entry: Entry) -> Result<Option<f64>, DbError> {
+        self.check_type(&entry, DataTypes::Json)?;
+        let segments = json_path(&entry, path)?;
+        let increment = match json_value(&entry, &entry.value)? {
+            JsonValue::Number(increment) => increment,
+            _ => return Err(json_error(&entry, "increment is not a number".to_owned())),
+        };
+        self.write_wal_with_value(&entry, payload::encode_json(path, &entry.value))?;
+
+        let bucket_name = std::str::from_utf8(entry.meta.bucket.as_ref()).unwrap_or("");
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        if let Some(bucket) = self.json.get_mut(bucket_name) {
+            return Ok(bucket.num_incr_by(entry_key_name, &segments, increment));
+        }
+        Ok(None)
+    }
+
+    // ts_create creates the time series key of entry, or updates its retention if it
+    // exists. samples older than the latest one by more than retention_ms are dropped by
+    // ts_retention, 0 keeps them all.
+    pub fn ts_create(&mut self, retention_ms: i64, entry: Entry) -> Result<(), DbError> {
+        self.check_type(&entry, DataTypes::TimeSeries)?;
+        self.write_wal_with_value(&entry, payload::encode_tscreate(retention_ms))?;
+
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let bucket = self.timeseries.entry(bucket_name.clone()).or_default();
+        bucket.create(entry_key_name, retention_ms);
+        self.touch(&bucket_name, entry_key_name, DataTypes::TimeSeries);
+        Ok(())
+    }
+
+    // ts_add adds a sample to the time series key of entry, creating it if needed. the
+    // buckets closed by the sample are written to the dest keys of the compaction rules.
+    pub fn ts_add(&mut self, timestamp: i64, value: f64, entry: Entry) -> Result<i64, DbError> {
+        self.ts_add_sample(timestamp, value, entry)?;
+        Ok(timestamp)
+    }
+
+    // ts_add_sample adds a sample like ts_add and returns what it changed in each series.
+    fn ts_add_sample(
+        &mut self,
+        timestamp: i64,
+        value: f64,
+        entry: Entry,
+    ) -> Result<Vec<Added>, DbError> {
+        self.check_type(&entry, DataTypes::TimeSeries)?;
+        self.write_wal_with_value(&entry, payload::encode_tsadd(timestamp, value))?;
+
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        let mut added = vec![];
+        self.timeseries
+            .entry(bucket_name.clone())
+            .or_default()
+            .add_sample(entry_key_name, timestamp, value, &mut added);
+        // the samples compacted by the rules of the key may recreate their dest keys
+        for sample in added.iter() {
+            self.touch(&bucket_name, sample.key(), DataTypes::TimeSeries);
+        }
+        Ok(added)
+    }
+
+    // ts_range returns the samples of key between from and to (both inclusive), aggregated
+    // per bucket of the given ms when aggregation is given.
+    pub fn ts_range(
+        &self,
+        bucket: &str,
+        key: &str,
+        from: i64,
+        to: i64,
+        aggregation: Option<(TsAggregation, i64)>,
+    ) -> Result<Vec<(i64, f64)>, DbError> {
+        if aggregation.is_some_and(|(_, bucket_ms)| bucket_ms <= 0) {
+            return Err(DbError::TimeSeriesInvalid {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+                msg: "bucket duration must be positive".to_owned(),
+            });
+        }
+        if let Some(bucket) = self.timeseries.get(bucket) {
+            return Ok(bucket.range(key, from, to, aggregation).unwrap_or_default());
+        }
+        Ok(vec![])
+    }
+
+    // ts_createrule downsamples the time series key of entry into dest, one sample per
+    // bucket of bucket_ms. returns false if the key of entry does not exist.
+    pub fn ts_createrule(
+        &mut self,
+        dest: &str,
+        aggregation: TsAggregation,
+        bucket_ms: i64,
+        entry: Entry,
+    ) -> Result<bool, DbError> {
+        self.check_type(&entry, DataTypes::TimeSeries)?;
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        self.check_key_type(&bucket_name, dest, DataTypes::TimeSeries)?;
+        if bucket_ms <= 0 || dest == entry_key_name {
+            return Err(DbError::TimeSeriesInvalid {
+                bucket: bucket_name,
+                key: entry_key_name.to_owned(),
+                msg: "a rule needs a positive bucket duration and another dest key".to_owned(),
+            });
+        }
+        self.write_wal_with_value(&entry, payload::encode_tsrule(dest, aggregation, bucket_ms))?;
+
+        let bucket = self.timeseries.entry(bucket_name.clone()).or_default();
+        let created = bucket
+            .create_rule(entry_key_name, dest, aggregation, bucket_ms)
+            .is_some();
+        self.touch(&bucket_name, dest, DataTypes::TimeSeries);
+        Ok(created)
+    }
+
+    // ts_retention drops the samples out of the retention of every time series, it is run
+    // by the retention worker. each trim is logged so that a replay drops the same samples,
+    // returns the number of dropped samples.
+    pub fn ts_retention(&mut self) -> Result<usize, DbError> {
+        let mut expired = vec![];
+        for (bucket_name, bucket) in self.timeseries.iter() {
+            for (key, before) in bucket.expired() {
+                expired.push((bucket_name.clone(), key, before));
+            }
+        }
+        let mut dropped = 0;
+        for (bucket_name, key, before) in expired {
+            let entry = Entry::new(
+                Bytes::from(bucket_name),
+                Bytes::from(key),
+                Bytes::new(),
+                EntryOperate::TsTrim,
+                DataTypes::TimeSeries,
+            );
+            dropped += self.ts_trim(before, entry)?;
+        }
+        Ok(dropped)
+    }
+
+    // ts_trim drops the samples of key before the given timestamp.
+    fn ts_trim(&mut self, before: i64, entry: Entry) -> Result<usize, DbError> {
+        self.write_wal_with_value(&entry, payload::encode_tstrim(before))?;
+
+        let bucket_name = String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned());
+        let entry_key_name = std::str::from_utf8(entry.key.as_ref()).unwrap_or("");
+        Ok(self
+            .timeseries
+            .get_mut(&bucket_name)
+            .map_or(0, |bucket| bucket.trim(entry_key_name, before)))
+    }
+
+    // log writes b to the wal, or gathers it in the write batch being applied.
+    fn log(&mut self, b: &[u8]) -> Result<usize, DbError> {
+        if self.replaying {
+            return Ok(b.len());
+        }
+        match self.batch.as_mut() {
+            Some(batch) => {
+                batch.records.push(b.to_vec());
+                Ok(b.len())
+            }
+            None => self.wal.write(b),
+        }
+    }
+
+    // log_all logs records as a single wal record, an operate writing several of them is
+    // then replayed all together or not at all.
+    fn log_all(&mut self, records: Vec<Vec<u8>>) -> Result<usize, DbError> {
+        let size = records.iter().map(|record| record.len()).sum();
+        if self.replaying {
+            return Ok(size);
+        }
+        if let Some(batch) = self.batch.as_mut() {
+            batch.records.extend(records);
+            return Ok(size);
+        }
+        self.wal.write(batch_entry(&records).encode().as_ref())
+    }
+
+    // write_wal_with_value logs entry with its value replaced, it is used by the operates
+    // whose arguments are encoded as the value.
+    fn write_wal_with_value(&mut self, entry: &Entry, value: Bytes) -> Result<usize, DbError> {
+        let mut wal_entry = entry.clone();
+        wal_entry.meta.value_size = value.len() as u32;
+        wal_entry.value = value;
+        self.log(wal_entry.encode().as_ref())
+    }
+}
+
+// with_active runs f on the active memtable of mem_tables under its lock.
+pub fn with_active<T>(mem_tables: &MemTables, f: impl FnOnce(&mut Memtable) -> T) -> T {
+    loop {
+        let active = mem_tables.read().last().cloned();
+        let active = active.expect("a db has an active memtable");
+        let mut memtable = active.write();
+        // rotated out since it was got
+        if memtable.active() {
+            return f(&mut memtable);
+        }
+    }
+}
+
+// batch_entry frames records as the value of a single wal entry, see payload::encode_batch.
+fn batch_entry(records: &[Vec<u8>]) -> Entry {
+    Entry::new(
+        Bytes::new(),
+        Bytes::new(),
+        payload::encode_batch(records),
+        EntryOperate::Batch,
+        DataTypes::String,
+    )
+}
+
+// zrem_entry is the entry logged for a member removed from the sorted set key.
+fn zrem_entry(bucket: &str, key: &str, member: Bytes) -> Entry {
+    Entry::new(
+        Bytes::from(bucket.to_owned()),
+        Bytes::from(key.to_owned()),
+        member,
+        EntryOperate::ZRem,
+        DataTypes::SortedSet,
+    )
+}
+
+// zscore_payload decodes the score and the member carried in the value of a sorted set
+// entry.
+fn zscore_payload(entry: &Entry) -> Result<(f64, &[u8]), DbError> {
+    payload::decode_zscore(entry.value.as_ref()).ok_or_else(|| DbError::EntryDecodeError {
+        bucket: String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned()),
+        key: String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned()),
+        msg: "invalid sorted set score".to_owned(),
+    })
+}
+
+// check_value_size fails if a string value would grow to size, past data::MAX_VALUE_SIZE.
+fn check_value_size(entry: &Entry, size: usize) -> Result<(), DbError> {
+    if size <= MAX_VALUE_SIZE {
+        return Ok(());
+    }
+    Err(DbError::ValueTooLarge {
+        bucket: String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned()),
+        key: String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned()),
+        size,
+    })
+}
+
+fn json_error(entry: &Entry, msg: String) -> DbError {
+    DbError::JsonInvalid {
+        bucket: String::from_utf8(entry.meta.bucket.to_vec()).unwrap_or("".to_owned()),
+        key: String::from_utf8(entry.key.to_vec()).unwrap_or("".to_owned()),
+        msg,
+    }
+}
+
+fn json_path(entry: &Entry, path: &str) -> Result<Vec<PathSegment>, DbError> {
+    json::parse_path(path).map_err(|msg| json_error(entry, msg))
+}
+
+fn json_value(entry: &Entry, b: &[u8]) -> Result<JsonValue, DbError> {
+    JsonValue::parse(b).map_err(|msg| json_error(entry, msg))
 }
 
 impl Iterator for Memtable {
@@ -406,3 +1877,179 @@ impl Iterator for Memtable {
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileio::FDManager;
+
+    fn memtable(name: &str) -> Memtable {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        Memtable::new(
+            1,
+            path.to_str().unwrap(),
+            1,
+            enums::RWMode::MMap,
+            FDManager::new(4),
+        )
+        .unwrap()
+    }
+
+    // fail_wal unmaps the wal of memtable, its writes then fail.
+    fn fail_wal(memtable: &Memtable) {
+        memtable.wal.file_io.write().release();
+    }
+
+    fn entry(key: &str, value: Bytes, operate: EntryOperate, data_type: DataTypes) -> Entry {
+        Entry::new(
+            Bytes::from("bucket"),
+            Bytes::from(key.to_owned()),
+            value,
+            operate,
+            data_type,
+        )
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut memtable = memtable("arrowdb_memtable_wrong_type.wal");
+        memtable
+            .put(entry(
+                "s",
+                Bytes::from("v"),
+                EntryOperate::Put,
+                DataTypes::String,
+            ))
+            .unwrap();
+        let logged = memtable.logged();
+        let wrong_type = |res: Result<(), DbError>| matches!(res, Err(DbError::WrongType { .. }));
+        let list = |key, operate| entry(key, Bytes::from("v"), operate, DataTypes::List);
+
+        assert!(wrong_type(
+            memtable.lpop(list("s", EntryOperate::LLpop)).map(|_| ())
+        ));
+        assert!(wrong_type(
+            memtable.rpop(list("s", EntryOperate::LRpop)).map(|_| ())
+        ));
+        assert!(wrong_type(
+            memtable.lrem(0, list("s", EntryOperate::LRem)).map(|_| ())
+        ));
+        assert!(wrong_type(
+            memtable
+                .ltrim(0, 1, list("s", EntryOperate::LTrim))
+                .map(|_| ())
+        ));
+        let member = entry("s", Bytes::from("v"), EntryOperate::SRem, DataTypes::Set);
+        assert!(wrong_type(memtable.srem(member).map(|_| ())));
+        let member = entry(
+            "s",
+            Bytes::from("v"),
+            EntryOperate::ZRem,
+            DataTypes::SortedSet,
+        );
+        assert!(wrong_type(memtable.zrem(member).map(|_| ())));
+        assert_eq!(memtable.logged(), logged);
+        assert_eq!(memtable.key_type("bucket", "s"), Some(DataTypes::String));
+
+        // linsert and lset record the list they write in the keyspace
+        memtable.rpush(list("l", EntryOperate::LRpush)).unwrap();
+        let inserted = list("l", EntryOperate::LInsert);
+        assert_eq!(
+            memtable.linsert(true, Bytes::from("v"), inserted).unwrap(),
+            Some(2)
+        );
+        memtable.lset(0, list("l", EntryOperate::LSet)).unwrap();
+        assert_eq!(memtable.key_type("bucket", "l"), Some(DataTypes::List));
+    }
+
+    #[test]
+    fn test_zpop_logged_first() {
+        let mut memtable = memtable("arrowdb_zpop_logged_first.wal");
+        for (score, member) in [(1.0, "a"), (2.0, "b"), (3.0, "c")] {
+            let value = payload::encode_zscore(score, member.as_bytes());
+            memtable
+                .zadd(entry("z", value, EntryOperate::ZPut, DataTypes::SortedSet))
+                .unwrap();
+        }
+        let popped = memtable.zpopmax("bucket", "z", 2).unwrap();
+        let popped: Vec<_> = popped
+            .iter()
+            .map(|node| node.borrow().key.clone())
+            .collect();
+        assert_eq!(popped, ["c", "b"]);
+        assert_eq!(memtable.zcard("bucket", "z"), 1);
+
+        // the members are removed only once their removal is logged
+        fail_wal(&memtable);
+        assert!(memtable.zpopmin("bucket", "z", 1).is_err());
+        assert!(memtable
+            .get_by_rank_range("bucket", "z", 1, 1, true)
+            .is_err());
+        assert!(memtable.get_by_rank("bucket", "z", 1, true).is_err());
+        assert_eq!(memtable.zcard("bucket", "z"), 1);
+        assert_eq!(memtable.key_type("bucket", "z"), Some(DataTypes::SortedSet));
+    }
+
+    #[test]
+    fn test_spop_logged_first() {
+        let mut memtable = memtable("arrowdb_spop_logged_first.wal");
+        for member in ["a", "b", "c"] {
+            memtable
+                .sadd(entry(
+                    "s",
+                    Bytes::from(member),
+                    EntryOperate::SAdd,
+                    DataTypes::Set,
+                ))
+                .unwrap();
+        }
+        let logged = memtable.logged();
+        let popped = memtable
+            .spop(
+                2,
+                entry("s", Bytes::new(), EntryOperate::SPop, DataTypes::Set),
+            )
+            .unwrap();
+        assert_eq!(popped.len(), 2);
+        assert_eq!(memtable.scard("bucket", "s").unwrap(), 1);
+        // both members are in a single record
+        assert_eq!(
+            memtable.logged() - logged,
+            batch_entry(
+                &popped
+                    .iter()
+                    .map(|member| member.encode())
+                    .collect::<Vec<_>>()
+            )
+            .encode()
+            .len() as u64
+        );
+
+        fail_wal(&memtable);
+        let spop = entry("s", Bytes::new(), EntryOperate::SPop, DataTypes::Set);
+        assert!(memtable.spop(1, spop).is_err());
+        assert_eq!(memtable.scard("bucket", "s").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_zremrangebylex_logged_first() {
+        let mut memtable = memtable("arrowdb_zremrangebylex_logged_first.wal");
+        for member in ["a", "b", "c"] {
+            let value = payload::encode_zscore(0.0, member.as_bytes());
+            memtable
+                .zadd(entry("z", value, EntryOperate::ZPut, DataTypes::SortedSet))
+                .unwrap();
+        }
+        let removed = memtable
+            .zremrangebylex("bucket", "z", Bound::Included(b"a"), Bound::Excluded(b"c"))
+            .unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(memtable.zcard("bucket", "z"), 1);
+
+        fail_wal(&memtable);
+        let removed = memtable.zremrangebylex("bucket", "z", Bound::Unbounded, Bound::Unbounded);
+        assert!(removed.is_err());
+        assert_eq!(memtable.zcard("bucket", "z"), 1);
+    }
+}
@@ -0,0 +1,280 @@
+use bytes::Bytes;
+use num_enum::TryFromPrimitive;
+
+use super::Memtable;
+use crate::{
+    data::{entry::Entry, payload},
+    enums::{DataTypes, EntryOperate},
+    errors::DbError,
+};
+
+impl Memtable {
+    // recover rebuilds the memtable from its wal, the entries are applied again in the order
+    // they were logged, without logging them. returns the number of entries replayed.
+    pub fn recover(&mut self) -> Result<usize, DbError> {
+        let entries = self.wal.recover()?;
+        let replayed = entries.len();
+        self.replaying = true;
+        let res = entries.into_iter().try_for_each(|entry| self.replay(entry));
+        self.replaying = false;
+        res.map(|_| replayed)
+    }
+
+    // replay applies an entry logged by one of the write operates, the operates logged with
+    // a payload get their arguments back from it, see payload.
+    fn replay(&mut self, entry: Entry) -> Result<(), DbError> {
+        let Ok(operate) = EntryOperate::try_from_primitive(entry.meta.operate as usize) else {
+            return Err(replay_error(&entry));
+        };
+        let bucket = String::from_utf8_lossy(&entry.meta.bucket).into_owned();
+        let key = String::from_utf8_lossy(&entry.key).into_owned();
+        match operate {
+            EntryOperate::Put => self.put(entry).map(|_| ()),
+            EntryOperate::Del if entry.meta.data_type == DataTypes::String as u16 => {
+                self.put(entry).map(|_| ())
+            }
+            EntryOperate::Del => self.del(&bucket, &[&key]).map(|_| ()),
+            EntryOperate::Append => self.append(entry).map(|_| ()),
+            EntryOperate::SetRange => {
+                let (offset, value) =
+                    payload::decode_setrange(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let value = with_value(&entry, value);
+                self.setrange(offset, value).map(|_| ())
+            }
+            EntryOperate::LLpush => self.lpush(entry).map(|_| ()),
+            EntryOperate::LRpush => self.rpush(entry).map(|_| ()),
+            EntryOperate::LLpushx => self.lpushx(entry).map(|_| ()),
+            EntryOperate::LRpushx => self.rpushx(entry).map(|_| ()),
+            EntryOperate::LLpop => self.lpop(entry).map(|_| ()),
+            EntryOperate::LRpop => self.rpop(entry).map(|_| ()),
+            EntryOperate::LRem => {
+                let (count, element) =
+                    payload::decode_lrem(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let element = with_value(&entry, element);
+                self.lrem(count, element).map(|_| ())
+            }
+            EntryOperate::LInsert => {
+                let (before, pivot, element) =
+                    payload::decode_linsert(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let (pivot, element) = (Bytes::copy_from_slice(pivot), with_value(&entry, element));
+                self.linsert(before, pivot, element).map(|_| ())
+            }
+            EntryOperate::LTrim => {
+                let (start, end) =
+                    payload::decode_ltrim(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                self.ltrim(start, end, entry).map(|_| ())
+            }
+            EntryOperate::LMove => {
+                let (destination, wherefrom, whereto) =
+                    payload::decode_lmove(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let destination = destination.to_owned();
+                self.lmove(&destination, wherefrom, whereto, entry)
+                    .map(|_| ())
+            }
+            EntryOperate::LSet => {
+                let (index, element) =
+                    payload::decode_lset(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let element = with_value(&entry, element);
+                self.lset(index, element).map(|_| ())
+            }
+            EntryOperate::SAdd => self.sadd(entry).map(|_| ()),
+            EntryOperate::SRem => self.srem(entry).map(|_| ()),
+            EntryOperate::SMove => {
+                let (destination, member) =
+                    payload::decode_smove(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let (destination, member) = (destination.to_owned(), with_value(&entry, member));
+                self.smove(&destination, member).map(|_| ())
+            }
+            EntryOperate::SDiffStore | EntryOperate::SInterStore | EntryOperate::SUnionStore => {
+                let source_keys =
+                    payload::decode_sstore(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let (source, keys) = source_keys
+                    .split_first()
+                    .ok_or_else(|| replay_error(&entry))?;
+                match operate {
+                    EntryOperate::SDiffStore => {
+                        self.sdiffstore(&bucket, &key, source, keys.to_vec())
+                    }
+                    EntryOperate::SInterStore => {
+                        self.sinterstore(&bucket, &key, source, keys.to_vec())
+                    }
+                    _ => self.suionstore(&bucket, &key, source, keys.to_vec()),
+                }
+                .map(|_| ())
+            }
+            EntryOperate::ZPut => self.zadd(entry).map(|_| ()),
+            EntryOperate::ZRem => self.zrem(entry).map(|_| ()),
+            EntryOperate::ZIncrBy => self.zincrby(entry).map(|_| ()),
+            EntryOperate::Rename => {
+                let newkey = String::from_utf8_lossy(&entry.value).into_owned();
+                self.rename(&bucket, &key, &newkey)
+            }
+            EntryOperate::JsonSet
+            | EntryOperate::JsonDel
+            | EntryOperate::JsonArrAppend
+            | EntryOperate::JsonNumIncrBy => {
+                let (path, value) =
+                    payload::decode_json(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let (path, value) = (path.to_owned(), with_value(&entry, value));
+                match operate {
+                    EntryOperate::JsonSet => self.json_set(&path, value).map(|_| ()),
+                    EntryOperate::JsonDel => self.json_del(&path, value).map(|_| ()),
+                    EntryOperate::JsonArrAppend => self.json_arrappend(&path, value).map(|_| ()),
+                    _ => self.json_numincrby(&path, value).map(|_| ()),
+                }
+            }
+            EntryOperate::TsCreate => {
+                let retention_ms =
+                    payload::decode_tscreate(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                self.ts_create(retention_ms, entry)
+            }
+            EntryOperate::TsAdd => {
+                let (timestamp, value) =
+                    payload::decode_tsadd(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                self.ts_add(timestamp, value, entry).map(|_| ())
+            }
+            EntryOperate::TsCreateRule => {
+                let (dest, aggregation, bucket_ms) =
+                    payload::decode_tsrule(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                let dest = dest.to_owned();
+                self.ts_createrule(&dest, aggregation, bucket_ms, entry)
+                    .map(|_| ())
+            }
+            EntryOperate::TsTrim => {
+                let before =
+                    payload::decode_tstrim(&entry.value).ok_or_else(|| replay_error(&entry))?;
+                self.ts_trim(before, entry).map(|_| ())
+            }
+            // the read operates are not logged
+            _ => Ok(()),
+        }
+    }
+}
+
+// with_value returns entry with value in place of its payload.
+fn with_value(entry: &Entry, value: &[u8]) -> Entry {
+    let mut entry = entry.clone();
+    entry.value = Bytes::copy_from_slice(value);
+    entry.meta.value_size = entry.value.len() as u32;
+    entry
+}
+
+fn replay_error(entry: &Entry) -> DbError {
+    DbError::EntryDecodeError {
+        bucket: String::from_utf8_lossy(&entry.meta.bucket).into_owned(),
+        key: String::from_utf8_lossy(&entry.key).into_owned(),
+        msg: "invalid wal entry".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        enums,
+        fileio::FDManager,
+        memtable::batch::{BatchOp, WriteBatch},
+    };
+
+    fn open(path: &std::path::Path) -> Memtable {
+        Memtable::new(
+            1,
+            path.to_str().unwrap(),
+            1,
+            enums::RWMode::StdIO,
+            FDManager::new(4),
+        )
+        .unwrap()
+    }
+
+    fn entry(key: &str, value: &str, operate: EntryOperate, data_type: DataTypes) -> Entry {
+        Entry::new(
+            Bytes::from("bucket"),
+            Bytes::from(key.to_owned()),
+            Bytes::from(value.to_owned()),
+            operate,
+            data_type,
+        )
+    }
+
+    #[test]
+    fn test_recover() {
+        let path = std::env::temp_dir().join("arrowdb_recover.wal");
+        let _ = std::fs::remove_file(&path);
+        let mut memtable = open(&path);
+        memtable
+            .put(entry("a", "1", EntryOperate::Put, DataTypes::String))
+            .unwrap();
+        for value in ["x", "y"] {
+            memtable
+                .rpush(entry("l", value, EntryOperate::LRpush, DataTypes::List))
+                .unwrap();
+        }
+        memtable
+            .lset(0, entry("l", "z", EntryOperate::LSet, DataTypes::List))
+            .unwrap();
+        memtable
+            .sadd(entry("s", "m", EntryOperate::SAdd, DataTypes::Set))
+            .unwrap();
+        let mut batch = WriteBatch::new();
+        batch
+            .put(entry("b", "2", EntryOperate::Put, DataTypes::String))
+            .push(BatchOp::RPush(entry(
+                "l",
+                "w",
+                EntryOperate::LRpush,
+                DataTypes::List,
+            )));
+        memtable.write_batch(batch).unwrap();
+        let logged = memtable.logged();
+        drop(memtable);
+
+        let mut memtable = open(&path);
+        assert_eq!(memtable.recover().unwrap(), 7);
+        assert_eq!(memtable.logged(), logged);
+        let b = memtable.get("bucket", "b").unwrap().unwrap();
+        assert_eq!(b.value, Bytes::from("2"));
+        let list: Vec<Bytes> = memtable
+            .lrange("bucket", "l", 0, 2)
+            .unwrap()
+            .iter()
+            .map(|b| Entry::decode_unchecked(b).value)
+            .collect();
+        assert_eq!(list, vec!["z", "y", "w"]);
+        assert!(memtable
+            .sismember(entry("s", "m", EntryOperate::SAdd, DataTypes::Set))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_recover_torn_batch() {
+        let path = std::env::temp_dir().join("arrowdb_recover_torn_batch.wal");
+        let _ = std::fs::remove_file(&path);
+        let mut memtable = open(&path);
+        memtable
+            .put(entry("a", "1", EntryOperate::Put, DataTypes::String))
+            .unwrap();
+        let logged = memtable.logged();
+        let mut batch = WriteBatch::new();
+        batch
+            .put(entry("b", "2", EntryOperate::Put, DataTypes::String))
+            .put(entry("c", "3", EntryOperate::Put, DataTypes::String));
+        memtable.write_batch(batch).unwrap();
+        // a crash tears the tail of the batch record
+        let end = memtable.logged();
+        memtable
+            .wal
+            .file_io
+            .write()
+            .write(&[0u8; 4], end - 4)
+            .unwrap();
+        drop(memtable);
+
+        let mut memtable = open(&path);
+        assert_eq!(memtable.recover().unwrap(), 1);
+        assert_eq!(memtable.logged(), logged);
+        assert!(memtable.get("bucket", "a").unwrap().is_some());
+        assert!(memtable.get("bucket", "b").unwrap().is_none());
+        assert!(memtable.get("bucket", "c").unwrap().is_none());
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::{data::entry::Entry, datatypes::sortedset::ArcNode, enums::ListDirection};
+
+// Waiter is a client blocked on one or more keys, it is registered on every key it
+// watches and served at most once.
+pub trait Waiter {
+    fn served(&self) -> bool;
+}
+
+// ListWaiter is a client blocked on list keys.
+pub struct ListWaiter {
+    pub wherefrom: ListDirection,
+    // destination key and side for blmove, none for blpop/brpop
+    pub dest: Option<(String, ListDirection)>,
+    served: AtomicBool,
+    sender: Sender<(String, Entry)>,
+}
+
+impl ListWaiter {
+    pub fn new(
+        wherefrom: ListDirection,
+        dest: Option<(String, ListDirection)>,
+    ) -> (Arc<Self>, Receiver<(String, Entry)>) {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let waiter = ListWaiter {
+            wherefrom,
+            dest,
+            served: AtomicBool::new(false),
+            sender,
+        };
+        (Arc::new(waiter), receiver)
+    }
+
+    // serve hands the popped element to the blocked client.
+    pub fn serve(&self, key: String, entry: Entry) {
+        self.served.store(true, Ordering::Release);
+        let _ = self.sender.send((key, entry));
+    }
+}
+
+impl Waiter for ListWaiter {
+    fn served(&self) -> bool {
+        self.served.load(Ordering::Acquire)
+    }
+}
+
+// ZSetWaiter is a client blocked on the sorted set keys of a bucket.
+pub struct ZSetWaiter {
+    // pop the highest member rather than the lowest
+    pub max: bool,
+    served: AtomicBool,
+    sender: Sender<(String, ArcNode)>,
+}
+
+impl ZSetWaiter {
+    pub fn new(max: bool) -> (Arc<Self>, Receiver<(String, ArcNode)>) {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let waiter = ZSetWaiter {
+            max,
+            served: AtomicBool::new(false),
+            sender,
+        };
+        (Arc::new(waiter), receiver)
+    }
+
+    // serve hands the popped member and the key it was popped from to the blocked client.
+    pub fn serve(&self, key: String, node: ArcNode) {
+        self.served.store(true, Ordering::Release);
+        let _ = self.sender.send((key, node));
+    }
+}
+
+impl Waiter for ZSetWaiter {
+    fn served(&self) -> bool {
+        self.served.load(Ordering::Acquire)
+    }
+}
+
+pub type ListWaiters = Waiters<ListWaiter>;
+pub type ZSetWaiters = Waiters<ZSetWaiter>;
+
+pub struct Waiters<W> {
+    waiters: HashMap<String, HashMap<String, VecDeque<Arc<W>>>>,
+}
+
+impl<W> Default for Waiters<W> {
+    fn default() -> Self {
+        Waiters {
+            waiters: HashMap::new(),
+        }
+    }
+}
+
+impl<W: Waiter> Waiters<W> {
+    pub fn register(&mut self, bucket: &str, keys: &[&str], waiter: &Arc<W>) {
+        let bucket = self.waiters.entry(bucket.to_owned()).or_default();
+        for key in keys {
+            bucket
+                .entry((*key).to_owned())
+                .or_default()
+                .push_back(Arc::clone(waiter));
+        }
+    }
+
+    pub fn unregister(&mut self, bucket: &str, keys: &[&str], waiter: &Arc<W>) {
+        if let Some(bucket) = self.waiters.get_mut(bucket) {
+            for key in keys {
+                if let Some(queue) = bucket.get_mut(*key) {
+                    queue.retain(|w| !Arc::ptr_eq(w, waiter));
+                    if queue.is_empty() {
+                        bucket.remove(*key);
+                    }
+                }
+            }
+        }
+    }
+
+    // pop_front returns the longest waiting client on the key that has not been served yet.
+    pub fn pop_front(&mut self, bucket: &str, key: &str) -> Option<Arc<W>> {
+        let queue = self.waiters.get_mut(bucket)?.get_mut(key)?;
+        let mut res = None;
+        while let Some(waiter) = queue.pop_front() {
+            if !waiter.served() {
+                res = Some(waiter);
+                break;
+            }
+        }
+        if queue.is_empty() {
+            self.waiters.get_mut(bucket).unwrap().remove(key);
+        }
+        res
+    }
+
+    // requeue puts back in front the waiters popped from the key but not served, in the
+    // order they waited.
+    pub fn requeue(&mut self, bucket: &str, key: &str, waiters: Vec<Arc<W>>) {
+        if waiters.is_empty() {
+            return;
+        }
+        let queue = self
+            .waiters
+            .entry(bucket.to_owned())
+            .or_default()
+            .entry(key.to_owned())
+            .or_default();
+        for waiter in waiters.into_iter().rev() {
+            queue.push_front(waiter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pop_front() {
+        let mut waiters = ListWaiters::default();
+        let (waiter1, receiver1) = ListWaiter::new(ListDirection::Left, None);
+        let (waiter2, _) = ListWaiter::new(ListDirection::Right, None);
+        let (waiter3, _) = ListWaiter::new(ListDirection::Left, None);
+        waiters.register("bucket", &["key1", "key2"], &waiter1);
+        waiters.register("bucket", &["key1"], &waiter2);
+        waiters.register("bucket", &["key2"], &waiter3);
+
+        let waiter = waiters.pop_front("bucket", "key2").unwrap();
+        assert!(Arc::ptr_eq(&waiter, &waiter1));
+        waiter.serve("key2".to_owned(), Entry::default());
+        assert_eq!(receiver1.try_recv().unwrap().0, "key2");
+
+        // waiter1 has been served through key2, so key1 skips it
+        let waiter = waiters.pop_front("bucket", "key1").unwrap();
+        assert!(Arc::ptr_eq(&waiter, &waiter2));
+        assert!(waiters.pop_front("bucket", "key1").is_none());
+        assert!(waiters.pop_front("bucket", "key3").is_none());
+    }
+
+    #[test]
+    fn test_unregister() {
+        let mut waiters = ListWaiters::default();
+        let (waiter1, _) = ListWaiter::new(ListDirection::Left, None);
+        let (waiter2, _) = ListWaiter::new(ListDirection::Left, None);
+        waiters.register("bucket", &["key1", "key2"], &waiter1);
+        waiters.register("bucket", &["key1"], &waiter2);
+        waiters.unregister("bucket", &["key1", "key2"], &waiter1);
+
+        let waiter = waiters.pop_front("bucket", "key1").unwrap();
+        assert!(Arc::ptr_eq(&waiter, &waiter2));
+        assert!(waiters.pop_front("bucket", "key2").is_none());
+    }
+
+    #[test]
+    fn test_requeue() {
+        let mut waiters = ListWaiters::default();
+        let (waiter1, _) = ListWaiter::new(ListDirection::Left, None);
+        let (waiter2, _) = ListWaiter::new(ListDirection::Left, None);
+        let (waiter3, _) = ListWaiter::new(ListDirection::Left, None);
+        for waiter in [&waiter1, &waiter2, &waiter3] {
+            waiters.register("bucket", &["key"], waiter);
+        }
+        let popped = vec![
+            waiters.pop_front("bucket", "key").unwrap(),
+            waiters.pop_front("bucket", "key").unwrap(),
+        ];
+        waiters.requeue("bucket", "key", popped);
+
+        for waiter in [waiter1, waiter2, waiter3] {
+            let front = waiters.pop_front("bucket", "key").unwrap();
+            assert!(Arc::ptr_eq(&front, &waiter));
+        }
+        assert!(waiters.pop_front("bucket", "key").is_none());
+    }
+}
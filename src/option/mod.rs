@@ -1,7 +1,20 @@
-use crate::enums;
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    enums,
+    fileio::{
+        block_cache::{self, BlockCache},
+        rate_limiter::RateLimiter,
+        FDManager,
+    },
+    index::bloom,
+    wal::SyncPolicy,
+};
 use derivative::Derivative;
+use parking_lot::Mutex;
 
-#[derive(Debug, Clone, Default, Derivative)]
+#[derive(Debug, Clone, Derivative)]
+#[derivative(Default)]
 pub struct Option {
     file_option: FileOption,
     index_mode: enums::IndexMode,
@@ -10,6 +23,8 @@ pub struct Option {
     max_memtable_nums: usize,
     #[derivative(Default(value = "1024"))]
     memtable_size_mb: usize,
+    #[derivative(Default(value = "Duration::from_secs(1)"))]
+    retention_interval: Duration,
     compaction: CompactionOption,
 }
 
@@ -19,26 +34,122 @@ impl Option {
         self.to_owned()
     }
 
+    pub fn dir(&self) -> &str {
+        &self.file_option.dir
+    }
+
     pub fn with_dat_file_size(&mut self, size_mb: usize) -> Self {
         self.file_option.dat_file_size_mb = size_mb;
         self.to_owned()
     }
 
+    pub fn dat_file_size_mb(&self) -> u64 {
+        self.file_option.dat_file_size_mb as u64
+    }
+
     pub fn with_rw_mode(&mut self, rw_mode: enums::RWMode) -> Self {
         self.file_option.rw_mode = rw_mode;
         self.to_owned()
     }
 
+    pub fn rw_mode(&self) -> enums::RWMode {
+        self.file_option.rw_mode.clone()
+    }
+
+    // with_write_sync_immediately syncs the wal on every write if true, else leaves it to the
+    // os, see with_sync_policy.
     pub fn with_write_sync_immediately(&mut self, write_sync_immediately: bool) -> Self {
-        self.file_option.write_sync_immediately = write_sync_immediately;
+        self.file_option.sync_policy = match write_sync_immediately {
+            true => SyncPolicy::EveryWrite,
+            false => SyncPolicy::Os,
+        };
+        self.to_owned()
+    }
+
+    // with_sync_policy sets when the wal is synced, a write can override it.
+    pub fn with_sync_policy(&mut self, sync_policy: SyncPolicy) -> Self {
+        self.file_option.sync_policy = sync_policy;
         self.to_owned()
     }
 
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.file_option.sync_policy
+    }
+
     pub fn with_fd_cache_size(&mut self, fd_cache_size: usize) -> Self {
         self.file_option.fd_cache_size = fd_cache_size;
         self.to_owned()
     }
 
+    // with_fd_manager shares the fd cache of another db, fd_cache_size is then unused.
+    pub fn with_fd_manager(&mut self, fd_manager: Arc<Mutex<FDManager>>) -> Self {
+        self.file_option.fd_manager = Some(fd_manager);
+        self.to_owned()
+    }
+
+    // fd_manager returns the shared fd cache if any, or a new one of fd_cache_size.
+    pub fn fd_manager(&self) -> Arc<Mutex<FDManager>> {
+        match &self.file_option.fd_manager {
+            Some(fd_manager) => Arc::clone(fd_manager),
+            None => FDManager::new(self.file_option.fd_cache_size),
+        }
+    }
+
+    // with_block_cache_size sets the bytes of data file blocks cached for reads, 0 disables
+    // the cache.
+    pub fn with_block_cache_size(&mut self, block_cache_size: usize) -> Self {
+        self.file_option.block_cache_size = block_cache_size;
+        self.to_owned()
+    }
+
+    pub fn with_block_cache_shards(&mut self, block_cache_shards: usize) -> Self {
+        self.file_option.block_cache_shards = block_cache_shards;
+        self.to_owned()
+    }
+
+    // new_block_cache builds a block cache of the configured size, none if disabled. a db
+    // builds one when it is opened and shares it with its readers.
+    pub fn new_block_cache(&self) -> std::option::Option<Arc<BlockCache>> {
+        if self.file_option.block_cache_size == 0 {
+            return None;
+        }
+        let shards = match self.file_option.block_cache_shards {
+            0 => block_cache::DEFAULT_BLOCK_CACHE_SHARDS,
+            shards => shards,
+        };
+        Some(Arc::new(BlockCache::new(
+            self.file_option.block_cache_size,
+            shards,
+        )))
+    }
+
+    // with_rate_limit sets the bytes per second the flush and compaction workers write at
+    // most, 0 disables the limit. the wal is not limited.
+    pub fn with_rate_limit(&mut self, bytes_per_sec: u64) -> Self {
+        self.file_option.rate_limit = bytes_per_sec;
+        self.to_owned()
+    }
+
+    // new_rate_limiter builds a limiter at the configured rate, a db builds one when it is
+    // opened and shares it with its workers, see DB::set_rate_limit.
+    pub fn new_rate_limiter(&self) -> Arc<RateLimiter> {
+        Arc::new(RateLimiter::new(self.file_option.rate_limit))
+    }
+
+    // with_bloom_bits_per_key sets the size of the bloom filters of the sealed data files,
+    // about 1% of the lookups of a missing key read a file with 10 bits per key.
+    pub fn with_bloom_bits_per_key(&mut self, bloom_bits_per_key: usize) -> Self {
+        self.file_option.bloom_bits_per_key = bloom_bits_per_key;
+        self.to_owned()
+    }
+
+    pub fn bloom_bits_per_key(&self) -> usize {
+        match self.file_option.bloom_bits_per_key {
+            0 => bloom::DEFAULT_BITS_PER_KEY,
+            bits_per_key => bits_per_key,
+        }
+    }
+
     pub fn whth_index_mode(&mut self, index_mode: enums::IndexMode) -> Self {
         self.index_mode = index_mode;
         self.to_owned()
@@ -54,6 +165,23 @@ impl Option {
         self.to_owned()
     }
 
+    // memtable_size_mb is the size of the wal of a memtable, a new memtable replaces it once
+    // its wal is full.
+    pub fn memtable_size_mb(&self) -> u64 {
+        self.memtable_size_mb as u64
+    }
+
+    // with_retention_interval sets how often the time series samples out of their retention
+    // are dropped.
+    pub fn with_retention_interval(&mut self, interval: Duration) -> Self {
+        self.retention_interval = interval;
+        self.to_owned()
+    }
+
+    pub fn retention_interval(&self) -> Duration {
+        self.retention_interval
+    }
+
     pub fn with_candidate_live_key_ratio(&mut self, candidate_live_key_ratio: f32) -> Self {
         self.compaction.candidate_live_key_ratio = candidate_live_key_ratio;
         self.to_owned()
@@ -70,17 +198,28 @@ impl Option {
     }
 }
 
-#[derive(Debug, Clone, Default, Derivative)]
+#[derive(Debug, Clone, Derivative)]
+#[derivative(Default)]
 pub struct FileOption {
     dir: String,
     #[derivative(Default(value = "256"))]
     dat_file_size_mb: usize,
     rw_mode: enums::RWMode,
-    write_sync_immediately: bool,
+    sync_policy: SyncPolicy,
+    #[derivative(Default(value = "1024"))]
     fd_cache_size: usize,
+    fd_manager: std::option::Option<Arc<Mutex<FDManager>>>,
+    #[derivative(Default(value = "64 * 1024 * 1024"))]
+    block_cache_size: usize,
+    #[derivative(Default(value = "16"))]
+    block_cache_shards: usize,
+    #[derivative(Default(value = "10"))]
+    bloom_bits_per_key: usize,
+    rate_limit: u64,
 }
 
-#[derive(Debug, Clone, Default, Derivative)]
+#[derive(Debug, Clone, Derivative)]
+#[derivative(Default)]
 pub struct CompactionOption {
     #[derivative(Default(value = "0.1"))]
     candidate_live_key_ratio: f32,
@@ -89,3 +228,22 @@ pub struct CompactionOption {
     #[derivative(Default(value = "0.5"))]
     candidate_ratio_everytime: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let opt = Option::default();
+        assert_eq!(opt.max_memtable_nums, 5);
+        assert_eq!(opt.memtable_size_mb, 1024);
+        assert_eq!(opt.retention_interval, Duration::from_secs(1));
+        assert_eq!(opt.file_option.dat_file_size_mb, 256);
+        assert_eq!(opt.file_option.fd_cache_size, 1024);
+        assert_eq!(opt.file_option.block_cache_size, 64 * 1024 * 1024);
+        assert!(opt.new_block_cache().is_some());
+        assert_eq!(opt.bloom_bits_per_key(), 10);
+        assert_eq!(opt.compaction.candidate_ratio_everytime, 0.5);
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use crate::{errors::DbError, fileio::FileIOManagerObject};
+
+// SyncPolicy tells when the wal is synced to disk after a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    // every write waits for its bytes to be synced, concurrent writers share a sync
+    EveryWrite,
+    // a worker syncs the wal at this interval, a crash loses the writes of the last one
+    Periodic(Duration),
+    // the os writes the pages back when it wants to
+    #[default]
+    Os,
+}
+
+struct SyncState {
+    // wal offset up to which the bytes are synced
+    synced: u64,
+    // a writer is syncing for the others
+    syncing: bool,
+}
+
+// GroupCommit syncs a wal for the writers waiting on it. the first writer to wait becomes the
+// leader and syncs all the bytes written so far, the writers that come while it syncs wait
+// for it and sync again only if their bytes were written after it started.
+pub struct GroupCommit {
+    file_io: FileIOManagerObject,
+    // wal offset up to which the bytes are written
+    written: AtomicU64,
+    state: Mutex<SyncState>,
+    synced_cond: Condvar,
+}
+
+impl GroupCommit {
+    pub fn new(file_io: FileIOManagerObject, written: u64) -> Self {
+        GroupCommit {
+            file_io,
+            written: AtomicU64::new(written),
+            state: Mutex::new(SyncState {
+                synced: written,
+                syncing: false,
+            }),
+            synced_cond: Condvar::new(),
+        }
+    }
+
+    pub fn set_written(&self, written: u64) {
+        self.written.fetch_max(written, Ordering::AcqRel);
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Acquire)
+    }
+
+    pub fn synced(&self) -> u64 {
+        self.state.lock().synced
+    }
+
+    // commit makes a write ending at offset durable as policy requires, it is called once
+    // the memtable lock is released so that the writers can share a sync.
+    pub fn commit(&self, offset: u64, policy: SyncPolicy) -> Result<(), DbError> {
+        match policy {
+            SyncPolicy::EveryWrite => self.sync_to(offset),
+            SyncPolicy::Periodic(_) | SyncPolicy::Os => Ok(()),
+        }
+    }
+
+    // sync syncs all the bytes written, it is run by the sync worker.
+    pub fn sync(&self) -> Result<(), DbError> {
+        self.sync_to(self.written())
+    }
+
+    // sync_to returns once the bytes up to offset are synced. the leader takes the offset
+    // written so far, then syncs with neither the state nor the file locked.
+    pub fn sync_to(&self, offset: u64) -> Result<(), DbError> {
+        let mut state = self.state.lock();
+        while state.synced < offset {
+            if state.syncing {
+                self.synced_cond.wait(&mut state);
+                continue;
+            }
+            state.syncing = true;
+            let target = self.written();
+            let res = MutexGuard::unlocked(&mut state, || self.sync_file());
+            state.syncing = false;
+            self.synced_cond.notify_all();
+            res?;
+            state.synced = state.synced.max(target);
+        }
+        Ok(())
+    }
+
+    // sync_file syncs the bytes written to the wal, the file is only locked to get a handle
+    // of it so that the writers go on while it syncs.
+    fn sync_file(&self) -> Result<(), DbError> {
+        let handle = self.file_io.write().sync_handle()?;
+        match handle {
+            Some(file) => file.sync_all()?,
+            None => {
+                self.file_io.write().sync()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use parking_lot::RwLock;
+
+    use super::*;
+    use crate::fileio::FileIOManager;
+
+    struct SlowSync {
+        syncs: Arc<AtomicU64>,
+    }
+
+    impl FileIOManager for SlowSync {
+        fn write(&mut self, b: &[u8], _offset: u64) -> Result<usize, DbError> {
+            Ok(b.len())
+        }
+
+        fn read(&self, _b: &mut [u8], _offset: u64) -> Result<usize, DbError> {
+            Ok(0)
+        }
+
+        fn sync(&mut self) -> Result<bool, DbError> {
+            thread::sleep(Duration::from_millis(20));
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+            Ok(true)
+        }
+
+        fn release(&mut self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_group_commit() {
+        let syncs = Arc::new(AtomicU64::new(0));
+        let file_io: FileIOManagerObject = Arc::new(RwLock::new(Box::new(SlowSync {
+            syncs: Arc::clone(&syncs),
+        })));
+        let group = Arc::new(GroupCommit::new(file_io, 0));
+
+        let writers: Vec<_> = (1..=16)
+            .map(|i| {
+                let group = Arc::clone(&group);
+                thread::spawn(move || {
+                    group.set_written(i * 10);
+                    group.commit(i * 10, SyncPolicy::EveryWrite).unwrap();
+                    assert!(group.synced() >= i * 10);
+                })
+            })
+            .collect();
+        writers
+            .into_iter()
+            .for_each(|writer| writer.join().unwrap());
+        // the writers waiting during a sync share the next one
+        assert!(syncs.load(Ordering::Relaxed) < 16);
+        assert_eq!(group.synced(), 160);
+
+        let before = syncs.load(Ordering::Relaxed);
+        group.set_written(170);
+        group.commit(170, SyncPolicy::Os).unwrap();
+        group.sync_to(160).unwrap();
+        assert_eq!(syncs.load(Ordering::Relaxed), before);
+        group.sync().unwrap();
+        assert_eq!(group.synced(), 170);
+    }
+
+    // HandleSync is only synced through its handle.
+    struct HandleSync {
+        file: std::fs::File,
+    }
+
+    impl FileIOManager for HandleSync {
+        fn write(&mut self, b: &[u8], _offset: u64) -> Result<usize, DbError> {
+            Ok(b.len())
+        }
+
+        fn read(&self, _b: &mut [u8], _offset: u64) -> Result<usize, DbError> {
+            Ok(0)
+        }
+
+        fn sync(&mut self) -> Result<bool, DbError> {
+            panic!("synced with the file locked");
+        }
+
+        fn sync_handle(&mut self) -> Result<Option<std::fs::File>, DbError> {
+            Ok(Some(self.file.try_clone()?))
+        }
+
+        fn release(&mut self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_sync_unlocked() {
+        let path = std::env::temp_dir().join("arrowdb_sync_unlocked");
+        let file = std::fs::File::create(&path).unwrap();
+        let file_io: FileIOManagerObject = Arc::new(RwLock::new(Box::new(HandleSync { file })));
+        let group = GroupCommit::new(file_io, 0);
+        group.set_written(10);
+        group.commit(10, SyncPolicy::EveryWrite).unwrap();
+        assert_eq!(group.synced(), 10);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,16 +1,24 @@
 use std::sync::Arc;
 
+use parking_lot::Mutex;
+
 use crate::{
-    enums,
+    data::{entry::Entry, payload, ENTRYHEADERSIZE},
+    enums::{self, EntryOperate},
     errors::DbError,
-    fileio::{self, FileIOManagerObject},
+    fileio::{self, FDManager, FileIOManagerObject},
 };
 
+pub use self::group_commit::{GroupCommit, SyncPolicy};
+
+mod group_commit;
+
 #[derive(Clone)]
 pub struct Wal {
     pub file_id: u64,
     pub write_at: u64,
     pub file_io: FileIOManagerObject,
+    pub group_commit: Arc<GroupCommit>,
 }
 
 impl Wal {
@@ -19,12 +27,15 @@ impl Wal {
         path: &str,
         file_size_mb: u64,
         rw_mode: enums::RWMode,
+        fd_manager: Arc<Mutex<FDManager>>,
     ) -> Result<Wal, DbError> {
-        match fileio::FileManager::new(rw_mode).get_fileio_manager(path, file_size_mb) {
+        let mut file_manager = fileio::FileManager::new(rw_mode, fd_manager);
+        match file_manager.get_fileio_manager(path, file_size_mb) {
             Ok(file_manager) => Ok(Wal {
                 file_id,
                 write_at: 0,
-                file_io: Arc::clone(&file_manager),
+                group_commit: Arc::new(GroupCommit::new(Arc::clone(&file_manager), 0)),
+                file_io: file_manager,
             }),
             Err(err) => Err(err),
         }
@@ -33,6 +44,156 @@ impl Wal {
     pub fn write(&mut self, b: &[u8]) -> Result<usize, DbError> {
         let mut wal = self.file_io.write();
         let len = wal.write(b, self.write_at)?;
+        self.write_at += len as u64;
+        self.group_commit.set_written(self.write_at);
         Ok(len)
     }
+
+    // recover reads the entries logged in the wal and moves write_at after them. the log ends
+    // at a zeroed header or at an entry failing its crc, torn by a crash. a batch is a single
+    // entry so its entries are recovered all together or not at all.
+    pub fn recover(&mut self) -> Result<Vec<Entry>, DbError> {
+        let wal = self.file_io.read();
+        let mut entries = vec![];
+        let mut offset = 0;
+        loop {
+            let mut header = vec![0u8; ENTRYHEADERSIZE];
+            match wal.read(&mut header, offset) {
+                Ok(n) if n == header.len() => {}
+                Ok(_) | Err(DbError::OffsetOutOfRange { .. }) => break,
+                Err(err) => return Err(err),
+            }
+            if header.iter().all(|b| *b == 0) {
+                break;
+            }
+            let size_at =
+                |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap()) as usize;
+            // key, value and bucket sizes, a torn header may claim more than the file holds so
+            // its last byte is read before the entry is
+            let size = ENTRYHEADERSIZE + size_at(12) + size_at(16) + size_at(26);
+            match wal.read(&mut [0u8; 1], offset + size as u64 - 1) {
+                Ok(1) => {}
+                Ok(_) | Err(DbError::OffsetOutOfRange { .. }) => break,
+                Err(err) => return Err(err),
+            }
+            let mut buf = vec![0u8; size];
+            match wal.read(&mut buf, offset) {
+                Ok(n) if n == size => {}
+                Ok(_) | Err(DbError::OffsetOutOfRange { .. }) => break,
+                Err(err) => return Err(err),
+            }
+            let Ok(entry) = Entry::decode(&buf) else {
+                break;
+            };
+            offset += size as u64;
+
+            if entry.meta.operate != EntryOperate::Batch as u16 {
+                entries.push(entry);
+                continue;
+            }
+            let Some(batch) = payload::decode_batch(&entry.value) else {
+                break;
+            };
+            for b in batch {
+                entries.push(Entry::decode(b)?);
+            }
+        }
+        drop(wal);
+        self.file_io.write().set_data_len(offset);
+        self.write_at = offset;
+        self.group_commit.set_written(offset);
+        Ok(entries)
+    }
+
+    // seal syncs the wal once it is rotated out, a growing file is truncated to the entries
+    // it holds.
+    pub fn seal(&self) -> Result<(), DbError> {
+        self.group_commit.sync()?;
+        self.file_io.write().seal()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::enums::DataTypes;
+
+    fn open(path: &std::path::Path, rw_mode: enums::RWMode) -> Wal {
+        Wal::new(1, path.to_str().unwrap(), 1, rw_mode, FDManager::new(4)).unwrap()
+    }
+
+    fn entry(key: &str, value: Vec<u8>) -> Entry {
+        Entry::new(
+            Bytes::from("bucket"),
+            Bytes::from(key.to_owned()),
+            Bytes::from(value),
+            EntryOperate::Put,
+            DataTypes::String,
+        )
+    }
+
+    #[test]
+    fn test_recover_torn_header() {
+        let path = std::env::temp_dir().join("arrowdb_wal_torn_header.wal");
+        let _ = std::fs::remove_file(&path);
+        let mut wal = open(&path, enums::RWMode::StdIO);
+        let written = wal.write(&entry("a", b"1".to_vec()).encode()).unwrap() as u64;
+        // a header claiming a value of 4GB
+        let mut torn = entry("b", b"2".to_vec()).encode().to_vec();
+        torn[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        wal.write(&torn).unwrap();
+        drop(wal);
+
+        let mut wal = open(&path, enums::RWMode::StdIO);
+        let entries = wal.recover().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(wal.write_at, written);
+    }
+
+    #[test]
+    fn test_recover_past_file_size() {
+        let path = std::env::temp_dir().join("arrowdb_wal_past_file_size.wal");
+        let _ = std::fs::remove_file(&path);
+        let mut wal = open(&path, enums::RWMode::StdIO);
+        // the last entry starts before 1MB and ends after it
+        for i in 0..11 {
+            wal.write(&entry(&i.to_string(), vec![1u8; 100 * 1024]).encode())
+                .unwrap();
+        }
+        let written = wal.write_at;
+        assert!(written > enums::MB);
+        drop(wal);
+
+        let mut wal = open(&path, enums::RWMode::StdIO);
+        assert_eq!(wal.recover().unwrap().len(), 11);
+        assert_eq!(wal.write_at, written);
+    }
+
+    #[test]
+    fn test_seal_recovered_mmap() {
+        let path = std::env::temp_dir().join("arrowdb_wal_seal_recovered_mmap.wal");
+        let _ = std::fs::remove_file(&path);
+        let mut wal = open(&path, enums::RWMode::MMap);
+        for i in 0..3 {
+            wal.write(&entry(&i.to_string(), vec![1u8; 1024]).encode())
+                .unwrap();
+        }
+        let written = wal.write_at;
+        drop(wal);
+        // left unsealed, the file is as long as its mapping
+        assert!(std::fs::metadata(&path).unwrap().len() > written);
+
+        let mut wal = open(&path, enums::RWMode::MMap);
+        assert_eq!(wal.recover().unwrap().len(), 3);
+        wal.seal().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), written);
+        drop(wal);
+
+        let mut wal = open(&path, enums::RWMode::MMap);
+        assert_eq!(wal.recover().unwrap().len(), 3);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), written);
+    }
 }